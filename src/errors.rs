@@ -1,5 +1,10 @@
 //! errors provides error generics for the gpx parser.
 
+// error_chain's generated code checks a cfg set by its own build script.
+#![allow(unexpected_cfgs)]
+
+use std::fmt;
+
 use error_chain::*;
 
 // This gives us our error boilerplate macros.
@@ -12,6 +17,13 @@ error_chain! {
             display("invalid child element '{}' in {}", child, parent)
         }
 
+        /// DuplicateChildElement signifies when an element has the same child
+        /// more than once, where the GPX spec allows only one.
+        DuplicateChildElement(child: String, parent: &'static str) {
+            description("duplicate child element")
+            display("duplicate child element '{}' in {}", child, parent)
+        }
+
         /// InvalidClosingTag signifies incorrect XML syntax: A tag was closed that
         /// could not be closed at this point.
         InvalidClosingTag(invalid_tag: String, parent: &'static str) {
@@ -31,5 +43,64 @@ error_chain! {
             description("invalid element, lacks required attribute")
             display("invalid element, {} lacks required attribute {}", parent, attr)
         }
+
+        /// AtPosition tells where in the document an error was found: at the
+        /// 1-based `row` and `column`, inside the elements of `path`, like
+        /// `gpx/trk/trkseg/trkpt`. The error itself is the cause.
+        AtPosition(row: u64, column: u64, path: String, message: String) {
+            description("error at position")
+            display("error at {}:{}: {}", row, column, message)
+        }
+    }
+}
+
+/// Warning signifies a problem with the data of a GPX document that does not
+/// prevent it from being read, see `read_with_warnings`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Warning {
+    /// Some track or route points have no elevation.
+    MissingElevation {
+        /// Number of points without an `<ele>`.
+        count: usize,
+    },
+
+    /// Some track or route points have a timestamp earlier than the point
+    /// before them.
+    NonMonotonicTime {
+        /// Number of points that go back in time.
+        count: usize,
+    },
+
+    /// The document declares a version that is not known, and was read as
+    /// GPX 1.1.
+    UnknownVersion {
+        /// Version declared by the document.
+        version: String,
+    },
+
+    /// An `<extensions>` element did not contain well-formed XML, and it was
+    /// skipped along with its content. Only given when parsing leniently.
+    MalformedExtensions {
+        /// Line of the document where the element starts.
+        line: usize,
+    },
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Warning::MissingElevation { count } => {
+                write!(f, "elevation missing on {} points", count)
+            }
+            Warning::NonMonotonicTime { count } => {
+                write!(f, "non-monotonic time on {} points", count)
+            }
+            Warning::UnknownVersion { version } => {
+                write!(f, "unknown version {} read as 1.1", version)
+            }
+            Warning::MalformedExtensions { line } => {
+                write!(f, "skipped malformed extensions on line {}", line)
+            }
+        }
     }
 }
@@ -0,0 +1,101 @@
+//! index provides a spatial index of the track points of a document, see
+//! `Gpx::build_index`. Needs the `rstar` feature.
+
+use geo_types::Point;
+use rstar::primitives::PointWithData;
+use rstar::RTree;
+
+use crate::Gpx;
+
+/// The mean radius of the Earth (in meters), as used by the haversine
+/// distance of `geo`.
+const EARTH_RADIUS: f64 = 6_371_008.8;
+
+/// TrackPointRef locates a track point in the tracks of a document, as given
+/// by the queries of `GpxIndex`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct TrackPointRef {
+    /// Index of the track in `Gpx::tracks`.
+    pub track: usize,
+
+    /// Index of the segment in `Track::segments`.
+    pub segment: usize,
+
+    /// Index of the point in `TrackSegment::points`.
+    pub point: usize,
+}
+
+/// GpxIndex is an R-tree of all track points of a document, for answering
+/// many queries quickly, such as in an interactive map. It only holds the
+/// positions of the points, so it is built again when they change.
+///
+/// Points are indexed by their position on a sphere, so that the straight
+/// distances between them order them like distances over the Earth, poles
+/// and the antimeridian included.
+pub struct GpxIndex {
+    tree: RTree<PointWithData<TrackPointRef, [f64; 3]>>,
+}
+
+impl GpxIndex {
+    /// Builds the index of all track points of `gpx`.
+    pub fn new(gpx: &Gpx) -> GpxIndex {
+        let mut points = Vec::new();
+        for (track, trk) in gpx.tracks.iter().enumerate() {
+            for (segment, seg) in trk.segments.iter().enumerate() {
+                for (point, wpt) in seg.points.iter().enumerate() {
+                    let location = TrackPointRef {
+                        track,
+                        segment,
+                        point,
+                    };
+                    points.push(PointWithData::new(location, on_sphere(wpt.point())));
+                }
+            }
+        }
+        GpxIndex {
+            tree: RTree::bulk_load(points),
+        }
+    }
+
+    /// Gives the number of points in the index.
+    pub fn len(&self) -> usize {
+        self.tree.size()
+    }
+
+    /// Checks whether the index has no points.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Gives the track points within `meters` of `center`, in no particular
+    /// order.
+    pub fn query_radius(&self, center: Point<f64>, meters: f64) -> Vec<TrackPointRef> {
+        // The straight distance through the Earth matching the distance over
+        // its surface.
+        let angle = (meters / EARTH_RADIUS).min(std::f64::consts::PI);
+        let chord = 2.0 * EARTH_RADIUS * (angle / 2.0).sin();
+        self.tree
+            .locate_within_distance(on_sphere(center), chord * chord)
+            .map(|point| point.data)
+            .collect()
+    }
+
+    /// Gives the track point nearest to `point`, or `None` if the index is
+    /// empty.
+    pub fn nearest(&self, point: Point<f64>) -> Option<TrackPointRef> {
+        self.tree
+            .nearest_neighbor(&on_sphere(point))
+            .map(|nearest| nearest.data)
+    }
+}
+
+/// Gives the position of `point` on a sphere the size of the Earth, with the
+/// center of the Earth at the origin.
+fn on_sphere(point: Point<f64>) -> [f64; 3] {
+    let (lat, lon) = (point.lat().to_radians(), point.lng().to_radians());
+    [
+        EARTH_RADIUS * lat.cos() * lon.cos(),
+        EARTH_RADIUS * lat.cos() * lon.sin(),
+        EARTH_RADIUS * lat.sin(),
+    ]
+}
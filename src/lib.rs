@@ -32,10 +32,21 @@
 //! ```
 
 // Export our type structs in the root, along with the read and write functions.
-pub use crate::reader::read;
+#[cfg(feature = "rstar")]
+pub use crate::index::{GpxIndex, TrackPointRef};
+pub use crate::reader::{
+    from_reader, from_str, read, read_all, read_dir_merged, read_repair, read_streaming,
+    read_with_options, read_with_options_and_warnings, read_with_warnings, ParsingOptions,
+};
 pub use crate::types::*;
-pub use crate::writer::write;
+pub use crate::writer::{
+    append_track_points, finalize, write, write_to_bytes, write_unfinalized, write_with_hook,
+    write_with_options, MetadataTimePolicy, WaypointSort, WriteOptions, WrittenElement,
+    PLAYBACK_EXTENSION_NAMESPACE,
+};
 
+#[cfg(feature = "rstar")]
+mod index;
 mod parser;
 mod reader;
 mod types;
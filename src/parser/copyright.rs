@@ -0,0 +1,109 @@
+//! copyright handles parsing of GPX-spec copyright notices.
+
+use std::io::Read;
+
+use error_chain::{bail, ensure};
+use xml::reader::XmlEvent;
+
+use crate::errors::*;
+use crate::parser::{string, verify_starting_tag, Context};
+use crate::Copyright;
+
+/// consume consumes a GPX copyright notice from the `reader` until it ends.
+pub fn consume<R: Read>(context: &mut Context<R>) -> Result<Copyright> {
+    let mut copyright: Copyright = Default::default();
+    let attributes = verify_starting_tag(context, "copyright")?;
+    let attr = attributes
+        .into_iter()
+        .find(|attr| attr.name.local_name == "author");
+
+    let attr = attr.ok_or(ErrorKind::InvalidElementLacksAttribute(
+        "author",
+        "copyright",
+    ))?;
+
+    copyright.author = attr.value;
+
+    loop {
+        let next_event = {
+            if let Some(next) = context.reader.peek() {
+                match next {
+                    Ok(n) => n,
+                    Err(_) => bail!("error while parsing copyright event"),
+                }
+            } else {
+                break;
+            }
+        };
+
+        match next_event {
+            XmlEvent::StartElement { ref name, .. } => match name.local_name.as_ref() {
+                "year" => {
+                    copyright.year = Some(
+                        string::consume(context, "year", false)?
+                            .trim()
+                            .parse()
+                            .chain_err(|| "error while casting copyright year to i32")?,
+                    )
+                }
+                "license" => copyright.license = Some(string::consume(context, "license", false)?),
+                child => {
+                    bail!(ErrorKind::InvalidChildElement(
+                        String::from(child),
+                        "copyright"
+                    ));
+                }
+            },
+            XmlEvent::EndElement { ref name } => {
+                ensure!(
+                    name.local_name == "copyright",
+                    ErrorKind::InvalidClosingTag(name.local_name.clone(), "copyright")
+                );
+                context.reader.next(); //consume the end tag
+                return Ok(copyright);
+            }
+            _ => {
+                context.reader.next(); //consume and ignore this event
+            }
+        }
+    }
+
+    bail!(ErrorKind::MissingClosingTag("copyright"));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::consume;
+    use crate::GpxVersion;
+
+    #[test]
+    fn consume_full_copyright() {
+        let copyright = consume!(
+            "
+                <copyright author=\"OpenStreetMap contributors\">
+                    <year>2020</year>
+                    <license>https://opendatacommons.org/licenses/odbl/</license>
+                </copyright>
+            ",
+            GpxVersion::Gpx11
+        )
+        .unwrap();
+
+        assert_eq!(copyright.author, "OpenStreetMap contributors");
+        assert_eq!(copyright.year, Some(2020));
+        assert_eq!(
+            copyright.license,
+            Some(String::from("https://opendatacommons.org/licenses/odbl/"))
+        );
+    }
+
+    #[test]
+    fn consume_copyright_without_author() {
+        let result = consume!(
+            "<copyright><year>2020</year></copyright>",
+            GpxVersion::Gpx11
+        );
+
+        assert!(result.is_err());
+    }
+}
@@ -26,7 +26,7 @@ pub fn consume<R: Read>(context: &mut Context<R>) -> Result<String> {
 
     let email = format!("{id}@{domain}", id = &id.value, domain = &domain.value);
 
-    while let Some(event) = context.reader.next() {
+    for event in context.reader.by_ref() {
         match event.chain_err(|| "error while parsing XML")? {
             XmlEvent::StartElement { ref name, .. } => {
                 bail!(ErrorKind::InvalidChildElement(
@@ -1,47 +1,252 @@
 //! extensions handles parsing of GPX-spec extensions.
 
-// TODO: extensions are not implemented
-
 use std::io::Read;
 
-use error_chain::ensure;
+use error_chain::{bail, ensure};
 use xml::reader::XmlEvent;
 
 use crate::errors::*;
-use crate::parser::Context;
+use crate::parser::{verify_starting_tag, Context};
+use crate::{ExtensionElement, ExtensionNode, Extensions, TrackPointExtension};
+
+/// The deepest that elements may be nested inside an extensions element.
+/// The tree of nodes is dropped, compared and written recursively, so deeper
+/// ones, which are likely adversarial, are refused rather than risk
+/// overflowing the stack.
+const MAX_DEPTH: usize = 256;
 
-/// consume consumes a single string as tag content.
-pub fn consume<R: Read>(context: &mut Context<R>) -> Result<()> {
-    let mut started = false;
+/// consume consumes an extensions element, keeping its content as a tree of
+/// nodes. Gives `None` when the parsing options drop extensions.
+pub fn consume<R: Read>(context: &mut Context<R>) -> Result<Option<Extensions>> {
+    verify_starting_tag(context, "extensions")?;
+    if context.options.drop_extensions {
+        return skip(context).map(|_| None);
+    }
+    let mut extensions: Extensions = Default::default();
+    // Elements that have been opened but not closed yet, innermost last.
+    let mut open: Vec<ExtensionElement> = Vec::new();
 
     for event in context.reader() {
         match event.chain_err(|| "error while parsing XML")? {
-            XmlEvent::StartElement { name, .. } => {
-                // flip started depending on conditions
-                if &name.local_name == "extensions" {
-                    ensure!(!started, "extensions tag opened twice");
-
-                    started = true;
-                }
+            XmlEvent::StartElement {
+                name, attributes, ..
+            } => {
+                ensure!(
+                    open.len() < MAX_DEPTH,
+                    "extensions nested deeper than {} elements",
+                    MAX_DEPTH
+                );
+                open.push(ExtensionElement {
+                    name: name.local_name,
+                    prefix: name.prefix,
+                    namespace: name.namespace,
+                    attributes: attributes
+                        .into_iter()
+                        .map(|attr| (attr.name.borrow().to_repr(), attr.value))
+                        .collect(),
+                    children: Vec::new(),
+                });
             }
-
-            XmlEvent::EndElement { name, .. } => {
-                if &name.local_name == "extensions" {
-                    return Ok(());
+            XmlEvent::EndElement { name } => match open.pop() {
+                Some(mut element) => {
+                    drop_formatting(&mut element.children);
+                    add_node(&mut open, &mut extensions, ExtensionNode::Element(element));
                 }
+                None => {
+                    ensure!(
+                        name.local_name == "extensions",
+                        ErrorKind::InvalidClosingTag(name.local_name, "extensions")
+                    );
+                    drop_formatting(&mut extensions.children);
+                    return Ok(Some(extensions));
+                }
+            },
+            XmlEvent::Characters(text) | XmlEvent::Whitespace(text) => {
+                add_node(&mut open, &mut extensions, ExtensionNode::Text(text));
             }
+            _ => {}
+        }
+    }
+
+    bail!(ErrorKind::MissingClosingTag("extensions"));
+}
 
+/// skip skips over the content of an extensions element, up to and including
+/// its closing tag. It only counts the depth, so it takes any nesting.
+fn skip<R: Read>(context: &mut Context<R>) -> Result<()> {
+    let mut depth = 0;
+    for event in context.reader() {
+        match event.chain_err(|| "error while parsing XML")? {
+            XmlEvent::StartElement { .. } => depth += 1,
+            XmlEvent::EndElement { name } if depth == 0 => {
+                ensure!(
+                    name.local_name == "extensions",
+                    ErrorKind::InvalidClosingTag(name.local_name, "extensions")
+                );
+                return Ok(());
+            }
+            XmlEvent::EndElement { .. } => depth -= 1,
             _ => {}
         }
     }
 
-    Err("no end tag for extensions".into())
+    bail!(ErrorKind::MissingClosingTag("extensions"));
+}
+
+/// Namespace URIs of the versions of the Garmin TrackPointExtension, whose
+/// fields are the same.
+const TRACK_POINT_EXTENSION_NAMESPACES: [&str; 2] = [
+    "http://www.garmin.com/xmlschemas/TrackPointExtension/v1",
+    "http://www.garmin.com/xmlschemas/TrackPointExtension/v2",
+];
+
+/// take_track_point_extension moves the fields of the Garmin
+/// TrackPointExtension out of `extensions`, whatever namespace prefix it is
+/// written with. Only elements in the namespace of one of its versions are
+/// read, and only those in the namespace of the first one if there are
+/// several. Elements it does not know, with a value that does not parse, or
+/// for a field that has already been read, are left in place, as is the
+/// TrackPointExtension element itself if any of them remain. Empty fields are
+/// taken without a value.
+///
+/// Fields wrapped in a nested `Extensions` element, as some Garmin devices
+/// write them, are taken as well, see `TrackPointExtension::wrapped`.
+pub fn take_track_point_extension(extensions: &mut Extensions) -> Option<TrackPointExtension> {
+    let mut found: Option<TrackPointExtension> = None;
+    extensions.children.retain_mut(|node| {
+        let element = match node {
+            ExtensionNode::Element(element) if is_track_point_extension(element, &found) => element,
+            _ => return true,
+        };
+        let fields = found.get_or_insert_with(|| TrackPointExtension {
+            namespace: element.namespace.clone(),
+            prefix: element.prefix.clone(),
+            ..Default::default()
+        });
+        let namespace = element.namespace.clone();
+        element.children.retain_mut(|child| match child {
+            ExtensionNode::Element(wrapper)
+                if wrapper.name == "Extensions" && wrapper.namespace == namespace =>
+            {
+                let count = wrapper.children.len();
+                wrapper
+                    .children
+                    .retain(|child| !take_field(fields, child, &namespace));
+                fields.wrapped |= wrapper.children.len() < count;
+                !wrapper.children.is_empty()
+            }
+            child => !take_field(fields, child, &namespace),
+        });
+        !element.children.is_empty()
+    });
+    found
+}
+
+/// is_track_point_extension tells whether `element` is a TrackPointExtension
+/// to take fields from, given those found so far.
+fn is_track_point_extension(
+    element: &ExtensionElement,
+    found: &Option<TrackPointExtension>,
+) -> bool {
+    let namespace = element.namespace.as_deref();
+    element.name == "TrackPointExtension"
+        && match found {
+            Some(fields) => fields.namespace.as_deref() == namespace,
+            None => namespace.is_some_and(|uri| TRACK_POINT_EXTENSION_NAMESPACES.contains(&uri)),
+        }
+}
+
+/// take_field reads a child of a TrackPointExtension element in `namespace`
+/// into `fields`, and tells whether it was one of them. A field that is
+/// already set is not overwritten.
+fn take_field(
+    fields: &mut TrackPointExtension,
+    node: &ExtensionNode,
+    namespace: &Option<String>,
+) -> bool {
+    let element = match node {
+        ExtensionNode::Element(element) if element.namespace == *namespace => element,
+        _ => return false,
+    };
+    let unset = match element.name.as_str() {
+        "hr" => fields.hr.is_none(),
+        "cad" => fields.cad.is_none(),
+        "atemp" => fields.atemp.is_none(),
+        "wtemp" => fields.wtemp.is_none(),
+        "depth" => fields.depth.is_none(),
+        _ => false,
+    };
+    if !unset {
+        return false;
+    }
+    let text = match element.children.as_slice() {
+        [ExtensionNode::Text(text)] => text.trim(),
+        [] => "",
+        _ => return false,
+    };
+    // An empty field, such as `<gpxtpx:hr/>`, has no value to read.
+    if text.is_empty() {
+        return true;
+    }
+    match element.name.as_str() {
+        "hr" => text.parse().map(|hr| fields.hr = Some(hr)).is_ok(),
+        "cad" => text.parse().map(|cad| fields.cad = Some(cad)).is_ok(),
+        "atemp" => text.parse().map(|atemp| fields.atemp = Some(atemp)).is_ok(),
+        "wtemp" => text.parse().map(|wtemp| fields.wtemp = Some(wtemp)).is_ok(),
+        "depth" => text.parse().map(|depth| fields.depth = Some(depth)).is_ok(),
+        _ => false,
+    }
+}
+
+/// add_node adds a node to the innermost open element, or to the extensions
+/// themselves at the top level. Text following text is joined into one node.
+fn add_node(open: &mut [ExtensionElement], extensions: &mut Extensions, node: ExtensionNode) {
+    let children = match open.last_mut() {
+        Some(parent) => &mut parent.children,
+        None => &mut extensions.children,
+    };
+    match (children.last_mut(), node) {
+        (Some(ExtensionNode::Text(last)), ExtensionNode::Text(text)) => last.push_str(&text),
+        (_, node) => children.push(node),
+    }
+}
+
+/// drop_formatting removes the whitespace that only indents child elements.
+/// In mixed content, where elements are interleaved with other text, all
+/// whitespace is significant and kept.
+fn drop_formatting(children: &mut Vec<ExtensionNode>) {
+    let mixed = children
+        .iter()
+        .any(|node| matches!(node, ExtensionNode::Text(text) if !text.trim().is_empty()));
+    if !mixed {
+        children.retain(|node| matches!(node, ExtensionNode::Element(_)));
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::consume;
-    use crate::GpxVersion;
+    use super::{consume, take_track_point_extension};
+    use crate::{ExtensionNode, GpxVersion};
+
+    #[test]
+    fn consume_deeply_nested_extensions() {
+        let nested = |depth: usize| {
+            format!(
+                "<extensions>{}{}</extensions>",
+                "<a>".repeat(depth),
+                "</a>".repeat(depth)
+            )
+        };
+
+        let result = consume!(nested(super::MAX_DEPTH), GpxVersion::Gpx11);
+        assert!(result.unwrap().is_some());
+
+        let result = consume!(nested(100_000), GpxVersion::Gpx11);
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "extensions nested deeper than 256 elements"
+        );
+    }
 
     #[test]
     fn consume_arbitrary_extensions() {
@@ -56,4 +261,201 @@ mod tests {
 
         assert!(result.is_ok());
     }
+
+    #[test]
+    fn consume_extensions_tree() {
+        let extensions = consume!(
+            "<extensions xmlns:ns=\"urn:example\">
+                <ns:summary total=\"2\">
+                    <ns:calories>512</ns:calories>
+                    <ns:empty/>
+                </ns:summary>
+            </extensions>",
+            GpxVersion::Gpx11
+        )
+        .unwrap()
+        .unwrap();
+
+        assert_eq!(extensions.children.len(), 1);
+        let summary = match &extensions.children[0] {
+            ExtensionNode::Element(element) => element,
+            node => panic!("unexpected node {:?}", node),
+        };
+        assert_eq!(summary.name, "summary");
+        assert_eq!(summary.prefix, Some(String::from("ns")));
+        assert_eq!(summary.namespace, Some(String::from("urn:example")));
+        assert_eq!(
+            summary.attributes,
+            vec![(String::from("total"), String::from("2"))]
+        );
+
+        assert_eq!(summary.children.len(), 2);
+        match &summary.children[0] {
+            ExtensionNode::Element(calories) => {
+                assert_eq!(calories.name, "calories");
+                assert_eq!(
+                    calories.children,
+                    vec![ExtensionNode::Text(String::from("512"))]
+                );
+            }
+            node => panic!("unexpected node {:?}", node),
+        }
+        match &summary.children[1] {
+            ExtensionNode::Element(empty) => assert!(empty.children.is_empty()),
+            node => panic!("unexpected node {:?}", node),
+        }
+    }
+
+    #[test]
+    fn consume_mixed_content() {
+        let extensions = consume!(
+            "<extensions>
+                <note>Climb <b>1</b> <i>of 3</i>, steep</note>
+                <empty> </empty>
+            </extensions>",
+            GpxVersion::Gpx11
+        )
+        .unwrap()
+        .unwrap();
+
+        assert_eq!(extensions.children.len(), 2);
+        let note = match &extensions.children[0] {
+            ExtensionNode::Element(element) => element,
+            node => panic!("unexpected node {:?}", node),
+        };
+        let text: Vec<_> = note
+            .children
+            .iter()
+            .map(|node| match node {
+                ExtensionNode::Text(text) => text.as_str(),
+                ExtensionNode::Element(element) => element.name.as_str(),
+            })
+            .collect();
+        assert_eq!(text, vec!["Climb ", "b", " ", "i", ", steep"]);
+        match &extensions.children[1] {
+            ExtensionNode::Element(empty) => assert!(empty.children.is_empty()),
+            node => panic!("unexpected node {:?}", node),
+        }
+    }
+
+    #[test]
+    fn consume_unclosed_extensions() {
+        let result = consume!("<extensions><a></a>", GpxVersion::Gpx11);
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn take_track_point_extension_fields() {
+        let mut extensions = consume!(
+            "<extensions xmlns:tpx=\"http://www.garmin.com/xmlschemas/TrackPointExtension/v2\">
+                <tpx:TrackPointExtension>
+                    <tpx:atemp>21.5</tpx:atemp>
+                    <tpx:hr>142</tpx:hr>
+                    <tpx:cad>high</tpx:cad>
+                    <tpx:speed>3.2</tpx:speed>
+                </tpx:TrackPointExtension>
+                <power>230</power>
+            </extensions>",
+            GpxVersion::Gpx11
+        )
+        .unwrap()
+        .unwrap();
+
+        let fields = take_track_point_extension(&mut extensions).unwrap();
+        assert_eq!(fields.hr, Some(142));
+        assert_eq!(fields.atemp, Some(21.5));
+        assert_eq!(fields.cad, None);
+
+        // What was not read is kept.
+        assert_eq!(extensions.children.len(), 2);
+        match &extensions.children[0] {
+            ExtensionNode::Element(element) => assert_eq!(element.children.len(), 2),
+            node => panic!("unexpected node {:?}", node),
+        }
+    }
+
+    #[test]
+    fn take_track_point_extension_by_namespace() {
+        let mut extensions = consume!(
+            "<extensions xmlns:tpx=\"http://www.garmin.com/xmlschemas/TrackPointExtension/v2\"
+                xmlns:gpxtpx=\"http://www.garmin.com/xmlschemas/TrackPointExtension/v1\"
+                xmlns:acme=\"http://example.com/acme\">
+                <acme:TrackPointExtension><acme:hr>99</acme:hr></acme:TrackPointExtension>
+                <tpx:TrackPointExtension><tpx:hr>140</tpx:hr><acme:cad>60</acme:cad></tpx:TrackPointExtension>
+                <tpx:TrackPointExtension><tpx:hr>150</tpx:hr><tpx:cad>80</tpx:cad></tpx:TrackPointExtension>
+                <gpxtpx:TrackPointExtension><gpxtpx:atemp>20</gpxtpx:atemp></gpxtpx:TrackPointExtension>
+            </extensions>",
+            GpxVersion::Gpx11
+        )
+        .unwrap()
+        .unwrap();
+
+        let fields = take_track_point_extension(&mut extensions).unwrap();
+        assert_eq!(fields.hr, Some(140));
+        assert_eq!(fields.cad, Some(80));
+        assert_eq!(fields.atemp, None);
+        assert_eq!(
+            fields.namespace.as_deref(),
+            Some("http://www.garmin.com/xmlschemas/TrackPointExtension/v2")
+        );
+        assert_eq!(fields.prefix.as_deref(), Some("tpx"));
+
+        // The vendor element, the cadence in its namespace, the heart rate
+        // that was already read and the other version are all kept.
+        let remaining: Vec<_> = extensions
+            .children
+            .iter()
+            .map(|node| match node {
+                ExtensionNode::Element(element) => element.children.len(),
+                node => panic!("unexpected node {:?}", node),
+            })
+            .collect();
+        assert_eq!(remaining, vec![1, 1, 1, 1]);
+    }
+
+    #[test]
+    fn take_track_point_extension_empty_fields() {
+        let mut extensions = consume!(
+            "<extensions xmlns:gpxtpx=\"http://www.garmin.com/xmlschemas/TrackPointExtension/v1\">
+                <gpxtpx:TrackPointExtension>
+                    <gpxtpx:hr/>
+                    <gpxtpx:cad> </gpxtpx:cad>
+                    <gpxtpx:atemp>18</gpxtpx:atemp>
+                </gpxtpx:TrackPointExtension>
+            </extensions>",
+            GpxVersion::Gpx11
+        )
+        .unwrap()
+        .unwrap();
+
+        let fields = take_track_point_extension(&mut extensions).unwrap();
+        assert_eq!(fields.hr, None);
+        assert_eq!(fields.cad, None);
+        assert_eq!(fields.atemp, Some(18.0));
+        assert!(extensions.children.is_empty());
+    }
+
+    #[test]
+    fn take_wrapped_track_point_extension() {
+        let mut extensions = consume!(
+            "<extensions xmlns:gpxtpx=\"http://www.garmin.com/xmlschemas/TrackPointExtension/v1\">
+                <gpxtpx:TrackPointExtension>
+                    <gpxtpx:Extensions>
+                        <gpxtpx:hr>138</gpxtpx:hr>
+                        <gpxtpx:cad>88</gpxtpx:cad>
+                    </gpxtpx:Extensions>
+                </gpxtpx:TrackPointExtension>
+            </extensions>",
+            GpxVersion::Gpx11
+        )
+        .unwrap()
+        .unwrap();
+
+        let fields = take_track_point_extension(&mut extensions).unwrap();
+        assert_eq!(fields.hr, Some(138));
+        assert_eq!(fields.cad, Some(88));
+        assert!(fields.wrapped);
+        assert!(extensions.children.is_empty());
+    }
 }
@@ -9,40 +9,177 @@ use xml::reader::XmlEvent;
 
 use crate::errors::*;
 use crate::parser::{
-    bounds, metadata, route, string, time, track, verify_starting_tag, waypoint, Context,
+    bounds, extensions, metadata, route, string, time, track, verify_starting_tag, waypoint,
+    Context,
 };
 use crate::{Gpx, GpxVersion, Link, Metadata, Person};
 
-/// Convert the version string to the version enum
-fn version_string_to_version(version_str: &str) -> Result<GpxVersion> {
+/// Convert the version string to the version enum, if it is a known version.
+fn version_string_to_version(version_str: &str) -> Option<GpxVersion> {
     match version_str {
-        "1.0" => Ok(GpxVersion::Gpx10),
-        "1.1" => Ok(GpxVersion::Gpx11),
-        version => Err(Error::from(format!("Unknown version {}", version))),
+        "1.0" => Some(GpxVersion::Gpx10),
+        "1.1" => Some(GpxVersion::Gpx11),
+        _ => None,
     }
 }
 
-/// consume consumes an entire GPX element.
-pub fn consume<R: Read>(context: &mut Context<R>) -> Result<Gpx> {
+/// skip_to_gpx skips all events up to the next `gpx` opening tag, wherever it
+/// is in the document.
+fn skip_to_gpx<R: Read>(context: &mut Context<R>) -> Result<()> {
+    loop {
+        match context.reader.peek() {
+            Some(Ok(XmlEvent::StartElement { name, .. })) if name.local_name == "gpx" => {
+                return Ok(());
+            }
+            Some(Ok(_)) => {
+                context.reader.next(); //consume and ignore this event
+            }
+            Some(Err(error)) => {
+                return Err(error.clone()).chain_err(|| "error while parsing gpx event");
+            }
+            None => bail!("did not find expected opening tag for gpx"),
+        }
+    }
+}
+
+/// consume_prolog consumes everything before the root element, keeping the
+/// processing instructions.
+fn consume_prolog<R: Read>(context: &mut Context<R>, gpx: &mut Gpx) {
+    loop {
+        match context.reader.peek() {
+            Some(Ok(XmlEvent::ProcessingInstruction { name, data })) => {
+                let instruction = match data {
+                    Some(data) => format!("{} {}", name, data),
+                    None => name.clone(),
+                };
+                gpx.processing_instructions.push(instruction);
+            }
+            Some(Ok(XmlEvent::StartDocument { .. })) => {}
+            Some(Ok(XmlEvent::Characters(chars))) if chars.trim().is_empty() => {}
+            _ => return,
+        }
+        context.reader.next(); //consume the event we just looked at
+    }
+}
+
+/// Gpx10Metadata gathers the metadata of a GPX 1.0 document, which is spread
+/// over the children of the root element.
+#[derive(Default)]
+pub struct Gpx10Metadata {
+    author: Option<String>,
+    url: Option<String>,
+    urlname: Option<String>,
+    email: Option<String>,
+    time: Option<DateTime<Utc>>,
+    bounds: Option<Rect<f64>>,
+    name: Option<String>,
+    description: Option<String>,
+    keywords: Option<String>,
+}
+
+impl Gpx10Metadata {
+    /// is_field tells whether `child` of the root element is GPX 1.0 metadata.
+    pub fn is_field(child: &str) -> bool {
+        matches!(
+            child,
+            "time"
+                | "bounds"
+                | "author"
+                | "email"
+                | "url"
+                | "urlname"
+                | "name"
+                | "description"
+                | "keywords"
+        )
+    }
+
+    /// consume consumes the metadata element `child` of the root element.
+    pub fn consume<R: Read>(&mut self, context: &mut Context<R>, child: &str) -> Result<()> {
+        match child {
+            "time" => self.time = Some(time::consume(context)?),
+            "bounds" => self.bounds = Some(bounds::consume(context)?),
+            "author" => self.author = Some(string::consume(context, "author", false)?),
+            "email" => self.email = Some(string::consume(context, "email", false)?),
+            "url" => self.url = Some(string::consume(context, "url", false)?),
+            "urlname" => self.urlname = Some(string::consume(context, "urlname", false)?),
+            "name" => self.name = Some(string::consume(context, "name", false)?),
+            "description" => {
+                self.description = Some(string::consume(context, "description", true)?)
+            }
+            "keywords" => self.keywords = Some(string::consume(context, "keywords", true)?),
+            child => bail!(ErrorKind::InvalidChildElement(String::from(child), "gpx")),
+        }
+        Ok(())
+    }
+
+    /// into_metadata gives the metadata gathered, if there is any.
+    pub fn into_metadata(self) -> Option<Metadata> {
+        let mut metadata = Metadata {
+            name: self.name,
+            time: self.time,
+            bounds: self.bounds,
+            keywords: self.keywords,
+            description: self.description,
+            ..Default::default()
+        };
+        let urlname = self.urlname;
+        let person = Person {
+            name: self.author,
+            email: self.email,
+            link: self.url.map(|href| Link {
+                href,
+                text: urlname,
+                ..Default::default()
+            }),
+        };
+        if person != Default::default() {
+            metadata.author = Some(person);
+        }
+        if metadata != Default::default() {
+            Some(metadata)
+        } else {
+            None
+        }
+    }
+}
+
+/// consume_start consumes everything up to and including the opening tag of
+/// the root element, and gives a GPX with the details found there.
+pub fn consume_start<R: Read>(context: &mut Context<R>) -> Result<Gpx> {
     let mut gpx: Gpx = Default::default();
 
-    let mut author: Option<String> = None;
-    let mut url: Option<String> = None;
-    let mut urlname: Option<String> = None;
-    let mut email: Option<String> = None;
-    let mut time: Option<DateTime<Utc>> = None;
-    let mut bounds: Option<Rect<f64>> = None;
-    let mut gpx_name: Option<String> = None;
-    let mut description: Option<String> = None;
-    let mut keywords: Option<String> = None;
-
-    // First we consume the gpx tag and its attributes
+    consume_prolog(context, &mut gpx);
+
+    // Then we consume the gpx tag and its attributes
+    if context.options.find_gpx_anywhere {
+        skip_to_gpx(context)?;
+    }
+    // The root element is matched by its local name only, whichever namespace
+    // it is in.
+    if let Some(Ok(XmlEvent::StartElement {
+        name, namespace, ..
+    })) = context.reader.peek()
+    {
+        gpx.namespace = name.namespace.clone();
+        gpx.namespaces = namespace
+            .0
+            .iter()
+            .filter(|(prefix, _)| !matches!(prefix.as_str(), "" | "xml" | "xmlns"))
+            .map(|(prefix, uri)| (prefix.clone(), uri.clone()))
+            .collect();
+    }
     let attributes = verify_starting_tag(context, "gpx")?;
     let version = attributes
         .iter()
         .find(|attr| attr.name.local_name == "version")
         .ok_or(ErrorKind::InvalidElementLacksAttribute("version", "gpx"))?;
-    gpx.version = version_string_to_version(&version.value)?;
+    // Unknown versions are read as the latest one known, which they most
+    // likely extend.
+    gpx.version = version_string_to_version(&version.value).unwrap_or_else(|| {
+        gpx.declared_version = Some(version.value.clone());
+        GpxVersion::Gpx11
+    });
     context.version = gpx.version;
 
     let creator = attributes
@@ -50,12 +187,27 @@ pub fn consume<R: Read>(context: &mut Context<R>) -> Result<Gpx> {
         .find(|attr| attr.name.local_name == "creator");
     gpx.creator = creator.map(|c| c.value.to_owned());
 
+    gpx.schema_location = attributes
+        .iter()
+        .find(|attr| attr.name.local_name == "schemaLocation")
+        .map(|attr| attr.value.clone());
+
+    Ok(gpx)
+}
+
+/// consume consumes an entire GPX element.
+pub fn consume<R: Read>(context: &mut Context<R>) -> Result<Gpx> {
+    let mut gpx = consume_start(context)?;
+    let mut gpx10_metadata: Gpx10Metadata = Default::default();
+
     loop {
         let next_event = {
             if let Some(next) = context.reader.peek() {
                 match next {
                     Ok(n) => n,
-                    Err(_) => bail!("error while parsing gpx event"),
+                    Err(error) => {
+                        return Err(error.clone()).chain_err(|| "error while parsing gpx event");
+                    }
                 }
             } else {
                 break;
@@ -76,32 +228,14 @@ pub fn consume<R: Read>(context: &mut Context<R>) -> Result<Gpx> {
                 "wpt" => {
                     gpx.waypoints.push(waypoint::consume(context, "wpt")?);
                 }
-                "time" if context.version == GpxVersion::Gpx10 => {
-                    time = Some(time::consume(context)?);
-                }
-                "bounds" if context.version == GpxVersion::Gpx10 => {
-                    bounds = Some(bounds::consume(context)?);
-                }
-                "author" if context.version == GpxVersion::Gpx10 => {
-                    author = Some(string::consume(context, "author", false)?);
-                }
-                "email" if context.version == GpxVersion::Gpx10 => {
-                    email = Some(string::consume(context, "email", false)?);
+                // GPX 1.0 has no extensions element, which is only read
+                // when parsing leniently.
+                "extensions" if context.version != GpxVersion::Gpx10 || !context.options.strict => {
+                    gpx.summary_extensions = extensions::consume(context)?;
                 }
-                "url" if context.version == GpxVersion::Gpx10 => {
-                    url = Some(string::consume(context, "url", false)?);
-                }
-                "urlname" if context.version == GpxVersion::Gpx10 => {
-                    urlname = Some(string::consume(context, "urlname", false)?);
-                }
-                "name" if context.version == GpxVersion::Gpx10 => {
-                    gpx_name = Some(string::consume(context, "name", false)?);
-                }
-                "description" if context.version == GpxVersion::Gpx10 => {
-                    description = Some(string::consume(context, "description", true)?);
-                }
-                "keywords" if context.version == GpxVersion::Gpx10 => {
-                    keywords = Some(string::consume(context, "keywords", true)?);
+                child if context.version == GpxVersion::Gpx10 && Gpx10Metadata::is_field(child) => {
+                    let child = child.to_owned();
+                    gpx10_metadata.consume(context, &child)?;
                 }
                 child => {
                     bail!(ErrorKind::InvalidChildElement(String::from(child), "gpx"));
@@ -113,30 +247,12 @@ pub fn consume<R: Read>(context: &mut Context<R>) -> Result<Gpx> {
                     ErrorKind::InvalidClosingTag(name.local_name.clone(), "gpx")
                 );
                 if gpx.version == GpxVersion::Gpx10 {
-                    let mut metadata: Metadata = Default::default();
-                    metadata.name = gpx_name;
-                    metadata.time = time;
-                    metadata.bounds = bounds;
-                    let mut person: Person = Default::default();
-                    person.name = author;
-                    if let Some(url) = url {
-                        let mut link: Link = Default::default();
-                        link.href = url;
-                        link.text = urlname;
-                        person.link = Some(link);
-                    }
-                    person.email = email;
-                    if person != Default::default() {
-                        metadata.author = Some(person);
-                    }
-                    metadata.keywords = keywords;
-                    metadata.description = description;
-                    if metadata != Default::default() {
-                        gpx.metadata = Some(metadata);
-                    }
+                    gpx.metadata = gpx10_metadata.into_metadata();
                 }
                 context.reader.next();
 
+                // Anything after the root element, such as a log or a
+                // signature appended to the file, is left unread.
                 return Ok(gpx);
             }
             _ => {
@@ -150,10 +266,13 @@ pub fn consume<R: Read>(context: &mut Context<R>) -> Result<Gpx> {
 
 #[cfg(test)]
 mod tests {
+    use std::io::BufReader;
+
     use geo_types::Point;
 
     use super::consume;
-    use crate::GpxVersion;
+    use crate::parser::create_context_with_options;
+    use crate::{GpxVersion, ParsingOptions};
 
     #[test]
     fn consume_gpx() {
@@ -170,10 +289,11 @@ mod tests {
     }
 
     #[test]
-    fn consume_gpx_version_error() {
-        let gpx = consume!("<gpx version=\"1.2\"></gpx>", GpxVersion::Unknown);
+    fn consume_gpx_unknown_version() {
+        let gpx = consume!("<gpx version=\"1.2\"></gpx>", GpxVersion::Unknown).unwrap();
 
-        assert!(gpx.is_err());
+        assert_eq!(gpx.version, GpxVersion::Gpx11);
+        assert_eq!(gpx.declared_version, Some("1.2".into()));
     }
 
     #[test]
@@ -216,4 +336,60 @@ mod tests {
         let wpt = &gpx.waypoints[1];
         assert_eq!(wpt.point(), Point::new(10.256, -81.324));
     }
+
+    #[test]
+    fn consume_gpx_anywhere() {
+        let xml = "<feed><entry><gpx version=\"1.1\"></gpx></entry></feed>";
+        let options = ParsingOptions {
+            find_gpx_anywhere: true,
+            ..Default::default()
+        };
+
+        let gpx = consume(&mut create_context_with_options(
+            BufReader::new(xml.as_bytes()),
+            GpxVersion::Unknown,
+            options,
+        ));
+        assert!(gpx.is_ok());
+
+        // Without the option the wrapper is not a valid root element.
+        let gpx = consume!(xml, GpxVersion::Unknown);
+        assert!(gpx.is_err());
+    }
+
+    #[test]
+    fn consume_gpx10_extensions() {
+        let xml = "<gpx version=\"1.0\"><extensions><a>1</a></extensions></gpx>";
+        assert!(consume!(xml, GpxVersion::Unknown).is_err());
+
+        let options = ParsingOptions {
+            strict: false,
+            ..Default::default()
+        };
+        let gpx = consume(&mut create_context_with_options(
+            BufReader::new(xml.as_bytes()),
+            GpxVersion::Unknown,
+            options,
+        ))
+        .unwrap();
+        assert!(gpx.summary_extensions.is_some());
+    }
+
+    #[test]
+    fn consume_gpx_anywhere_malformed() {
+        let xml = "<feed><entry id></entry><gpx version=\"1.1\"></gpx></feed>";
+        let options = ParsingOptions {
+            find_gpx_anywhere: true,
+            ..Default::default()
+        };
+
+        let error = consume(&mut create_context_with_options(
+            BufReader::new(xml.as_bytes()),
+            GpxVersion::Unknown,
+            options,
+        ))
+        .unwrap_err();
+        // The XML error is kept as the cause.
+        assert_eq!(error.iter().count(), 2);
+    }
 }
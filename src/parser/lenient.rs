@@ -0,0 +1,566 @@
+//! lenient handles repairs of malformed input that the XML parser cannot
+//! recover from. They run on the raw document before it is parsed, such as
+//! when `ParsingOptions::strict` is turned off.
+
+use crate::errors::Warning;
+
+/// The kinds of markup found by `next_tag`.
+#[derive(Debug, PartialEq)]
+enum TagKind<'a> {
+    /// An opening tag with its qualified name, and whether it closes itself.
+    Open(&'a [u8], bool),
+    /// A closing tag with its qualified name.
+    Close(&'a [u8]),
+    /// A comment, CDATA section, processing instruction or declaration.
+    Other,
+}
+
+/// A piece of markup, spanning `start..end` of the document.
+struct Tag<'a> {
+    start: usize,
+    end: usize,
+    kind: TagKind<'a>,
+}
+
+/// next_tag finds the first piece of markup at or after `from`.
+///
+/// Returns `None` at the end of the document, and `Err` with the position of
+/// the markup if it is not terminated.
+fn next_tag(data: &[u8], from: usize) -> Option<Result<Tag<'_>, usize>> {
+    let start = from + data.get(from..)?.iter().position(|&b| b == b'<')?;
+    let rest = &data[start..];
+    let find = |pattern: &[u8]| {
+        rest.windows(pattern.len())
+            .position(|w| w == pattern)
+            .map(|i| start + i + pattern.len())
+            .ok_or(start)
+    };
+    let other = |end: Result<usize, usize>| {
+        Some(end.map(|end| Tag {
+            start,
+            end,
+            kind: TagKind::Other,
+        }))
+    };
+
+    if rest.starts_with(b"<!--") {
+        return other(find(b"-->"));
+    } else if rest.starts_with(b"<![CDATA[") {
+        return other(find(b"]]>"));
+    } else if rest.starts_with(b"<?") {
+        return other(find(b"?>"));
+    } else if rest.starts_with(b"<!") {
+        return other(find(b">"));
+    }
+
+    // Find the end of the tag, skipping over quoted attribute values.
+    let mut quote = None;
+    let mut end = None;
+    for (i, &b) in rest.iter().enumerate().skip(1) {
+        match quote {
+            Some(q) if b == q => quote = None,
+            Some(_) => {}
+            None if b == b'"' || b == b'\'' => quote = Some(b),
+            None if b == b'>' => {
+                end = Some(i);
+                break;
+            }
+            None => {}
+        }
+    }
+    let end = match end {
+        Some(end) => end,
+        None => return Some(Err(start)),
+    };
+
+    let closing = rest[1] == b'/';
+    let name_start = if closing { 2 } else { 1 };
+    let name_len = rest[name_start..end]
+        .iter()
+        .position(|&b| b.is_ascii_whitespace() || b == b'/' || b == b'>')
+        .unwrap_or(end - name_start);
+    let name = &rest[name_start..name_start + name_len];
+    let kind = if closing {
+        TagKind::Close(name)
+    } else {
+        TagKind::Open(name, rest[end - 1] == b'/')
+    };
+
+    Some(Ok(Tag {
+        start,
+        end: start + end + 1,
+        kind,
+    }))
+}
+
+/// local_name strips the namespace prefix from a qualified name.
+fn local_name(name: &[u8]) -> &[u8] {
+    match name.iter().position(|&b| b == b':') {
+        Some(colon) => &name[colon + 1..],
+        None => name,
+    }
+}
+
+/// is_balanced checks that every element opened in `data` is also closed, in
+/// the right order.
+fn is_balanced(data: &[u8]) -> bool {
+    let mut open = Vec::new();
+    let mut from = 0;
+    while let Some(tag) = next_tag(data, from) {
+        let tag = match tag {
+            Ok(tag) => tag,
+            Err(_) => return false,
+        };
+        match tag.kind {
+            TagKind::Open(name, false) => open.push(name),
+            TagKind::Close(name) if open.last() == Some(&name) => {
+                open.pop();
+            }
+            TagKind::Close(_) => return false,
+            _ => {}
+        }
+        from = tag.end;
+    }
+    open.is_empty()
+}
+
+/// find_closing_tag gives the position of the first closing tag of `name` at
+/// or after `from`, outside of comments and CDATA sections. Other markup is
+/// not parsed, so that this works on content that is not well-formed.
+fn find_closing_tag(data: &[u8], from: usize, name: &[u8]) -> Option<usize> {
+    let closing = [b"</", name].concat();
+    let mut position = from;
+    loop {
+        let start = position + data.get(position..)?.iter().position(|&b| b == b'<')?;
+        let rest = &data[start..];
+        let skip_to = |end: &[u8]| {
+            rest.windows(end.len())
+                .position(|w| w == end)
+                .map(|i| start + i + end.len())
+        };
+        position = if rest.starts_with(b"<!--") {
+            skip_to(b"-->")?
+        } else if rest.starts_with(b"<![CDATA[") {
+            skip_to(b"]]>")?
+        } else if rest.starts_with(&closing)
+            && matches!(rest.get(closing.len()), Some(b) if *b == b'>' || b.is_ascii_whitespace())
+        {
+            return Some(start);
+        } else {
+            start + 1
+        };
+    }
+}
+
+/// line_of gives the 1-based line number of `position` in `data`.
+fn line_of(data: &[u8], position: usize) -> usize {
+    data[..position].iter().filter(|&&b| b == b'\n').count() + 1
+}
+
+/// skip_malformed_extensions removes every `<extensions>` element whose
+/// content is not well-formed XML, so that the rest of the document can still
+/// be parsed, and read as if it had no extensions there. A
+/// `Warning::MalformedExtensions` is given for each of them.
+pub fn skip_malformed_extensions(data: Vec<u8>) -> (Vec<u8>, Vec<Warning>) {
+    let mut repaired = Vec::with_capacity(data.len());
+    let mut warnings = Vec::new();
+    // Everything before `copied` has been moved over to `repaired`.
+    let mut copied = 0;
+    let mut from = 0;
+
+    while let Some(Ok(tag)) = next_tag(&data, from) {
+        from = tag.end;
+        let name = match tag.kind {
+            TagKind::Open(name, false) if local_name(name) == b"extensions" => name,
+            _ => continue,
+        };
+
+        let content_end = match find_closing_tag(&data, tag.end, name) {
+            Some(position) => position,
+            None => break,
+        };
+        if !is_balanced(&data[tag.end..content_end]) {
+            let element_end = match data[content_end..].iter().position(|&b| b == b'>') {
+                Some(i) => content_end + i + 1,
+                None => break,
+            };
+            repaired.extend_from_slice(&data[copied..tag.start]);
+            copied = element_end;
+            warnings.push(Warning::MalformedExtensions {
+                line: line_of(&data, tag.start),
+            });
+        }
+        from = content_end;
+    }
+
+    if copied == 0 {
+        return (data, warnings);
+    }
+    repaired.extend_from_slice(&data[copied..]);
+    (repaired, warnings)
+}
+
+/// The GPX elements that have a `<desc>` child.
+const DESCRIBED_ELEMENTS: [&[u8]; 6] = [b"wpt", b"trkpt", b"rtept", b"trk", b"rte", b"metadata"];
+
+/// prefix gives the namespace prefix of a qualified name, empty if it has
+/// none.
+fn prefix(name: &[u8]) -> &[u8] {
+    &name[..name.len() - local_name(name).len()]
+}
+
+/// escape_markup_in_descriptions turns the markup inside the `<desc>` elements
+/// of waypoints, tracks, routes and metadata into text, so that descriptions
+/// holding HTML (like an unclosed `<br>`) are read as they were written
+/// instead of failing the parse. The content of `<extensions>` elements,
+/// which may have `desc` elements of their own, is left as it is.
+///
+/// Only tags are escaped: comments and CDATA sections are left as they are,
+/// and HTML entities that XML does not define (like `&nbsp;`) are still
+/// errors. The description ends at the first `</desc>` outside of them.
+pub fn escape_markup_in_descriptions(data: Vec<u8>) -> Vec<u8> {
+    let mut escaped = Vec::with_capacity(data.len());
+    // Everything before `copied` has been moved over to `escaped`.
+    let mut copied = 0;
+    let mut from = 0;
+    // The elements that are open at `from`, innermost last.
+    let mut open: Vec<&[u8]> = Vec::new();
+
+    while let Some(Ok(tag)) = next_tag(&data, from) {
+        from = tag.end;
+        let name = match tag.kind {
+            TagKind::Open(name, false) => name,
+            TagKind::Close(_) => {
+                open.pop();
+                continue;
+            }
+            _ => continue,
+        };
+        let parent = open.last().copied();
+        open.push(name);
+        let is_description = local_name(name) == b"desc"
+            && parent.is_some_and(|parent| {
+                DESCRIBED_ELEMENTS.contains(&local_name(parent)) && prefix(parent) == prefix(name)
+            });
+        if local_name(name) != b"extensions" && !is_description {
+            continue;
+        }
+
+        // Either way, the content is skipped up to the closing tag, which
+        // then closes the element.
+        let content_end = match find_closing_tag(&data, tag.end, name) {
+            Some(position) => position,
+            None => break,
+        };
+        from = content_end;
+        if !is_description {
+            continue;
+        }
+
+        let mut position = tag.end;
+        while position < content_end {
+            let markup = match next_tag(&data[..content_end], position) {
+                Some(Ok(markup)) => markup,
+                Some(Err(start)) => Tag {
+                    start,
+                    end: start + 1,
+                    kind: TagKind::Open(&[], false),
+                },
+                None => break,
+            };
+            if markup.kind != TagKind::Other {
+                escaped.extend_from_slice(&data[copied..markup.start]);
+                escaped.extend_from_slice(b"&lt;");
+                copied = markup.start + 1;
+            }
+            position = markup.end;
+        }
+    }
+
+    if copied == 0 {
+        return data;
+    }
+    escaped.extend_from_slice(&data[copied..]);
+    escaped
+}
+
+/// close_truncated_elements repairs a document that ends before all of its
+/// elements are closed, as happens when recording is interrupted.
+///
+/// Anything after the last closed element is dropped, since a half-written
+/// value (like a truncated timestamp) cannot be read, and closing tags are
+/// added for the elements that are still open. Documents that are not
+/// truncated are given back unchanged.
+pub fn close_truncated_elements(data: Vec<u8>) -> Vec<u8> {
+    let mut open: Vec<Vec<u8>> = Vec::new();
+    // The end of the last complete element, and how many elements were open
+    // there.
+    let mut cut = 0;
+    let mut depth = 0;
+    let mut from = 0;
+
+    while let Some(Ok(tag)) = next_tag(&data, from) {
+        from = tag.end;
+        match tag.kind {
+            TagKind::Open(name, false) => open.push(name.to_vec()),
+            TagKind::Open(_, true) => {
+                cut = tag.end;
+                depth = open.len();
+            }
+            TagKind::Close(_) => {
+                open.pop();
+                cut = tag.end;
+                depth = open.len();
+            }
+            TagKind::Other => {}
+        }
+    }
+    if open.is_empty() {
+        return data;
+    }
+
+    let mut repaired = data;
+    repaired.truncate(cut);
+    for name in open[..depth].iter().rev() {
+        repaired.extend_from_slice(b"</");
+        repaired.extend_from_slice(name);
+        repaired.push(b'>');
+    }
+    repaired
+}
+
+/// split_documents splits data holding several XML documents one after the
+/// other, as written by loggers that append a document per session, into the
+/// separate documents.
+///
+/// Each document ends with the closing tag of its root element, and the
+/// whitespace between documents is skipped. Anything left after the last
+/// complete document is given as one more document, so that reading it
+/// reports what is wrong with it.
+pub fn split_documents(data: &[u8]) -> Vec<&[u8]> {
+    let mut documents = Vec::new();
+    let mut start = 0;
+    let mut depth = 0;
+    let mut from = 0;
+
+    while let Some(Ok(tag)) = next_tag(data, from) {
+        from = tag.end;
+        match tag.kind {
+            TagKind::Open(_, false) => depth += 1,
+            TagKind::Close(_) => depth -= 1,
+            TagKind::Open(_, true) => {}
+            TagKind::Other => continue,
+        }
+        if depth <= 0 {
+            documents.push(data[start..tag.end].trim_ascii());
+            start = tag.end;
+            depth = 0;
+        }
+    }
+
+    let rest = data[start..].trim_ascii();
+    if !rest.is_empty() || documents.is_empty() {
+        documents.push(rest);
+    }
+    documents
+}
+
+/// The UTF-8 encoding of the byte order mark, U+FEFF.
+const BYTE_ORDER_MARK: &[u8] = b"\xEF\xBB\xBF";
+
+/// strip_byte_order_marks removes the byte order marks found after the start
+/// of the document, as left in the middle of it by concatenating files.
+/// The one at the very start, where it is allowed, is kept.
+pub fn strip_byte_order_marks(data: Vec<u8>) -> Vec<u8> {
+    let start = if data.starts_with(BYTE_ORDER_MARK) {
+        BYTE_ORDER_MARK.len()
+    } else {
+        0
+    };
+    if !data[start..]
+        .windows(BYTE_ORDER_MARK.len())
+        .any(|w| w == BYTE_ORDER_MARK)
+    {
+        return data;
+    }
+
+    let mut stripped = data[..start].to_vec();
+    let mut rest = &data[start..];
+    while let Some(i) = rest
+        .windows(BYTE_ORDER_MARK.len())
+        .position(|w| w == BYTE_ORDER_MARK)
+    {
+        stripped.extend_from_slice(&rest[..i]);
+        rest = &rest[i + BYTE_ORDER_MARK.len()..];
+    }
+    stripped.extend_from_slice(rest);
+    stripped
+}
+
+/// strip_unit removes the `unit` that some exporters write after a number,
+/// as in `100m` or `5.0 m/s`, so that the number can be parsed. Values without
+/// it are given back as they are.
+pub fn strip_unit<'a>(value: &'a str, unit: &str) -> &'a str {
+    value
+        .trim()
+        .strip_suffix(unit)
+        .map(str::trim_end)
+        .unwrap_or(value)
+}
+
+/// truncate_count reads a count that some devices write as a decimal number,
+/// as in `4.0`, dropping its fraction. Gives `None` for values that are not
+/// a non-negative number.
+pub fn truncate_count(value: &str) -> Option<u64> {
+    let count: f64 = value.trim().parse().ok()?;
+    if count.is_finite() && count >= 0.0 {
+        Some(count.trunc() as u64)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        close_truncated_elements, escape_markup_in_descriptions, is_balanced,
+        skip_malformed_extensions, split_documents, strip_byte_order_marks, strip_unit,
+        truncate_count,
+    };
+    use crate::errors::Warning;
+
+    #[test]
+    fn balanced_content() {
+        assert!(is_balanced(b"<a><b attr='>'/><!-- <c> --></a>text"));
+        assert!(is_balanced(b"<ns:a>1</ns:a><![CDATA[<b>]]>"));
+        assert!(!is_balanced(b"<a><b></a>"));
+        assert!(!is_balanced(b"<a>"));
+        assert!(!is_balanced(b"</a>"));
+        assert!(!is_balanced(b"<a attr='unterminated></a>"));
+    }
+
+    #[test]
+    fn skip_malformed() {
+        let data =
+            b"<wpt>\n<extensions><a><b></a></extensions>\n<extensions><c/></extensions></wpt>";
+        let (repaired, warnings) = skip_malformed_extensions(data.to_vec());
+
+        assert_eq!(
+            String::from_utf8(repaired).unwrap(),
+            "<wpt>\n\n<extensions><c/></extensions></wpt>"
+        );
+        assert_eq!(warnings, vec![Warning::MalformedExtensions { line: 2 }]);
+    }
+
+    #[test]
+    fn skip_malformed_with_comments() {
+        let data = b"<extensions><!-- </extensions> --><a></extensions><extensions><b/><!-- <c> --></extensions>";
+        let (repaired, warnings) = skip_malformed_extensions(data.to_vec());
+
+        assert_eq!(
+            String::from_utf8(repaired).unwrap(),
+            "<extensions><b/><!-- <c> --></extensions>"
+        );
+        assert_eq!(warnings.len(), 1);
+    }
+
+    #[test]
+    fn keep_well_formed() {
+        let data = b"<gpx><extensions/><extensions><a>1</a></extensions></gpx>";
+        let (repaired, warnings) = skip_malformed_extensions(data.to_vec());
+
+        assert_eq!(repaired, data.to_vec());
+        assert!(warnings.is_empty());
+    }
+
+    #[test]
+    fn escape_descriptions() {
+        let data = b"<wpt><desc>a<br>b <i>c</i> <!-- d --> e < f</desc><name>g<br/></name></wpt>";
+        let escaped = escape_markup_in_descriptions(data.to_vec());
+
+        assert_eq!(
+            String::from_utf8(escaped).unwrap(),
+            "<wpt><desc>a&lt;br>b &lt;i>c&lt;/i> <!-- d --> e &lt; f</desc><name>g<br/></name></wpt>"
+        );
+    }
+
+    #[test]
+    fn escape_gpx_descriptions_only() {
+        let data = b"<gpx><metadata><desc><b>a</b></desc></metadata><wpt><extensions><x:desc><b>b</b></x:desc></extensions></wpt><trk><x:desc><i/></x:desc></trk></gpx>";
+        let escaped = escape_markup_in_descriptions(data.to_vec());
+
+        assert_eq!(
+            String::from_utf8(escaped).unwrap(),
+            "<gpx><metadata><desc>&lt;b>a&lt;/b></desc></metadata><wpt><extensions><x:desc><b>b</b></x:desc></extensions></wpt><trk><x:desc><i/></x:desc></trk></gpx>"
+        );
+    }
+
+    #[test]
+    fn escape_descriptions_past_cdata() {
+        let data =
+            b"<rte><desc><![CDATA[</desc>]]><br><!-- </desc> --></desc><name><b/></name></rte>";
+        let escaped = escape_markup_in_descriptions(data.to_vec());
+
+        assert_eq!(
+            String::from_utf8(escaped).unwrap(),
+            "<rte><desc><![CDATA[</desc>]]>&lt;br><!-- </desc> --></desc><name><b/></name></rte>"
+        );
+    }
+
+    #[test]
+    fn close_truncated() {
+        let data = b"<gpx><trk><trkseg><trkpt lat='1' lon='2'><ele>3</ele></trkpt><trkpt lat='1' lon='3'><ele>4</ele><time>2020-";
+        let repaired = close_truncated_elements(data.to_vec());
+
+        assert_eq!(
+            String::from_utf8(repaired).unwrap(),
+            "<gpx><trk><trkseg><trkpt lat='1' lon='2'><ele>3</ele></trkpt><trkpt lat='1' lon='3'><ele>4</ele></trkpt></trkseg></trk></gpx>"
+        );
+
+        let data = b"<gpx><trk><name>a</name></trk></gpx>\n";
+        assert_eq!(close_truncated_elements(data.to_vec()), data.to_vec());
+    }
+
+    #[test]
+    fn split() {
+        let data = b"<?xml version='1.0'?>\n<gpx><a/></gpx>\n\n  \n<!-- b --><gpx/>\r\n<gpx>";
+        let documents: Vec<&[u8]> = vec![
+            b"<?xml version='1.0'?>\n<gpx><a/></gpx>",
+            b"<!-- b --><gpx/>",
+            b"<gpx>",
+        ];
+        assert_eq!(split_documents(data), documents);
+
+        assert_eq!(split_documents(b"<gpx></gpx>\n\n"), vec![b"<gpx></gpx>"]);
+        assert_eq!(split_documents(b" "), vec![b""]);
+    }
+
+    #[test]
+    fn strip_bom() {
+        let data = b"\xEF\xBB\xBF<gpx><a/>\xEF\xBB\xBF\n<b>\xEF\xBB\xBF\xEF\xBB\xBF</b></gpx>";
+        assert_eq!(
+            strip_byte_order_marks(data.to_vec()),
+            b"\xEF\xBB\xBF<gpx><a/>\n<b></b></gpx>".to_vec()
+        );
+
+        let data = b"<gpx></gpx>";
+        assert_eq!(strip_byte_order_marks(data.to_vec()), data.to_vec());
+    }
+
+    #[test]
+    fn strip_units() {
+        assert_eq!(strip_unit("100m", "m"), "100");
+        assert_eq!(strip_unit(" 5.0 m/s ", "m/s"), "5.0");
+        assert_eq!(strip_unit("100", "m"), "100");
+        assert_eq!(strip_unit("100 ft", "m"), "100 ft");
+    }
+
+    #[test]
+    fn truncate_counts() {
+        assert_eq!(truncate_count("4.0"), Some(4));
+        assert_eq!(truncate_count(" 7.9 "), Some(7));
+        assert_eq!(truncate_count("12"), Some(12));
+        assert_eq!(truncate_count("-1.0"), None);
+        assert_eq!(truncate_count("many"), None);
+    }
+}
@@ -6,7 +6,9 @@ use error_chain::{bail, ensure};
 use xml::reader::XmlEvent;
 
 use crate::errors::*;
-use crate::parser::{bounds, extensions, link, person, string, time, verify_starting_tag, Context};
+use crate::parser::{
+    bounds, copyright, extensions, link, person, string, time, verify_starting_tag, Context,
+};
 use crate::Metadata;
 
 pub fn consume<R: Read>(context: &mut Context<R>) -> Result<Metadata> {
@@ -36,6 +38,9 @@ pub fn consume<R: Read>(context: &mut Context<R>) -> Result<Metadata> {
                 "author" => {
                     metadata.author = Some(person::consume(context, "author")?);
                 }
+                "copyright" => {
+                    metadata.copyright = Some(copyright::consume(context)?);
+                }
                 "keywords" => {
                     metadata.keywords = Some(string::consume(context, "keywords", true)?);
                 }
@@ -77,7 +82,7 @@ pub fn consume<R: Read>(context: &mut Context<R>) -> Result<Metadata> {
 
 #[cfg(test)]
 mod tests {
-    use chrono::{TimeZone, Utc};
+    use chrono::{Duration, TimeZone, Utc};
 
     use super::consume;
     use crate::GpxVersion;
@@ -142,7 +147,7 @@ mod tests {
         assert!(result.time.is_some());
         assert_eq!(
             result.time.unwrap(),
-            Utc.ymd(2017, 8, 16).and_hms_micro(4, 3, 33, 735_000)
+            Utc.with_ymd_and_hms(2017, 8, 16, 4, 3, 33).unwrap() + Duration::milliseconds(735)
         );
 
         assert_eq!(result.links.len(), 1);
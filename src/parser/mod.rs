@@ -5,24 +5,24 @@
 #[macro_export]
 macro_rules! consume {
     ($xml:expr, $version:expr) => {{
-        use crate::parser::create_context;
         use std::io::BufReader;
+        use $crate::parser::create_context;
         consume(&mut create_context(
             BufReader::new($xml.as_bytes()),
             $version,
         ))
     }};
     ($xml:expr, $version:expr, $tagname:expr) => {{
-        use crate::parser::create_context;
         use std::io::BufReader;
+        use $crate::parser::create_context;
         consume(
             &mut create_context(BufReader::new($xml.as_bytes()), $version),
             $tagname,
         )
     }};
     ($xml:expr, $version:expr, $tagname:expr, $allow_empty:expr) => {{
-        use crate::parser::create_context;
         use std::io::BufReader;
+        use $crate::parser::create_context;
         consume(
             &mut create_context(BufReader::new($xml.as_bytes()), $version),
             $tagname,
@@ -32,14 +32,17 @@ macro_rules! consume {
 }
 
 pub mod bounds;
+pub mod copyright;
 pub mod email;
 pub mod extensions;
 pub mod fix;
 pub mod gpx;
+pub mod lenient;
 pub mod link;
 pub mod metadata;
 pub mod person;
 pub mod route;
+pub mod stream;
 pub mod string;
 pub mod time;
 pub mod track;
@@ -47,31 +50,130 @@ pub mod tracksegment;
 pub mod waypoint;
 
 use std::io::Read;
-use std::iter::Peekable;
 
 use error_chain::{bail, ensure};
 use xml::attribute::OwnedAttribute;
-use xml::reader::{Events, XmlEvent};
+use xml::common::{Position, TextPosition};
+use xml::reader::XmlEvent;
 use xml::{EventReader, ParserConfig};
 
 use crate::errors::*;
+use crate::reader::ParsingOptions;
 use crate::types::GpxVersion;
 
 pub struct Context<R: Read> {
-    reader: Peekable<Events<R>>,
+    reader: Reader<R>,
     version: GpxVersion,
+    options: ParsingOptions,
 }
 
 impl<R: Read> Context<R> {
-    pub fn new(reader: Peekable<Events<R>>, version: GpxVersion) -> Context<R> {
-        Context { reader, version }
+    pub fn new(reader: EventReader<R>, version: GpxVersion, options: ParsingOptions) -> Context<R> {
+        Context {
+            reader: Reader::new(reader),
+            version,
+            options,
+        }
     }
 
-    pub fn reader(&mut self) -> &mut Peekable<Events<R>> {
+    pub fn reader(&mut self) -> &mut Reader<R> {
         &mut self.reader
     }
 }
 
+/// Reader gives the events of an XML document one at a time, with a look at
+/// the next one, and keeps track of where in the document they are so that
+/// errors can tell.
+pub struct Reader<R: Read> {
+    parser: EventReader<R>,
+    peeked: Option<Option<xml::reader::Result<XmlEvent>>>,
+    finished: bool,
+    /// Local names of the elements that are open at the last event read.
+    path: Vec<String>,
+}
+
+impl<R: Read> Reader<R> {
+    fn new(parser: EventReader<R>) -> Reader<R> {
+        Reader {
+            parser,
+            peeked: None,
+            finished: false,
+            path: Vec::new(),
+        }
+    }
+
+    /// Gives the next event without consuming it.
+    pub fn peek(&mut self) -> Option<&xml::reader::Result<XmlEvent>> {
+        if self.peeked.is_none() {
+            self.peeked = Some(self.read());
+        }
+        self.peeked.as_ref().and_then(|event| event.as_ref())
+    }
+
+    /// The position of the last event read from the document, peeked or not.
+    pub fn position(&self) -> TextPosition {
+        self.parser.position()
+    }
+
+    /// The elements that are open at the last event read from the document,
+    /// starting at the root, e.g. `gpx/trk/trkseg/trkpt`.
+    pub fn path(&self) -> String {
+        self.path.join("/")
+    }
+
+    fn read(&mut self) -> Option<xml::reader::Result<XmlEvent>> {
+        if self.finished {
+            return None;
+        }
+        let event = self.parser.next();
+        match event {
+            Ok(XmlEvent::StartElement { ref name, .. }) => self.path.push(name.local_name.clone()),
+            Ok(XmlEvent::EndElement { .. }) => {
+                self.path.pop();
+            }
+            Ok(XmlEvent::EndDocument) | Err(_) => self.finished = true,
+            _ => {}
+        }
+        Some(event)
+    }
+}
+
+impl<R: Read> Iterator for Reader<R> {
+    type Item = xml::reader::Result<XmlEvent>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.peeked.take() {
+            Some(event) => event,
+            None => self.read(),
+        }
+    }
+}
+
+/// consume_positioned runs `consume` on the context, and tells where in the
+/// document it failed if it does, see `positioned`.
+pub fn consume_positioned<R: Read, T>(
+    context: &mut Context<R>,
+    consume: fn(&mut Context<R>) -> Result<T>,
+) -> Result<T> {
+    consume(context).map_err(|err| positioned(context, err))
+}
+
+/// positioned wraps an error in `ErrorKind::AtPosition`, to tell that it was
+/// found at the last event read from the document.
+pub fn positioned<R: Read>(context: &Context<R>, err: Error) -> Error {
+    let position = context.reader.position();
+    let message = err.to_string();
+    Error::with_chain(
+        err,
+        ErrorKind::AtPosition(
+            position.row + 1,
+            position.column + 1,
+            context.reader.path(),
+            message,
+        ),
+    )
+}
+
 pub fn verify_starting_tag<R: Read>(
     context: &mut Context<R>,
     local_name: &'static str,
@@ -102,13 +204,39 @@ pub fn verify_starting_tag<R: Read>(
     }
 }
 
+/// skip_element consumes the next element along with all of its content, up
+/// to and including its closing tag.
+pub fn skip_element<R: Read>(context: &mut Context<R>) -> Result<()> {
+    let mut depth = 0;
+    for event in context.reader() {
+        match event.chain_err(|| "error while parsing XML")? {
+            XmlEvent::StartElement { .. } => depth += 1,
+            XmlEvent::EndElement { .. } => {
+                depth -= 1;
+                if depth == 0 {
+                    return Ok(());
+                }
+            }
+            _ => {}
+        }
+    }
+    bail!("did not find closing tag of skipped element");
+}
+
 pub(crate) fn create_context<R: Read>(reader: R, version: GpxVersion) -> Context<R> {
+    create_context_with_options(reader, version, Default::default())
+}
+
+pub(crate) fn create_context_with_options<R: Read>(
+    reader: R,
+    version: GpxVersion,
+    options: ParsingOptions,
+) -> Context<R> {
     let parser_config = ParserConfig {
         whitespace_to_characters: true, //convert Whitespace event to Characters
         cdata_to_characters: true,      //convert CData event to Characters
         ..ParserConfig::new()
     };
     let parser = EventReader::new_with_config(reader, parser_config);
-    let events = parser.into_iter().peekable();
-    Context::new(events, version)
+    Context::new(parser, version, options)
 }
@@ -6,7 +6,7 @@ use error_chain::{bail, ensure};
 use xml::reader::XmlEvent;
 
 use crate::errors::*;
-use crate::parser::{link, string, verify_starting_tag, waypoint, Context};
+use crate::parser::{extensions, link, string, verify_starting_tag, waypoint, Context};
 use crate::Route;
 
 /// consume consumes a GPX route from the `reader` until it ends.
@@ -28,36 +28,12 @@ pub fn consume<R: Read>(context: &mut Context<R>) -> Result<Route> {
 
         match next_event {
             XmlEvent::StartElement { ref name, .. } => match name.local_name.as_ref() {
-                "name" => {
-                    route.name = Some(string::consume(context, "name", false)?);
-                }
-                "cmt" => {
-                    route.comment = Some(string::consume(context, "cmt", true)?);
-                }
-                "desc" => {
-                    route.description = Some(string::consume(context, "desc", true)?);
-                }
-                "src" => {
-                    route.source = Some(string::consume(context, "src", true)?);
-                }
-                "number" => {
-                    route.number = Some(
-                        string::consume(context, "number", false)?
-                            .parse()
-                            .chain_err(|| "error while casting route number (number) to u32")?,
-                    )
-                }
-                "type" => {
-                    route._type = Some(string::consume(context, "type", false)?);
-                }
                 "rtept" => {
                     route.points.push(waypoint::consume(context, "rtept")?);
                 }
-                "link" => {
-                    route.links.push(link::consume(context)?);
-                }
                 child => {
-                    bail!(ErrorKind::InvalidChildElement(String::from(child), "route"));
+                    let child = child.to_owned();
+                    consume_field(context, &mut route, &child)?;
                 }
             },
             XmlEvent::EndElement { ref name } => {
@@ -77,6 +53,33 @@ pub fn consume<R: Read>(context: &mut Context<R>) -> Result<Route> {
     bail!(ErrorKind::MissingClosingTag("route"));
 }
 
+/// consume_field consumes the child element `child` of a route into `route`,
+/// for any child but a route point.
+pub fn consume_field<R: Read>(
+    context: &mut Context<R>,
+    route: &mut Route,
+    child: &str,
+) -> Result<()> {
+    match child {
+        "name" => route.name = Some(string::consume(context, "name", false)?),
+        "cmt" => route.comment = Some(string::consume(context, "cmt", true)?),
+        "desc" => route.description = Some(string::consume(context, "desc", true)?),
+        "src" => route.source = Some(string::consume(context, "src", true)?),
+        "number" => {
+            route.number = Some(
+                string::consume(context, "number", false)?
+                    .parse()
+                    .chain_err(|| "error while casting route number (number) to u32")?,
+            )
+        }
+        "type" => route._type = Some(string::consume(context, "type", false)?),
+        "extensions" => route.extensions = extensions::consume(context)?,
+        "link" => route.links.push(link::consume(context)?),
+        child => bail!(ErrorKind::InvalidChildElement(String::from(child), "route")),
+    }
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::consume;
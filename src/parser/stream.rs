@@ -0,0 +1,255 @@
+//! stream handles parsing of GPX documents one piece at a time.
+
+use std::io::Read;
+
+use error_chain::{bail, ensure};
+use xml::reader::XmlEvent;
+
+use crate::errors::*;
+use crate::parser::gpx::{self, Gpx10Metadata};
+use crate::parser::{
+    extensions, metadata, positioned, route, track, verify_starting_tag, waypoint, Context,
+};
+use crate::{GpxEvent, GpxVersion, Route, Track};
+
+/// Peeked is what matters about the next event of the document.
+enum Peeked {
+    Start(String),
+    End(String),
+    Other,
+}
+
+/// peek tells what the next event of the document is, inside of `parent`.
+fn peek<R: Read>(context: &mut Context<R>, parent: &'static str) -> Result<Peeked> {
+    match context.reader.peek() {
+        Some(Ok(XmlEvent::StartElement { name, .. })) => Ok(Peeked::Start(name.local_name.clone())),
+        Some(Ok(XmlEvent::EndElement { name })) => Ok(Peeked::End(name.local_name.clone())),
+        Some(Ok(_)) => Ok(Peeked::Other),
+        Some(Err(_)) => bail!(format!("error while parsing {} event", parent)),
+        None => bail!(ErrorKind::MissingClosingTag(parent)),
+    }
+}
+
+/// State is where in the document the next event is.
+enum State {
+    Start,
+    Root,
+    /// In a track, with the track until its `TrackStart` has been given.
+    Track(Option<Track>),
+    /// In a track segment, with the number of its points read so far.
+    Segment(usize),
+    /// In a route, with the route until its `RouteStart` has been given.
+    Route(Option<Route>),
+    Done,
+}
+
+/// Events gives the pieces of a GPX document as they are read, see
+/// `read_streaming`.
+pub struct Events<R: Read> {
+    context: Context<R>,
+    state: State,
+    /// The metadata of a GPX 1.0 document until it has been given.
+    gpx10_metadata: Option<Gpx10Metadata>,
+}
+
+impl<R: Read> Events<R> {
+    pub fn new(context: Context<R>) -> Events<R> {
+        Events {
+            context,
+            state: State::Start,
+            gpx10_metadata: Some(Default::default()),
+        }
+    }
+
+    /// Gives the metadata of a GPX 1.0 document, if it has not been given
+    /// yet and there is any.
+    fn take_gpx10_metadata(&mut self) -> Option<GpxEvent> {
+        if self.context.version != GpxVersion::Gpx10 {
+            return None;
+        }
+        self.gpx10_metadata
+            .take()
+            .and_then(Gpx10Metadata::into_metadata)
+            .map(GpxEvent::Metadata)
+    }
+
+    fn step(&mut self) -> Result<Option<GpxEvent>> {
+        loop {
+            match self.state {
+                State::Start => {
+                    let gpx = gpx::consume_start(&mut self.context)?;
+                    self.state = State::Root;
+                    return Ok(Some(GpxEvent::Start(gpx)));
+                }
+                State::Root => match peek(&mut self.context, "gpx")? {
+                    Peeked::Start(child) => {
+                        if self.context.version == GpxVersion::Gpx10
+                            && Gpx10Metadata::is_field(&child)
+                        {
+                            match self.gpx10_metadata.as_mut() {
+                                Some(gpx10_metadata) => {
+                                    gpx10_metadata.consume(&mut self.context, &child)?
+                                }
+                                // Metadata after the first other element can
+                                // no longer be given, but is still checked.
+                                None => {
+                                    Gpx10Metadata::default().consume(&mut self.context, &child)?
+                                }
+                            }
+                            continue;
+                        }
+                        if let Some(event) = self.take_gpx10_metadata() {
+                            return Ok(Some(event));
+                        }
+                        let context = &mut self.context;
+                        match child.as_ref() {
+                            "metadata" if context.version != GpxVersion::Gpx10 => {
+                                return Ok(Some(GpxEvent::Metadata(metadata::consume(context)?)));
+                            }
+                            "trk" => {
+                                verify_starting_tag(context, "trk")?;
+                                self.state = State::Track(Some(Track::new()));
+                            }
+                            "rte" => {
+                                verify_starting_tag(context, "rte")?;
+                                self.state = State::Route(Some(Route::new()));
+                            }
+                            "wpt" => {
+                                return Ok(Some(GpxEvent::Waypoint(waypoint::consume(
+                                    context, "wpt",
+                                )?)));
+                            }
+                            // GPX 1.0 has no extensions element, which is
+                            // only read when parsing leniently.
+                            "extensions"
+                                if context.version != GpxVersion::Gpx10
+                                    || !context.options.strict =>
+                            {
+                                // Nothing is given when extensions are dropped.
+                                if let Some(extensions) = extensions::consume(context)? {
+                                    return Ok(Some(GpxEvent::Extensions(extensions)));
+                                }
+                            }
+                            child => {
+                                bail!(ErrorKind::InvalidChildElement(String::from(child), "gpx"));
+                            }
+                        }
+                    }
+                    Peeked::End(name) => {
+                        ensure!(
+                            name == "gpx",
+                            ErrorKind::InvalidClosingTag(name.clone(), "gpx")
+                        );
+                        if let Some(event) = self.take_gpx10_metadata() {
+                            return Ok(Some(event));
+                        }
+                        self.context.reader.next(); //consume the end tag
+                        self.state = State::Done;
+                    }
+                    Peeked::Other => {
+                        self.context.reader.next(); //consume and ignore this event
+                    }
+                },
+                State::Track(ref mut pending) => match peek(&mut self.context, "track")? {
+                    Peeked::Start(ref child) if child == "trkseg" => {
+                        if let Some(track) = pending.take() {
+                            return Ok(Some(GpxEvent::TrackStart(track)));
+                        }
+                        verify_starting_tag(&mut self.context, "trkseg")?;
+                        self.state = State::Segment(0);
+                        return Ok(Some(GpxEvent::TrackSegmentStart));
+                    }
+                    Peeked::Start(child) => match pending {
+                        Some(track) => track::consume_field(&mut self.context, track, &child)?,
+                        // Details after the first segment can no longer be
+                        // given, but are still checked.
+                        None => track::consume_field(&mut self.context, &mut Track::new(), &child)?,
+                    },
+                    Peeked::End(name) => {
+                        ensure!(
+                            name == "trk",
+                            ErrorKind::InvalidClosingTag(name.clone(), "track")
+                        );
+                        if let Some(track) = pending.take() {
+                            return Ok(Some(GpxEvent::TrackStart(track)));
+                        }
+                        self.context.reader.next(); //consume the end tag
+                        self.state = State::Root;
+                    }
+                    Peeked::Other => {
+                        self.context.reader.next(); //consume and ignore this event
+                    }
+                },
+                State::Segment(ref mut count) => match peek(&mut self.context, "tracksegment")? {
+                    Peeked::Start(ref child) if child == "trkpt" => {
+                        let point = waypoint::consume(&mut self.context, "trkpt")?;
+                        let limit = self.context.options.max_track_points_per_segment;
+                        *count += 1;
+                        if limit.is_none_or(|limit| *count <= limit) {
+                            return Ok(Some(GpxEvent::TrackPoint(point)));
+                        }
+                    }
+                    Peeked::Start(child) => {
+                        bail!(ErrorKind::InvalidChildElement(child, "tracksegment"));
+                    }
+                    Peeked::End(name) => {
+                        ensure!(
+                            name == "trkseg",
+                            ErrorKind::InvalidClosingTag(name.clone(), "trksegment")
+                        );
+                        self.context.reader.next(); //consume the end tag
+                        self.state = State::Track(None);
+                    }
+                    Peeked::Other => {
+                        self.context.reader.next(); //consume and ignore this event
+                    }
+                },
+                State::Route(ref mut pending) => match peek(&mut self.context, "route")? {
+                    Peeked::Start(ref child) if child == "rtept" => {
+                        if let Some(route) = pending.take() {
+                            return Ok(Some(GpxEvent::RouteStart(route)));
+                        }
+                        let point = waypoint::consume(&mut self.context, "rtept")?;
+                        return Ok(Some(GpxEvent::RoutePoint(point)));
+                    }
+                    Peeked::Start(child) => match pending {
+                        Some(route) => route::consume_field(&mut self.context, route, &child)?,
+                        // Details after the first point can no longer be
+                        // given, but are still checked.
+                        None => route::consume_field(&mut self.context, &mut Route::new(), &child)?,
+                    },
+                    Peeked::End(name) => {
+                        ensure!(
+                            name == "rte",
+                            ErrorKind::InvalidClosingTag(name.clone(), "route")
+                        );
+                        if let Some(route) = pending.take() {
+                            return Ok(Some(GpxEvent::RouteStart(route)));
+                        }
+                        self.context.reader.next(); //consume the end tag
+                        self.state = State::Root;
+                    }
+                    Peeked::Other => {
+                        self.context.reader.next(); //consume and ignore this event
+                    }
+                },
+                State::Done => return Ok(None),
+            }
+        }
+    }
+}
+
+impl<R: Read> Iterator for Events<R> {
+    type Item = Result<GpxEvent>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.step() {
+            Ok(event) => event.map(Ok),
+            Err(err) => {
+                // Nothing more can be read after an error.
+                self.state = State::Done;
+                Some(Err(positioned(&self.context, err)))
+            }
+        }
+    }
+}
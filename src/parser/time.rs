@@ -10,17 +10,33 @@ use crate::errors::*;
 use crate::parser::{string, Context};
 
 /// consume consumes an element as a time.
+///
+/// Times are given in RFC 3339, with optional fractional seconds and any UTC
+/// offset, and are converted to UTC so that the times of a track compare
+/// alike whatever offsets they were written with.
+///
+/// When parsing leniently, a time made up of digits only is taken as seconds
+/// since the Unix epoch, as some sensors write them.
 pub fn consume<R: Read>(context: &mut Context<R>) -> Result<DateTime<Utc>> {
     let time = string::consume(context, "time", false)?;
 
-    let time =
-        DateTime::parse_from_rfc3339(&time).chain_err(|| "error while parsing time as RFC3339")?;
+    if !context.options.strict && time.bytes().all(|b| b.is_ascii_digit()) {
+        let seconds: i64 = time
+            .parse()
+            .chain_err(|| "error while parsing time as Unix seconds")?;
+        return DateTime::from_timestamp(seconds, 0).ok_or_else(|| "Unix time out of range".into());
+    }
+
+    let time = DateTime::parse_from_rfc3339(&time)
+        .chain_err(|| format!("error while parsing time '{}' as RFC3339", time))?;
 
-    Ok(DateTime::from_utc(time.naive_utc(), Utc))
+    Ok(time.with_timezone(&Utc))
 }
 
 #[cfg(test)]
 mod tests {
+    use chrono::{TimeZone, Timelike, Utc};
+
     use super::consume;
     use crate::GpxVersion;
 
@@ -48,8 +64,8 @@ mod tests {
         // let result = consume!("<time>-2001-10-26T21:32:52</time>", GpxVersion::Gpx11);
         // assert!(result.is_ok());
 
-        // let result = consume!("<time>2001-10-26T21:32:52.12679</time>", GpxVersion::Gpx11);
-        // assert!(result.is_ok());
+        let result = consume!("<time>2001-10-26T21:32:52.12679Z</time>", GpxVersion::Gpx11);
+        assert_eq!(result.unwrap().nanosecond(), 126_790_000);
 
         // These are invalid, again, from xsd:dateTime examples.
         let result = consume!("<time>2001-10-26</time>", GpxVersion::Gpx11);
@@ -64,4 +80,27 @@ mod tests {
         let result = consume!("<time>01-10-26T21:32</time>", GpxVersion::Gpx11);
         assert!(result.is_err());
     }
+
+    #[test]
+    fn consume_time_normalizes_to_utc() {
+        let fractional = consume!("<time>2023-05-01T12:34:56.789Z</time>", GpxVersion::Gpx11);
+        let offset = consume!(
+            "<time>2023-05-01T14:34:56.789+02:00</time>",
+            GpxVersion::Gpx11
+        );
+
+        let expected = Utc.with_ymd_and_hms(2023, 5, 1, 12, 34, 56).unwrap()
+            + chrono::Duration::milliseconds(789);
+        assert_eq!(fractional.unwrap(), expected);
+        assert_eq!(offset.unwrap(), expected);
+    }
+
+    #[test]
+    fn consume_malformed_time() {
+        let err = consume!("<time>2023-13-01T12:34:56Z</time>", GpxVersion::Gpx11).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "error while parsing time '2023-13-01T12:34:56Z' as RFC3339"
+        );
+    }
 }
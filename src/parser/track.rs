@@ -6,7 +6,7 @@ use error_chain::{bail, ensure};
 use xml::reader::XmlEvent;
 
 use crate::errors::*;
-use crate::parser::{link, string, tracksegment, verify_starting_tag, Context};
+use crate::parser::{extensions, link, string, tracksegment, verify_starting_tag, Context};
 use crate::Track;
 
 /// consume consumes a GPX track from the `reader` until it ends.
@@ -28,29 +28,12 @@ pub fn consume<R: Read>(context: &mut Context<R>) -> Result<Track> {
 
         match next_event {
             XmlEvent::StartElement { ref name, .. } => match name.local_name.as_ref() {
-                "name" => {
-                    track.name = Some(string::consume(context, "name", false)?);
-                }
-                "cmt" => {
-                    track.comment = Some(string::consume(context, "cmt", true)?);
-                }
-                "desc" => {
-                    track.description = Some(string::consume(context, "desc", true)?);
-                }
-                "src" => {
-                    track.source = Some(string::consume(context, "src", true)?);
-                }
-                "type" => {
-                    track._type = Some(string::consume(context, "type", false)?);
-                }
                 "trkseg" => {
                     track.segments.push(tracksegment::consume(context)?);
                 }
-                "link" => {
-                    track.links.push(link::consume(context)?);
-                }
                 child => {
-                    bail!(ErrorKind::InvalidChildElement(String::from(child), "track"));
+                    let child = child.to_owned();
+                    consume_field(context, &mut track, &child)?;
                 }
             },
             XmlEvent::EndElement { ref name } => {
@@ -70,6 +53,26 @@ pub fn consume<R: Read>(context: &mut Context<R>) -> Result<Track> {
     bail!(ErrorKind::MissingClosingTag("track"));
 }
 
+/// consume_field consumes the child element `child` of a track into `track`,
+/// for any child but a track segment.
+pub fn consume_field<R: Read>(
+    context: &mut Context<R>,
+    track: &mut Track,
+    child: &str,
+) -> Result<()> {
+    match child {
+        "name" => track.name = Some(string::consume(context, "name", false)?),
+        "cmt" => track.comment = Some(string::consume(context, "cmt", true)?),
+        "desc" => track.description = Some(string::consume(context, "desc", true)?),
+        "src" => track.source = Some(string::consume(context, "src", true)?),
+        "type" => track._type = Some(string::consume(context, "type", false)?),
+        "extensions" => track.extensions = extensions::consume(context)?,
+        "link" => track.links.push(link::consume(context)?),
+        child => bail!(ErrorKind::InvalidChildElement(String::from(child), "track")),
+    }
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::consume;
@@ -101,6 +104,27 @@ mod tests {
         assert_eq!(track._type.unwrap(), "track type");
     }
 
+    #[test]
+    fn consume_extensions() {
+        let track = consume!(
+            "
+            <trk>
+                <type>running</type>
+                <extensions>
+                    <color><rgb r=\"255\" g=\"0\" b=\"0\"/></color>
+                </extensions>
+                <trkseg></trkseg>
+            </trk>
+            ",
+            GpxVersion::Gpx11
+        )
+        .unwrap();
+
+        let extensions = track.extensions.unwrap();
+        assert_eq!(extensions.children.len(), 1);
+        assert_eq!(track.segments.len(), 1);
+    }
+
     #[test]
     fn consume_empty() {
         let track = consume!("<trk></trk>", GpxVersion::Gpx11);
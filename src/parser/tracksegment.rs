@@ -28,7 +28,13 @@ pub fn consume<R: Read>(context: &mut Context<R>) -> Result<TrackSegment> {
 
         match next_event {
             XmlEvent::StartElement { ref name, .. } => match name.local_name.as_ref() {
-                "trkpt" => segment.points.push(waypoint::consume(context, "trkpt")?),
+                "trkpt" => {
+                    let point = waypoint::consume(context, "trkpt")?;
+                    let limit = context.options.max_track_points_per_segment;
+                    if limit.is_none_or(|limit| segment.points.len() < limit) {
+                        segment.points.push(point);
+                    }
+                }
                 child => {
                     bail!(ErrorKind::InvalidChildElement(
                         String::from(child),
@@ -1,5 +1,6 @@
 //! waypoint handles parsing of GPX-spec waypoints.
 
+use std::collections::HashSet;
 use std::io::Read;
 
 use error_chain::{bail, ensure};
@@ -7,48 +8,54 @@ use geo_types::Point;
 use xml::reader::XmlEvent;
 
 use crate::errors::*;
-use crate::parser::{extensions, fix, link, string, time, verify_starting_tag, Context};
+use crate::parser::lenient::{strip_unit, truncate_count};
+use crate::parser::{
+    extensions, fix, link, skip_element, string, time, verify_starting_tag, Context,
+};
 use crate::{GpxVersion, Waypoint};
 
 /// consume consumes a GPX waypoint from the `reader` until it ends.
 pub fn consume<R: Read>(context: &mut Context<R>, tagname: &'static str) -> Result<Waypoint> {
     let attributes = verify_starting_tag(context, tagname)?;
 
-    // get required latitude and longitude
-    let latitude = attributes
-        .iter()
-        .find(|attr| attr.name.local_name == "lat")
-        .ok_or(ErrorKind::InvalidElementLacksAttribute(
-            "latitude", "waypoint",
-        ))?;
-
-    let latitude: f64 = latitude
-        .value
-        .parse()
-        .chain_err(|| "error while casting latitude to f64")?;
-
-    if latitude < -90.0 || latitude > 90.0 {
-        bail!("latitude must be between [-90.0, 90.0]");
+    // get required latitude and longitude, which lenient parsing also accepts
+    // under their full names
+    let lenient = !context.options.strict;
+    let find_attribute = |name: &str, alias: &str| {
+        attributes
+            .iter()
+            .find(|attr| attr.name.local_name == name)
+            .or_else(|| {
+                attributes
+                    .iter()
+                    .find(|attr| lenient && attr.name.local_name == alias)
+            })
     };
 
-    let longitude = attributes
-        .iter()
-        .find(|attr| attr.name.local_name == "lon")
-        .ok_or(ErrorKind::InvalidElementLacksAttribute(
-            "longitude",
-            "waypoint",
-        ))?;
-
-    let longitude: f64 = longitude
-        .value
-        .parse()
-        .chain_err(|| "error while casting longitude to f64")?;
-
-    if longitude < -180.0 || longitude >= 180.0 {
-        bail!("longitude must be between [-180.0, 180.0[");
+    // Lenient parsing also reads the coordinates of a point without them
+    // from a KML-like `<coordinates>` child, once it has been found.
+    let latitude = find_attribute("lat", "latitude");
+    let longitude = find_attribute("lon", "longitude");
+    let point = match (latitude, longitude) {
+        (None, None) if lenient => None,
+        (latitude, longitude) => {
+            let latitude = latitude.ok_or(ErrorKind::InvalidElementLacksAttribute(
+                "latitude", "waypoint",
+            ))?;
+            let longitude = longitude.ok_or(ErrorKind::InvalidElementLacksAttribute(
+                "longitude",
+                "waypoint",
+            ))?;
+            Some(checked_point(&longitude.value, &latitude.value)?)
+        }
     };
 
-    let mut waypoint: Waypoint = Waypoint::new(Point::new(longitude, latitude));
+    let mut waypoint: Waypoint = Waypoint::new(point.unwrap_or_else(|| Point::new(0.0, 0.0)));
+    let mut coordinates = None;
+
+    // Children other than links may only be given once. Lenient parsing
+    // keeps the last one.
+    let mut seen = HashSet::new();
 
     loop {
         let next_event = {
@@ -64,23 +71,67 @@ pub fn consume<R: Read>(context: &mut Context<R>, tagname: &'static str) -> Resu
 
         match next_event {
             XmlEvent::StartElement { ref name, .. } => {
-                match name.local_name.as_ref() {
+                let child = name.local_name.as_str();
+                if child != "link" && !seen.insert(child.to_owned()) && !lenient {
+                    bail!(ErrorKind::DuplicateChildElement(
+                        String::from(child),
+                        "waypoint"
+                    ));
+                }
+                match child {
                     "ele" => {
                         // Cast the elevation to an f64, from a string.
+                        let value = string::consume(context, "ele", false)?;
+                        let value = if lenient {
+                            strip_unit(&value, "m")
+                        } else {
+                            &value
+                        };
                         waypoint.elevation = Some(
-                            string::consume(context, "ele", false)?
+                            value
                                 .parse()
                                 .chain_err(|| "error while casting elevation to f64")?,
                         )
                     }
                     "speed" if context.version == GpxVersion::Gpx10 => {
                         // Speed is from GPX 1.0
+                        let value = string::consume(context, "speed", false)?;
+                        let value = if lenient {
+                            strip_unit(&value, "m/s")
+                        } else {
+                            &value
+                        };
                         waypoint.speed = Some(
-                            string::consume(context, "speed", false)?
+                            value
                                 .parse()
                                 .chain_err(|| "error while casting speed to f64")?,
                         );
                     }
+                    "course" if context.version == GpxVersion::Gpx10 => {
+                        // Course is from GPX 1.0 too
+                        waypoint.course = Some(
+                            string::consume(context, "course", false)?
+                                .parse()
+                                .chain_err(|| "error while casting course to f64")?,
+                        );
+                    }
+                    "coordinates" if lenient && point.is_none() => {
+                        let value = string::consume(context, "coordinates", false)?;
+                        let mut parts = value.trim().split(',');
+                        let (longitude, latitude) = match (parts.next(), parts.next()) {
+                            (Some(longitude), Some(latitude)) => (longitude, latitude),
+                            _ => bail!("coordinates must be given as lon,lat[,ele]"),
+                        };
+                        coordinates = Some(checked_point(longitude.trim(), latitude.trim())?);
+                        if let Some(elevation) = parts.next() {
+                            waypoint.elevation = waypoint.elevation.or(Some(
+                                elevation
+                                    .trim()
+                                    .parse()
+                                    .chain_err(|| "error while casting elevation to f64")?,
+                            ));
+                        }
+                    }
                     "time" => waypoint.time = Some(time::consume(context)?),
                     "name" => waypoint.name = Some(string::consume(context, "name", false)?),
                     "cmt" => waypoint.comment = Some(string::consume(context, "cmt", true)?),
@@ -100,10 +151,15 @@ pub fn consume<R: Read>(context: &mut Context<R>, tagname: &'static str) -> Resu
                         )
                     }
                     "sat" => {
-                        waypoint.sat =
-                            Some(string::consume(context, "sat", false)?.parse().chain_err(
-                                || "error while casting number of satellites (sat) to u64",
-                            )?)
+                        // Lenient parsing also takes decimal numbers.
+                        let value = string::consume(context, "sat", false)?;
+                        let sat = value.parse();
+                        waypoint.sat = Some(match truncate_count(&value) {
+                            Some(count) if lenient && sat.is_err() => count,
+                            _ => sat.chain_err(|| {
+                                "error while casting number of satellites (sat) to u64"
+                            })?,
+                        });
                     }
                     "hdop" => waypoint.hdop = Some(
                         string::consume(context, "hdop", false)?
@@ -142,7 +198,17 @@ pub fn consume<R: Read>(context: &mut Context<R>, tagname: &'static str) -> Resu
                     }
 
                     // Finally the GPX 1.1 extensions
-                    "extensions" => extensions::consume(context)?,
+                    "extensions" => {
+                        waypoint.extensions = extensions::consume(context)?;
+                        if let Some(ref mut raw) = waypoint.extensions {
+                            waypoint.track_point_extension =
+                                extensions::take_track_point_extension(raw);
+                            if waypoint.track_point_extension.is_some() && raw.children.is_empty() {
+                                waypoint.extensions = None;
+                            }
+                        }
+                    }
+                    _ if lenient => skip_element(context)?,
                     child => {
                         bail!(ErrorKind::InvalidChildElement(
                             String::from(child),
@@ -157,6 +223,18 @@ pub fn consume<R: Read>(context: &mut Context<R>, tagname: &'static str) -> Resu
                     ErrorKind::InvalidClosingTag(name.local_name.clone(), "waypoint")
                 );
                 context.reader.next(); //consume the end tag
+                if point.is_none() {
+                    let coordinates = coordinates.ok_or(
+                        ErrorKind::InvalidElementLacksAttribute("latitude", "waypoint"),
+                    )?;
+                    waypoint.set_point(coordinates);
+                }
+                if context.options.elevation_from_extensions && waypoint.elevation.is_none() {
+                    waypoint.elevation = waypoint.barometric_elevation();
+                }
+                if waypoint.course.is_none() {
+                    waypoint.course = waypoint.extension_course();
+                }
                 return Ok(waypoint);
             }
             _ => {
@@ -168,11 +246,33 @@ pub fn consume<R: Read>(context: &mut Context<R>, tagname: &'static str) -> Resu
     bail!(ErrorKind::MissingClosingTag("waypoint"));
 }
 
+/// checked_point reads a point from its coordinates, which must be in range.
+fn checked_point(longitude: &str, latitude: &str) -> Result<Point<f64>> {
+    let latitude: f64 = latitude
+        .parse()
+        .chain_err(|| "error while casting latitude to f64")?;
+
+    if !(-90.0..=90.0).contains(&latitude) {
+        bail!("latitude must be between [-90.0, 90.0]");
+    };
+
+    let longitude: f64 = longitude
+        .parse()
+        .chain_err(|| "error while casting longitude to f64")?;
+
+    if !(-180.0..180.0).contains(&longitude) {
+        bail!("longitude must be between [-180.0, 180.0[");
+    };
+
+    Ok(Point::new(longitude, latitude))
+}
+
 #[cfg(test)]
 mod tests {
     use geo_types::Point;
 
     use super::consume;
+    use crate::errors::ErrorKind;
     use crate::{Fix, GpxVersion};
 
     #[test]
@@ -190,6 +290,7 @@ mod tests {
                 <sat>4</sat>
                 <hdop>6.058</hdop>
                 <speed>0.0000</speed>
+                <course>271.5</course>
             </wpt>
             ",
             GpxVersion::Gpx10,
@@ -216,6 +317,8 @@ mod tests {
         assert_eq!(waypoint.fix.unwrap(), Fix::DGPS);
         assert_eq!(waypoint.sat.unwrap(), 4);
         assert_eq!(waypoint.hdop.unwrap(), 6.058);
+        assert_eq!(waypoint.speed.unwrap(), 0.0);
+        assert_eq!(waypoint.course.unwrap(), 271.5);
     }
 
     #[test]
@@ -234,6 +337,41 @@ mod tests {
         assert_eq!(waypoint.point().lat(), 2.345);
     }
 
+    #[test]
+    fn consume_duplicate_child() {
+        let waypoint = consume!(
+            "
+            <wpt lat=\"47.0\" lon=\"8.0\">
+                <name>Trailhead</name>
+                <name>Summit</name>
+            </wpt>
+            ",
+            GpxVersion::Gpx11,
+            "wpt"
+        );
+
+        assert!(matches!(
+            waypoint.unwrap_err().kind(),
+            ErrorKind::DuplicateChildElement(child, "waypoint") if child == "name"
+        ));
+    }
+
+    #[test]
+    fn consume_repeated_links() {
+        let waypoint = consume!(
+            "
+            <wpt lat=\"47.0\" lon=\"8.0\">
+                <link href=\"https://example.com/a\"></link>
+                <link href=\"https://example.com/b\"></link>
+            </wpt>
+            ",
+            GpxVersion::Gpx11,
+            "wpt"
+        );
+
+        assert_eq!(waypoint.unwrap().links.len(), 2);
+    }
+
     #[test]
     fn consume_bad_waypoint() {
         let waypoint = consume!(
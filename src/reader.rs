@@ -1,10 +1,81 @@
 //! Reads an activity from GPX format.
 
-use std::io::Read;
+use std::fs::{self, File};
+use std::io::{BufReader, Read};
+use std::path::Path;
 
 use crate::errors::*;
-use crate::parser::{create_context, gpx};
-use crate::{Gpx, GpxVersion};
+use crate::parser::{
+    consume_positioned, create_context, create_context_with_options, gpx, lenient, stream,
+};
+use crate::{Gpx, GpxEvent, GpxVersion, Track, Waypoint};
+
+/// Options that change how a GPX document is read, see `read_with_options`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ParsingOptions {
+    /// Reject any document that does not follow the GPX specification. This
+    /// is the default.
+    ///
+    /// When turned off, some common mistakes are worked around instead, and
+    /// reported as warnings by `read_with_options_and_warnings`:
+    ///
+    /// - The content of `<extensions>` elements that are not well-formed XML
+    ///   is skipped.
+    /// - Markup inside `<desc>` elements, such as HTML line breaks, is read
+    ///   as part of the description text.
+    /// - Point coordinates are also read from `latitude` and `longitude`
+    ///   attributes, as some exporters write them.
+    /// - Points without coordinate attributes are read from a KML-like
+    ///   `<coordinates>lon,lat,ele</coordinates>` child, as some converters
+    ///   write them.
+    /// - Times given as a number are read as seconds since the Unix epoch.
+    /// - Elevations and speeds followed by their unit, as in `100m` or
+    ///   `5.0 m/s`, are read without it.
+    /// - Byte order marks in the middle of the document, as left by
+    ///   concatenating files, are skipped.
+    /// - Unknown elements in waypoints, track points and route points,
+    ///   outside of `<extensions>`, are skipped along with their content.
+    /// - Waypoint children given more than once, such as two `<name>`
+    ///   elements, are read as the last one given instead of failing with
+    ///   `DuplicateChildElement`.
+    ///
+    /// Lenient parsing reads the whole document into memory before parsing it.
+    pub strict: bool,
+
+    /// Start reading at the first `<gpx>` element found anywhere in the
+    /// document, instead of requiring it to be the root element. This allows
+    /// reading GPX that has been embedded in another XML document, such as an
+    /// Atom or RSS feed. Everything after the closing `</gpx>` is ignored.
+    pub find_gpx_anywhere: bool,
+
+    /// Skip the content of all `<extensions>` elements instead of keeping it,
+    /// which saves time and memory when only the geometry is needed. The
+    /// `extensions` fields are then left empty.
+    pub drop_extensions: bool,
+
+    /// Keep at most this many points of each track segment, such as for a
+    /// quick preview. The points after them are still parsed, so that the
+    /// document is checked as a whole, but not kept.
+    pub max_track_points_per_segment: Option<usize>,
+
+    /// Fill in the `elevation` of points without an `<ele>` element from the
+    /// barometric elevation in their extensions, as written by devices that
+    /// only record that one. See `Waypoint::best_elevation` for the elements
+    /// recognized.
+    pub elevation_from_extensions: bool,
+}
+
+impl Default for ParsingOptions {
+    fn default() -> ParsingOptions {
+        ParsingOptions {
+            strict: true,
+            find_gpx_anywhere: false,
+            drop_extensions: false,
+            max_track_points_per_segment: None,
+            elevation_from_extensions: false,
+        }
+    }
+}
 
 /// Reads an activity in GPX format.
 ///
@@ -33,5 +104,288 @@ use crate::{Gpx, GpxVersion};
 /// }
 /// ```
 pub fn read<R: Read>(reader: R) -> Result<Gpx> {
-    gpx::consume(&mut create_context(reader, GpxVersion::Unknown))
+    consume_positioned(
+        &mut create_context(reader, GpxVersion::Unknown),
+        gpx::consume,
+    )
+}
+
+/// Reads an activity in GPX format from a string.
+///
+/// The version is taken from the `version` attribute of the root element,
+/// which must be there. Versions other than 1.0 and 1.1 are read as 1.1, see
+/// `Gpx::declared_version`.
+///
+/// ```
+/// use gpx::{from_str, GpxVersion};
+///
+/// let gpx = from_str("<gpx version=\"1.0\"></gpx>").unwrap();
+/// assert_eq!(gpx.version, GpxVersion::Gpx10);
+/// ```
+pub fn from_str(data: &str) -> Result<Gpx> {
+    read(data.as_bytes())
+}
+
+/// Reads an activity in GPX format from any `std::io::Read`, with its version
+/// detected like `from_str`. This is the same as `read`.
+pub fn from_reader<R: Read>(reader: R) -> Result<Gpx> {
+    read(reader)
+}
+
+/// Reads an activity in GPX format, using the given `ParsingOptions`.
+///
+/// ```
+/// use gpx::{read_with_options, ParsingOptions};
+///
+/// let data = "<feed><entry><gpx version=\"1.1\"></gpx></entry></feed>";
+/// let options = ParsingOptions {
+///     find_gpx_anywhere: true,
+///     ..Default::default()
+/// };
+///
+/// assert!(read_with_options(data.as_bytes(), &options).is_ok());
+/// ```
+pub fn read_with_options<R: Read>(reader: R, options: &ParsingOptions) -> Result<Gpx> {
+    read_with_options_and_warnings(reader, options).map(|(gpx, _)| gpx)
+}
+
+/// Reads every `.gpx` file in the directory at `path` and merges them into a
+/// single GPX 1.1 document, such as to show all activities on one map.
+///
+/// Each file becomes one track, holding the segments of all its tracks and
+/// named after its first named track, or else after the file. Files are read
+/// in the order of their names, and files with other extensions are skipped.
+pub fn read_dir_merged<P: AsRef<Path>>(path: P) -> Result<Gpx> {
+    let mut paths = Vec::new();
+    for entry in fs::read_dir(path).chain_err(|| "error while listing GPX directory")? {
+        let path = entry
+            .chain_err(|| "error while listing GPX directory")?
+            .path();
+        let is_gpx = path
+            .extension()
+            .is_some_and(|extension| extension.eq_ignore_ascii_case("gpx"));
+        if is_gpx && path.is_file() {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+
+    let mut merged = Gpx {
+        version: GpxVersion::Gpx11,
+        ..Default::default()
+    };
+    for path in paths {
+        let file =
+            File::open(&path).chain_err(|| format!("error while opening {}", path.display()))?;
+        let gpx = read(BufReader::new(file))
+            .chain_err(|| format!("error while reading {}", path.display()))?;
+
+        let mut track = Track::new();
+        track.name = gpx
+            .tracks
+            .iter()
+            .find_map(|track| track.name.clone())
+            .or_else(|| Some(path.file_stem()?.to_string_lossy().into_owned()));
+        track.segments = gpx
+            .tracks
+            .into_iter()
+            .flat_map(|track| track.segments)
+            .collect();
+        merged.tracks.push(track);
+    }
+    Ok(merged)
+}
+
+/// Reads an activity in GPX format that may have been cut off, such as the
+/// file of a recording that was interrupted.
+///
+/// When the document ends before all of its elements are closed, everything
+/// after the last complete element is dropped and the partial activity is
+/// returned, instead of failing with `MissingClosingTag`. The whole document
+/// is read into memory first.
+///
+/// ```
+/// use gpx::read_repair;
+///
+/// let data = "<gpx version=\"1.1\"><trk><trkseg>
+///     <trkpt lat=\"1.0\" lon=\"2.0\"></trkpt>
+///     <trkpt lat=\"1.0\" lon=";
+///
+/// let gpx = read_repair(data.as_bytes()).unwrap();
+/// assert_eq!(gpx.tracks[0].segments[0].points.len(), 1);
+/// ```
+pub fn read_repair<R: Read>(mut reader: R) -> Result<Gpx> {
+    let mut data = Vec::new();
+    reader
+        .read_to_end(&mut data)
+        .chain_err(|| "error while reading GPX document")?;
+    let data = lenient::close_truncated_elements(data);
+    read(data.as_slice())
+}
+
+/// Reads several activities in GPX format from one reader, where each
+/// document follows the previous one, such as in the file of a logger that
+/// appends a new document for each session.
+///
+/// Whitespace and blank lines between the documents are skipped. The whole
+/// input is read into memory first.
+///
+/// ```
+/// use gpx::read_all;
+///
+/// let data = "<gpx version=\"1.1\"></gpx>\n\n<gpx version=\"1.0\"></gpx>\n";
+///
+/// let documents = read_all(data.as_bytes()).unwrap();
+/// assert_eq!(documents.len(), 2);
+/// ```
+pub fn read_all<R: Read>(mut reader: R) -> Result<Vec<Gpx>> {
+    let mut data = Vec::new();
+    reader
+        .read_to_end(&mut data)
+        .chain_err(|| "error while reading GPX documents")?;
+    lenient::split_documents(&data)
+        .into_iter()
+        .enumerate()
+        .map(|(i, document)| {
+            read(document).chain_err(|| format!("error while reading GPX document {}", i + 1))
+        })
+        .collect()
+}
+
+/// Reads an activity in GPX format one piece at a time, such as to go through
+/// the points of a file too large to hold in memory.
+///
+/// Each piece is given as it is read, see `GpxEvent`, and the points are not
+/// kept. An error is given as the last item, after which nothing more is
+/// read.
+///
+/// ```
+/// use gpx::{read_streaming, GpxEvent};
+///
+/// let data = "<gpx version=\"1.1\"><trk><trkseg>
+///     <trkpt lat=\"1.0\" lon=\"2.0\"></trkpt>
+///     <trkpt lat=\"1.5\" lon=\"2.5\"></trkpt>
+/// </trkseg></trk></gpx>";
+///
+/// let points = read_streaming(data.as_bytes())
+///     .filter(|event| matches!(event, Ok(GpxEvent::TrackPoint(_))))
+///     .count();
+/// assert_eq!(points, 2);
+/// ```
+pub fn read_streaming<R: Read>(reader: R) -> impl Iterator<Item = Result<GpxEvent>> {
+    stream::Events::new(create_context(reader, GpxVersion::Unknown))
+}
+
+/// Reads an activity in GPX format, and also gives the non-fatal problems
+/// found in its data, such as points without elevation or timestamps that go
+/// back in time.
+///
+/// ```
+/// use gpx::read_with_warnings;
+/// use gpx::errors::Warning;
+///
+/// let data = "<gpx version=\"1.1\"><trk><trkseg>
+///     <trkpt lat=\"1.0\" lon=\"2.0\"></trkpt>
+/// </trkseg></trk></gpx>";
+///
+/// let (gpx, warnings) = read_with_warnings(data.as_bytes()).unwrap();
+/// assert_eq!(warnings, vec![Warning::MissingElevation { count: 1 }]);
+/// ```
+pub fn read_with_warnings<R: Read>(reader: R) -> Result<(Gpx, Vec<Warning>)> {
+    read_with_options_and_warnings(reader, &Default::default())
+}
+
+/// Reads an activity in GPX format using the given `ParsingOptions`, and also
+/// gives the non-fatal problems found in its data, like `read_with_warnings`.
+///
+/// When parsing leniently, the warnings also tell which mistakes in the
+/// document have been worked around.
+///
+/// ```
+/// use gpx::{read_with_options_and_warnings, ParsingOptions};
+/// use gpx::errors::Warning;
+///
+/// let data = "<gpx version=\"1.1\"><extensions><a><b></a></extensions></gpx>";
+/// let options = ParsingOptions {
+///     strict: false,
+///     ..Default::default()
+/// };
+///
+/// let (gpx, warnings) = read_with_options_and_warnings(data.as_bytes(), &options).unwrap();
+/// assert_eq!(warnings, vec![Warning::MalformedExtensions { line: 1 }]);
+/// ```
+pub fn read_with_options_and_warnings<R: Read>(
+    mut reader: R,
+    options: &ParsingOptions,
+) -> Result<(Gpx, Vec<Warning>)> {
+    let (gpx, mut warnings) = if options.strict {
+        let gpx = consume_positioned(
+            &mut create_context_with_options(reader, GpxVersion::Unknown, options.clone()),
+            gpx::consume,
+        )?;
+        (gpx, Vec::new())
+    } else {
+        let mut data = Vec::new();
+        reader
+            .read_to_end(&mut data)
+            .chain_err(|| "error while reading GPX document")?;
+        let data = lenient::strip_byte_order_marks(data);
+        let (data, warnings) = lenient::skip_malformed_extensions(data);
+        let data = lenient::escape_markup_in_descriptions(data);
+        let gpx = consume_positioned(
+            &mut create_context_with_options(data.as_slice(), GpxVersion::Unknown, options.clone()),
+            gpx::consume,
+        )?;
+        (gpx, warnings)
+    };
+    if let Some(ref version) = gpx.declared_version {
+        warnings.push(Warning::UnknownVersion {
+            version: version.clone(),
+        });
+    }
+    warnings.extend(check_points(&gpx));
+    Ok((gpx, warnings))
+}
+
+/// Checks the track and route points of `gpx` for data-quality problems.
+fn check_points(gpx: &Gpx) -> Vec<Warning> {
+    let sequences = gpx
+        .tracks
+        .iter()
+        .flat_map(|track| track.segments.iter().map(|seg| &seg.points))
+        .chain(gpx.routes.iter().map(|route| &route.points));
+
+    let mut missing_elevation = 0;
+    let mut non_monotonic_time = 0;
+    for points in sequences {
+        missing_elevation += points.iter().filter(|p| p.elevation.is_none()).count();
+        non_monotonic_time += count_non_monotonic_time(points);
+    }
+
+    let mut warnings = Vec::new();
+    if missing_elevation > 0 {
+        warnings.push(Warning::MissingElevation {
+            count: missing_elevation,
+        });
+    }
+    if non_monotonic_time > 0 {
+        warnings.push(Warning::NonMonotonicTime {
+            count: non_monotonic_time,
+        });
+    }
+    warnings
+}
+
+/// Counts the timed points that are earlier than the last timed point before
+/// them.
+fn count_non_monotonic_time(points: &[Waypoint]) -> usize {
+    let mut count = 0;
+    let mut previous = None;
+    for time in points.iter().filter_map(|p| p.time) {
+        if matches!(previous, Some(previous) if time < previous) {
+            count += 1;
+        }
+        previous = Some(time);
+    }
+    count
 }
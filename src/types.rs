@@ -1,28 +1,40 @@
 //! generic types for GPX
 
-use geo_types::{Geometry, LineString, MultiLineString, Point, Rect};
+use std::collections::{BTreeMap, HashMap, HashSet};
 
-use chrono::{DateTime, Utc};
+use geo::algorithm::convexhull::ConvexHull;
+use geo::algorithm::haversine_distance::HaversineDistance;
+use geo::algorithm::haversine_length::HaversineLength;
+#[cfg(feature = "geojson")]
+use geo::algorithm::simplify::Simplify;
+use geo_types::{Coordinate, Geometry, LineString, MultiLineString, Point, Polygon, Rect};
 
-#[derive(Clone, Copy, Debug, PartialEq)]
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use error_chain::bail;
+
+use crate::errors::*;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum GpxVersion {
+    #[default]
     Unknown,
     Gpx10,
     Gpx11,
 }
 
-impl Default for GpxVersion {
-    fn default() -> GpxVersion {
-        GpxVersion::Unknown
-    }
-}
-
 /// Gpx is the root element in the XML file.
 #[derive(Clone, Default, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Gpx {
     /// Version of the Gpx file.
     pub version: GpxVersion,
 
+    /// Version declared by the document when it is not one of the known
+    /// versions, such as the experimental `1.2`. The document is then read as
+    /// GPX 1.1 on a best-effort basis, and written back out with this version.
+    pub declared_version: Option<String>,
+
     /// Creator name or URL of the software that created GPX document
     pub creator: Option<String>,
 
@@ -37,6 +49,431 @@ pub struct Gpx {
 
     /// A list of routes with a list of point-by-point directions
     pub routes: Vec<Route>,
+
+    /// Namespace URI of the root `<gpx>` element, as read from the document.
+    /// It is written back out instead of the standard namespace, so that
+    /// documents using a variant schema keep it, unless it is the standard
+    /// namespace of another version than the one written. It is `None` for
+    /// documents declaring no namespace, which are read all the same and
+    /// written with the standard one.
+    pub namespace: Option<String>,
+
+    /// Namespaces declared with a prefix on the root `<gpx>` element, as
+    /// pairs of prefix and URI, such as `gpxtpx` for Garmin's extensions.
+    /// Those that the document uses are declared again on the root element
+    /// when writing.
+    pub namespaces: Vec<(String, String)>,
+
+    /// The `xsi:schemaLocation` of the root `<gpx>` element, giving the
+    /// schemas of the document and of its extensions. It is written back out.
+    pub schema_location: Option<String>,
+
+    /// Extensions of the root element. Fitness devices, Garmin's among them,
+    /// store summaries of the whole session here (total calories, average
+    /// heart rate, ...). GPX 1.0 has no such element, so there it is only
+    /// read when parsing leniently, and never written.
+    pub summary_extensions: Option<Extensions>,
+
+    /// Processing instructions found before the root element, such as
+    /// `xml-stylesheet type="text/xsl" href="style.xsl"`, without the
+    /// surrounding `<?` and `?>`. They are written back out before the root
+    /// element.
+    pub processing_instructions: Vec<String>,
+}
+
+impl Gpx {
+    /// Checks whether two documents describe the same geometry: the same
+    /// waypoints, tracks, track segments and routes, made up of points at the
+    /// same coordinates.
+    ///
+    /// Everything else, such as metadata, names, timestamps or elevations, is
+    /// ignored. This answers "did the route change?" rather than "is this the
+    /// same file?".
+    pub fn geometry_eq(&self, other: &Gpx) -> bool {
+        fn points_eq(a: &[Waypoint], b: &[Waypoint]) -> bool {
+            a.len() == b.len() && a.iter().zip(b).all(|(a, b)| a.point() == b.point())
+        }
+
+        points_eq(&self.waypoints, &other.waypoints)
+            && self.tracks.len() == other.tracks.len()
+            && self.tracks.iter().zip(&other.tracks).all(|(a, b)| {
+                a.segments.len() == b.segments.len()
+                    && a.segments
+                        .iter()
+                        .zip(&b.segments)
+                        .all(|(a, b)| points_eq(&a.points, &b.points))
+            })
+            && self.routes.len() == other.routes.len()
+            && self
+                .routes
+                .iter()
+                .zip(&other.routes)
+                .all(|(a, b)| points_eq(&a.points, &b.points))
+    }
+
+    /// Rounds the coordinates of every waypoint, track point and route point,
+    /// as well as the metadata bounds, to the nearest multiple of
+    /// `precision_deg` degrees.
+    ///
+    /// Unlike adding random noise, this is deterministic: every point within
+    /// the same grid cell ends up at the same coordinates, which is useful to
+    /// anonymize locations before sharing a file.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `precision_deg` is not positive.
+    ///
+    /// ```
+    /// use gpx::{Gpx, Waypoint};
+    /// use geo_types::Point;
+    ///
+    /// let mut gpx = Gpx::default();
+    /// gpx.waypoints.push(Waypoint::new(Point::new(2.3522, 48.8566)));
+    ///
+    /// gpx.snap_to_grid(0.5);
+    /// assert_eq!(gpx.waypoints[0].point(), Point::new(2.5, 49.0));
+    /// ```
+    pub fn snap_to_grid(&mut self, precision_deg: f64) {
+        assert!(precision_deg > 0.0, "grid precision must be positive");
+        let snap = |value: f64| (value / precision_deg).round() * precision_deg;
+        let snap_coordinate = |c: Coordinate<f64>| {
+            let mut lon = snap(c.x);
+            // Longitudes wrap around, latitudes stop at the poles.
+            if lon >= 180.0 {
+                lon -= 360.0;
+            }
+            Coordinate {
+                x: lon,
+                y: snap(c.y).clamp(-90.0, 90.0),
+            }
+        };
+
+        for wpt in self.points_mut() {
+            wpt.point = GpxPoint(snap_coordinate(wpt.point().0).into());
+        }
+        if let Some(bounds) = self.metadata.as_mut().and_then(|m| m.bounds.as_mut()) {
+            *bounds = Rect::new(snap_coordinate(bounds.min()), snap_coordinate(bounds.max()));
+        }
+    }
+
+    /// Adds `offset` to the time of the metadata and of all waypoints, track
+    /// points and route points, such as to correct the recording of a device
+    /// whose clock was wrong. A negative offset moves the times back.
+    ///
+    /// # Errors
+    ///
+    /// Fails if a time would go out of the range of `DateTime`, in which case
+    /// no time is shifted.
+    ///
+    /// ```
+    /// use chrono::{Duration, TimeZone, Utc};
+    /// use gpx::{Gpx, Waypoint};
+    /// use geo_types::Point;
+    ///
+    /// let mut gpx = Gpx::default();
+    /// let mut wpt = Waypoint::new(Point::new(2.3522, 48.8566));
+    /// wpt.time = Some(Utc.with_ymd_and_hms(2020, 6, 1, 8, 0, 0).unwrap());
+    /// gpx.waypoints.push(wpt);
+    ///
+    /// gpx.shift_times(Duration::minutes(-90)).unwrap();
+    /// assert_eq!(
+    ///     gpx.waypoints[0].time,
+    ///     Some(Utc.with_ymd_and_hms(2020, 6, 1, 6, 30, 0).unwrap())
+    /// );
+    /// ```
+    pub fn shift_times(&mut self, offset: Duration) -> Result<()> {
+        let metadata_time = self.metadata.as_ref().and_then(|m| m.time);
+        let times = metadata_time
+            .into_iter()
+            .chain(self.points().filter_map(|wpt| wpt.time));
+        for time in times {
+            if time.checked_add_signed(offset).is_none() {
+                bail!(
+                    "shifting {} by {} goes out of the range of dates",
+                    time,
+                    offset
+                );
+            }
+        }
+
+        if let Some(time) = self.metadata.as_mut().and_then(|m| m.time.as_mut()) {
+            *time += offset;
+        }
+        for time in self.points_mut().filter_map(|wpt| wpt.time.as_mut()) {
+            *time += offset;
+        }
+        Ok(())
+    }
+
+    /// Gives the smallest rectangle that holds all waypoints, track points and
+    /// route points, or `None` if there are none, such as to fill in the
+    /// bounds of the metadata before writing.
+    ///
+    /// ```
+    /// use gpx::{Gpx, Waypoint};
+    /// use geo_types::Point;
+    ///
+    /// let mut gpx = Gpx::default();
+    /// gpx.waypoints.push(Waypoint::new(Point::new(2.3522, 48.8566)));
+    /// gpx.waypoints.push(Waypoint::new(Point::new(4.8357, 45.764)));
+    ///
+    /// let bounds = gpx.bounds().unwrap();
+    /// assert_eq!(bounds.min().x, 2.3522);
+    /// assert_eq!(bounds.max().y, 48.8566);
+    /// ```
+    pub fn bounds(&self) -> Option<Rect<f64>> {
+        let mut points = self.points().map(|wpt| wpt.point().0);
+        let first = points.next()?;
+        let (min, max) = points.fold((first, first), |(min, max), c| {
+            (
+                Coordinate {
+                    x: min.x.min(c.x),
+                    y: min.y.min(c.y),
+                },
+                Coordinate {
+                    x: max.x.max(c.x),
+                    y: max.y.max(c.y),
+                },
+            )
+        });
+        Some(Rect::new(min, max))
+    }
+
+    /// Gives the width and height, in kilometers, of the rectangle given by
+    /// `bounds`, or `None` if there are no points, such as to pick the zoom
+    /// level of a map showing the document. The width is measured along the
+    /// middle latitude of the rectangle, where degrees of longitude are
+    /// shorter the farther from the equator.
+    pub fn extent_km(&self) -> Option<(f64, f64)> {
+        let bounds = self.bounds()?;
+        let (min, max) = (bounds.min(), bounds.max());
+        let middle = (min.y + max.y) / 2.0;
+        let width = Point::new(min.x, middle).haversine_distance(&Point::new(max.x, middle));
+        let height = Point::new(min.x, min.y).haversine_distance(&Point::new(min.x, max.y));
+        Some((width / 1000.0, height / 1000.0))
+    }
+
+    /// Recomputes the bounds of the metadata with `bounds`, such as after some
+    /// points have been moved or removed. Metadata is added if there is none,
+    /// and the bounds are cleared if there are no points.
+    pub fn refresh_bounds(&mut self) {
+        let bounds = self.bounds();
+        self.metadata.get_or_insert_with(Default::default).bounds = bounds;
+    }
+
+    /// Gives the distinct symbol names (`<sym>`) used by the waypoints, track
+    /// points and route points, e.g. to build a map legend.
+    ///
+    /// ```
+    /// use gpx::{Gpx, Waypoint};
+    /// use geo_types::Point;
+    ///
+    /// let mut gpx = Gpx::default();
+    /// for symbol in &["Flag", "Summit", "Flag"] {
+    ///     let mut wpt = Waypoint::new(Point::new(0.0, 0.0));
+    ///     wpt.symbol = Some(symbol.to_string());
+    ///     gpx.waypoints.push(wpt);
+    /// }
+    ///
+    /// assert_eq!(gpx.symbols_used().len(), 2);
+    /// ```
+    pub fn symbols_used(&self) -> HashSet<String> {
+        self.points().filter_map(|wpt| wpt.symbol.clone()).collect()
+    }
+
+    /// Gives the number of tracks.
+    pub fn track_count(&self) -> usize {
+        self.tracks.len()
+    }
+
+    /// Gives the number of track segments, over all tracks.
+    pub fn segment_count(&self) -> usize {
+        self.tracks.iter().map(|track| track.segments.len()).sum()
+    }
+
+    /// Gives the number of waypoints. Track points and route points are not
+    /// counted.
+    pub fn waypoint_count(&self) -> usize {
+        self.waypoints.len()
+    }
+
+    /// Gives the number of different names of the tracks, such as for
+    /// statistics of a library of activities. Surrounding whitespace is
+    /// ignored, and unnamed tracks, or tracks with an empty name, are not
+    /// counted.
+    pub fn unique_track_names(&self) -> usize {
+        self.tracks
+            .iter()
+            .filter_map(|track| track.name.as_deref().map(str::trim))
+            .filter(|name| !name.is_empty())
+            .collect::<HashSet<_>>()
+            .len()
+    }
+
+    /// Gives a copy of the document with only its waypoints, without the
+    /// tracks and routes, such as to extract the points of interest of a
+    /// trip. Everything else, metadata included, is kept.
+    pub fn waypoints_only(&self) -> Gpx {
+        Gpx {
+            waypoints: self.waypoints.clone(),
+            tracks: Vec::new(),
+            routes: Vec::new(),
+            version: self.version,
+            declared_version: self.declared_version.clone(),
+            creator: self.creator.clone(),
+            metadata: self.metadata.clone(),
+            namespace: self.namespace.clone(),
+            namespaces: self.namespaces.clone(),
+            schema_location: self.schema_location.clone(),
+            summary_extensions: self.summary_extensions.clone(),
+            processing_instructions: self.processing_instructions.clone(),
+        }
+    }
+
+    /// Counts the intervals between consecutive timed points of each track
+    /// segment, in buckets of whole seconds, to show how often the device
+    /// recorded a point: every second, every 5 seconds, or irregularly as
+    /// with "smart" recording.
+    ///
+    /// Intervals are rounded down to the second. Points without a time are
+    /// skipped, and intervals going back in time are not counted.
+    pub fn sampling_histogram(&self) -> BTreeMap<Duration, u32> {
+        let mut histogram = BTreeMap::new();
+        let segments = self.tracks.iter().flat_map(|track| track.segments.iter());
+        for segment in segments {
+            let times: Vec<DateTime<Utc>> =
+                segment.points.iter().filter_map(|wpt| wpt.time).collect();
+            for pair in times.windows(2) {
+                let interval = pair[1] - pair[0];
+                if interval >= Duration::zero() {
+                    let bucket = Duration::seconds(interval.num_seconds());
+                    *histogram.entry(bucket).or_insert(0) += 1;
+                }
+            }
+        }
+        histogram
+    }
+
+    /// Gives the number of distinct calendar days, in UTC, on which the
+    /// document has timed points, such as the days of a multi-day trip.
+    pub fn active_days(&self) -> usize {
+        self.active_days_in(&Utc)
+    }
+
+    /// Gives the number of distinct calendar days on which the document has
+    /// timed points, with days as in the given time zone, e.g.
+    /// `FixedOffset::east_opt(3600)` for UTC+1.
+    pub fn active_days_in<Tz: TimeZone>(&self, tz: &Tz) -> usize {
+        self.points()
+            .filter_map(|wpt| wpt.time)
+            .map(|time| time.with_timezone(tz).date_naive())
+            .collect::<HashSet<_>>()
+            .len()
+    }
+
+    /// Builds a spatial index of all track points, for finding those near a
+    /// position quickly, such as under the cursor of an interactive map. The
+    /// index is meant to be kept around for many queries. Needs the `rstar`
+    /// feature.
+    ///
+    /// ```
+    /// use gpx::{Gpx, Track, TrackSegment, Waypoint};
+    /// use geo_types::Point;
+    ///
+    /// let mut segment = TrackSegment::new();
+    /// segment.points.push(Waypoint::new(Point::new(8.0, 47.0)));
+    /// segment.points.push(Waypoint::new(Point::new(8.1, 47.0)));
+    /// let mut track = Track::new();
+    /// track.segments.push(segment);
+    /// let mut gpx = Gpx::default();
+    /// gpx.tracks.push(track);
+    ///
+    /// let index = gpx.build_index();
+    /// let nearest = index.nearest(Point::new(8.09, 47.0)).unwrap();
+    /// assert_eq!(nearest.point, 1);
+    /// ```
+    #[cfg(feature = "rstar")]
+    pub fn build_index(&self) -> crate::GpxIndex {
+        crate::GpxIndex::new(self)
+    }
+
+    /// Encodes the document in a compact binary format, such as to cache
+    /// parsed documents locally, since decoding it with `Gpx::from_bytes` is
+    /// much faster than parsing GPX again. The format is only meant to be
+    /// read back by the same version of this crate.
+    #[cfg(feature = "bincode")]
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        bincode::serialize(self).chain_err(|| "error while encoding GPX document")
+    }
+
+    /// Decodes a document encoded with `Gpx::to_bytes`.
+    ///
+    /// ```
+    /// use gpx::{Gpx, GpxVersion};
+    ///
+    /// let mut gpx: Gpx = Default::default();
+    /// gpx.version = GpxVersion::Gpx11;
+    ///
+    /// let bytes = gpx.to_bytes().unwrap();
+    /// assert_eq!(Gpx::from_bytes(&bytes).unwrap(), gpx);
+    /// ```
+    #[cfg(feature = "bincode")]
+    pub fn from_bytes(bytes: &[u8]) -> Result<Gpx> {
+        bincode::deserialize(bytes).chain_err(|| "error while decoding GPX document")
+    }
+
+    /// Gives every waypoint, track point and route point.
+    pub(crate) fn points(&self) -> impl Iterator<Item = &Waypoint> {
+        let tracks = self
+            .tracks
+            .iter()
+            .flat_map(|track| track.segments.iter())
+            .flat_map(|seg| seg.points.iter());
+        let routes = self.routes.iter().flat_map(|route| route.points.iter());
+        self.waypoints.iter().chain(tracks).chain(routes)
+    }
+
+    /// Gives mutable access to every waypoint, track point and route point.
+    fn points_mut(&mut self) -> impl Iterator<Item = &mut Waypoint> {
+        let tracks = self
+            .tracks
+            .iter_mut()
+            .flat_map(|track| track.segments.iter_mut())
+            .flat_map(|seg| seg.points.iter_mut());
+        let routes = self
+            .routes
+            .iter_mut()
+            .flat_map(|route| route.points.iter_mut());
+        self.waypoints.iter_mut().chain(tracks).chain(routes)
+    }
+}
+
+/// GpxEvent is a piece of a GPX document, as given one at a time by
+/// `read_streaming`. The pieces come in the order of the document.
+#[derive(Clone, Debug, PartialEq)]
+pub enum GpxEvent {
+    /// The root element, before anything else: a `Gpx` with the version,
+    /// creator, namespace and processing instructions of the document, but
+    /// no content.
+    Start(Gpx),
+    /// The metadata of the document.
+    Metadata(Metadata),
+    /// A waypoint.
+    Waypoint(Waypoint),
+    /// The start of a track, with its name and other details, but without
+    /// its segments.
+    TrackStart(Track),
+    /// The start of a segment of the last track started.
+    TrackSegmentStart,
+    /// A point of the last track segment started.
+    TrackPoint(Waypoint),
+    /// The start of a route, with its name and other details, but without its
+    /// points.
+    RouteStart(Route),
+    /// A point of the last route started.
+    RoutePoint(Waypoint),
+    /// The extensions of the root element.
+    Extensions(Extensions),
 }
 
 /// Metadata is information about the GPX file, author, and copyright restrictions.
@@ -44,6 +481,7 @@ pub struct Gpx {
 /// Providing rich, meaningful information about your GPX files allows others to
 /// search for and use your GPS data.
 #[derive(Clone, Default, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Metadata {
     /// The name of the GPX file.
     pub name: Option<String>,
@@ -63,7 +501,10 @@ pub struct Metadata {
     /// Keywords associated with the file. Search engines or databases can use
     /// this information to classify the data.
     pub keywords: Option<String>,
-    /*copyright: GpxCopyrightType,*/
+
+    /// Copyright holder and license of the file.
+    pub copyright: Option<Copyright>,
+
     /// Bounds for the tracks in the GPX.
     pub bounds: Option<Rect<f64>>,
     /*extensions: GpxExtensionsType,*/
@@ -71,6 +512,7 @@ pub struct Metadata {
 
 /// Route represents an ordered list of waypoints representing a series of turn points leading to a destination.
 #[derive(Clone, Default, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Route {
     /// GPS name of route.
     pub name: Option<String>,
@@ -97,6 +539,10 @@ pub struct Route {
     /// Each Waypoint holds the coordinates, elevation, timestamp, and metadata
     /// for a single point in a track.
     pub points: Vec<Waypoint>,
+
+    /// Extensions of the route, as written by the application that created
+    /// it.
+    pub extensions: Option<Extensions>,
 }
 
 impl Route {
@@ -125,6 +571,51 @@ impl Route {
     pub fn new() -> Route {
         Default::default()
     }
+
+    /// Estimates the time it takes to follow the route, such as to plan a
+    /// hike, with `speed_model` giving the speed (in meters per second) for
+    /// a grade, the rise over the run (e.g. `0.1` for 10% uphill).
+    ///
+    /// The speed is taken for the grade of each leg between consecutive
+    /// points, which is flat when a point lacks elevation. Legs for which the
+    /// model gives no positive speed are left out.
+    ///
+    /// ```
+    /// use chrono::Duration;
+    /// use geo_types::Point;
+    /// use gpx::{Route, Waypoint};
+    ///
+    /// let mut route = Route::new();
+    /// route.points.push(Waypoint::new(Point::new(8.0, 47.0)));
+    /// route.points.push(Waypoint::new(Point::new(8.0, 47.01)));
+    ///
+    /// // About 1112 m at 1 m/s.
+    /// let duration = route.estimate_duration(|_| 1.0);
+    /// assert_eq!(duration.num_minutes(), 18);
+    /// ```
+    pub fn estimate_duration(&self, speed_model: impl Fn(f64) -> f64) -> Duration {
+        let seconds: f64 = self
+            .points
+            .windows(2)
+            .map(|pair| {
+                let distance = pair[0].point().haversine_distance(&pair[1].point());
+                if distance <= 0.0 {
+                    return 0.0;
+                }
+                let grade = match (pair[0].elevation, pair[1].elevation) {
+                    (Some(from), Some(to)) => (to - from) / distance,
+                    _ => 0.0,
+                };
+                let speed = speed_model(grade);
+                if speed > 0.0 && speed.is_finite() {
+                    distance / speed
+                } else {
+                    0.0
+                }
+            })
+            .sum();
+        Duration::milliseconds((seconds * 1000.0).round() as i64)
+    }
 }
 
 impl From<Route> for Geometry<f64> {
@@ -135,6 +626,7 @@ impl From<Route> for Geometry<f64> {
 
 /// Track represents an ordered list of points describing a path.
 #[derive(Clone, Default, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Track {
     /// GPS name of track.
     pub name: Option<String>,
@@ -160,9 +652,11 @@ pub struct Track {
     /// was lost, or the GPS receiver was turned off, start a new Track Segment
     /// for each continuous span of track data.
     pub segments: Vec<TrackSegment>,
+
+    /// Extensions of the track, as written by the application that created
+    /// it.
+    pub extensions: Option<Extensions>,
     /* pub number: u8,*/
-    /* extensions */
-    /* trkSeg */
 }
 
 impl Track {
@@ -184,6 +678,772 @@ impl Track {
     pub fn new() -> Track {
         Default::default()
     }
+
+    /// Gives the position of the first point of the track, or `None` if it
+    /// has no points. Empty segments are skipped.
+    pub fn start_point(&self) -> Option<Point<f64>> {
+        self.points().next().map(Waypoint::point)
+    }
+
+    /// Gives the position of the last point of the track, or `None` if it
+    /// has no points. Empty segments are skipped.
+    pub fn end_point(&self) -> Option<Point<f64>> {
+        self.segments
+            .iter()
+            .rev()
+            .find_map(|seg| seg.points.last())
+            .map(Waypoint::point)
+    }
+
+    /// Gives the fraction of the elapsed time that was spent moving faster
+    /// than `min_speed` (in meters per second).
+    ///
+    /// The elapsed time runs from the first to the last timed point of the
+    /// track, so pauses between segments count as not moving. Returns `None`
+    /// if the track has fewer than two timed points.
+    ///
+    /// ```
+    /// use gpx::Track;
+    ///
+    /// let track = Track::new();
+    /// assert_eq!(track.moving_ratio(0.5), None);
+    /// ```
+    pub fn moving_ratio(&self, min_speed: f64) -> Option<f64> {
+        let elapsed = self.elapsed_time()?;
+        if elapsed <= Duration::zero() {
+            return None;
+        }
+        let moving = self.segments.iter().fold(Duration::zero(), |total, seg| {
+            total + seg.moving_time(min_speed)
+        });
+        Some(moving.num_milliseconds() as f64 / elapsed.num_milliseconds() as f64)
+    }
+
+    /// Splits the track into consecutive stretches of `distance` meters each,
+    /// such as the 1 km splits of a run, and gives the time taken for each.
+    ///
+    /// Split boundaries are interpolated between track points. The last split
+    /// is shorter unless the track length is a multiple of `distance`. Only
+    /// consecutive timed points within a segment count: stretches without
+    /// timestamps and gaps between segments are left out. Returns no splits if
+    /// `distance` is not positive.
+    pub fn splits(&self, distance: f64) -> Vec<Split> {
+        let mut splits = Vec::new();
+        if distance <= 0.0 {
+            return splits;
+        }
+        // Distance and time covered since the end of the last split.
+        let mut covered = 0.0;
+        let mut seconds = 0.0;
+        for segment in &self.segments {
+            for pair in segment.points.windows(2) {
+                let elapsed = match (pair[0].time, pair[1].time) {
+                    (Some(start), Some(end)) => end - start,
+                    _ => continue,
+                };
+                let mut step = pair[0].point().haversine_distance(&pair[1].point());
+                let mut step_seconds = elapsed.num_milliseconds() as f64 / 1000.0;
+                while covered + step >= distance {
+                    let fraction = (distance - covered) / step;
+                    let split_seconds = seconds + fraction * step_seconds;
+                    splits.push(Split::new(distance, split_seconds, distance));
+                    step -= distance - covered;
+                    step_seconds -= fraction * step_seconds;
+                    covered = 0.0;
+                    seconds = 0.0;
+                }
+                covered += step;
+                seconds += step_seconds;
+            }
+        }
+        if covered > 0.0 {
+            splits.push(Split::new(covered, seconds, distance));
+        }
+        splits
+    }
+
+    /// Gives the grade-adjusted pace of the track: the time per kilometer it
+    /// would have taken to run with the same effort on flat ground. This
+    /// makes the pace of hilly runs comparable to that of flat ones.
+    ///
+    /// Each stretch between consecutive points is weighted by the energy cost
+    /// of running at its grade, relative to flat ground, following the model
+    /// of Minetti et al. (2002), "Energy cost of walking and running at
+    /// extreme uphill and downhill slopes". Grades are capped to the ±45%
+    /// the model was measured on. Only stretches between points with both
+    /// elevation and time count. Returns `None` if there are none.
+    pub fn graded_pace(&self) -> Option<Duration> {
+        // Energy cost (in J/kg/m) of running at the given grade.
+        let cost = |grade: f64| {
+            155.4 * grade.powi(5) - 30.4 * grade.powi(4) - 43.3 * grade.powi(3)
+                + 46.3 * grade.powi(2)
+                + 19.5 * grade
+                + 3.6
+        };
+
+        let mut seconds = 0.0;
+        let mut flat_distance = 0.0;
+        for (from, to) in self.segments.iter().flat_map(|seg| seg.point_pairs()) {
+            let (start, end, low, high) = match (
+                from.time,
+                to.time,
+                from.best_elevation(),
+                to.best_elevation(),
+            ) {
+                (Some(start), Some(end), Some(low), Some(high)) => (start, end, low, high),
+                _ => continue,
+            };
+            let distance = from.point().haversine_distance(&to.point());
+            if distance <= 0.0 {
+                continue;
+            }
+            let grade = ((high - low) / distance).clamp(-0.45, 0.45);
+            flat_distance += distance * cost(grade) / cost(0.0);
+            seconds += (end - start).num_milliseconds() as f64 / 1000.0;
+        }
+
+        if flat_distance <= 0.0 {
+            return None;
+        }
+        let pace = seconds * 1000.0 / flat_distance;
+        Some(Duration::milliseconds((pace * 1000.0).round() as i64))
+    }
+
+    /// Finds where the track passes closest to `point`, the basic step of
+    /// matching outside positions to the track.
+    ///
+    /// Each stretch between consecutive points is treated as straight, and
+    /// projected on in a local flat approximation; distances are haversine
+    /// distances. Gaps between segments are not part of the track. Returns
+    /// `None` if the track has no points.
+    pub fn locate_point(&self, point: Point<f64>) -> Option<LinearReference> {
+        // Scales longitudes so that planar distance near `point` matches the
+        // ground distance.
+        let scale = point.lat().to_radians().cos();
+        let mut best: Option<LinearReference> = None;
+        let mut along = 0.0;
+
+        for segment in &self.segments {
+            let mut candidates = Vec::new();
+            if let [only] = segment.points.as_slice() {
+                candidates.push((only.point(), along));
+            }
+            for (from, to) in segment.point_pairs() {
+                let (a, b) = (from.point(), to.point());
+                let (dx, dy) = ((b.lng() - a.lng()) * scale, b.lat() - a.lat());
+                let (px, py) = ((point.lng() - a.lng()) * scale, point.lat() - a.lat());
+                let squared = dx * dx + dy * dy;
+                let fraction = if squared > 0.0 {
+                    ((px * dx + py * dy) / squared).clamp(0.0, 1.0)
+                } else {
+                    0.0
+                };
+                let projected = Point::new(
+                    a.lng() + fraction * (b.lng() - a.lng()),
+                    a.lat() + fraction * (b.lat() - a.lat()),
+                );
+                let length = a.haversine_distance(&b);
+                candidates.push((projected, along + fraction * length));
+                along += length;
+            }
+
+            for (projected, distance) in candidates {
+                let offset = point.haversine_distance(&projected);
+                if !matches!(best, Some(ref best) if best.offset <= offset) {
+                    best = Some(LinearReference {
+                        distance,
+                        offset,
+                        point: projected,
+                    });
+                }
+            }
+        }
+        best
+    }
+
+    /// Gives how directly the track went from its start to its end: the
+    /// straight-line distance between them divided by the length of the path.
+    /// This is 1 for a straight track and approaches 0 for one that wanders
+    /// about or returns to its start.
+    ///
+    /// Gaps between segments do not count towards the path. Returns `None` if
+    /// the path has no length.
+    pub fn straightness(&self) -> Option<f64> {
+        let length = self.multilinestring().haversine_length();
+        if length <= 0.0 {
+            return None;
+        }
+        let start = self.points().next()?.point();
+        let end = self.points().last()?.point();
+        Some(start.haversine_distance(&end) / length)
+    }
+
+    /// Finds the sustained climbs of the track that gain at least `min_gain`
+    /// meters at an average grade of at least `min_grade` (as a fraction,
+    /// e.g. 0.03 for 3%), like cycling apps flag categorized climbs.
+    ///
+    /// A climb runs from a low point to the following high point, and ends
+    /// once the track descends more than `CLIMB_TOLERANCE` meters from its
+    /// high point, so that short dips do not split it. Points without
+    /// elevation are skipped, and gaps between segments add no length.
+    pub fn categorized_climbs(&self, min_gain: f64, min_grade: f64) -> Vec<Climb> {
+        // Distance along the track, elevation and position of each point.
+        let mut profile: Vec<(f64, f64, Point<f64>)> = Vec::new();
+        let mut along = 0.0;
+        for segment in &self.segments {
+            let mut previous: Option<Point<f64>> = None;
+            for wpt in &segment.points {
+                if let Some(previous) = previous {
+                    along += previous.haversine_distance(&wpt.point());
+                }
+                previous = Some(wpt.point());
+                if let Some(elevation) = wpt.best_elevation() {
+                    profile.push((along, elevation, wpt.point()));
+                }
+            }
+        }
+
+        let mut climbs = Vec::new();
+        let mut finish = |start: usize, peak: usize| {
+            let (start_distance, low, start) = profile[start];
+            let (end_distance, high, end) = profile[peak];
+            let (gain, length) = (high - low, end_distance - start_distance);
+            if length > 0.0 && gain >= min_gain && gain / length >= min_grade {
+                climbs.push(Climb {
+                    start,
+                    end,
+                    gain,
+                    length,
+                    grade: gain / length,
+                });
+            }
+        };
+
+        let (mut start, mut peak) = (0, 0);
+        for i in 1..profile.len() {
+            let elevation = profile[i].1;
+            if elevation > profile[peak].1 {
+                peak = i;
+            } else if profile[peak].1 - elevation > CLIMB_TOLERANCE {
+                finish(start, peak);
+                start = i;
+                peak = i;
+            }
+            if elevation < profile[start].1 {
+                start = i;
+                peak = i;
+            }
+        }
+        if !profile.is_empty() {
+            finish(start, peak);
+        }
+        climbs
+    }
+
+    /// Gives the track as a GeoJSON feature for showing it on a web map, with
+    /// its line simplified to a handful of points by the Ramer–Douglas–Peucker
+    /// algorithm. Points closer than `epsilon` (in degrees) to the simplified
+    /// line are left out.
+    ///
+    /// The segments are joined into a single LineString, and the name of the
+    /// track becomes the `name` property. Needs the `geojson` feature.
+    #[cfg(feature = "geojson")]
+    pub fn to_simplified_geojson(&self, epsilon: f64) -> geojson::Feature {
+        let line: LineString<f64> = self.points().map(|wpt| wpt.point()).collect();
+        let coordinates = line
+            .simplify(&epsilon)
+            .0
+            .iter()
+            .map(|c| vec![c.x, c.y])
+            .collect();
+        let mut feature = geojson::Feature {
+            geometry: Some(geojson::Geometry::new(geojson::Value::LineString(
+                coordinates,
+            ))),
+            ..Default::default()
+        };
+        if let Some(ref name) = self.name {
+            feature.set_property("name", name.as_str());
+        }
+        feature
+    }
+
+    /// Finds the laps of a track that goes round the same circuit, such as on
+    /// a running track: the indices of the points where it comes back within
+    /// `radius` meters of its first point. Indices count the points of all
+    /// segments in order.
+    ///
+    /// A lap only ends after the track has gone farther than twice `radius`
+    /// from the start, so that lingering around the start is not counted as
+    /// laps. Of the points of each pass within `radius`, the one closest to
+    /// the start is taken.
+    pub fn detect_laps(&self, radius: f64) -> Vec<usize> {
+        let start = match self.points().next() {
+            Some(start) => start.point(),
+            None => return Vec::new(),
+        };
+        let mut laps = Vec::new();
+        let mut away = false;
+        let mut closest: Option<(usize, f64)> = None;
+        for (i, wpt) in self.points().enumerate() {
+            let distance = start.haversine_distance(&wpt.point());
+            if distance <= radius {
+                if away && closest.is_none_or(|(_, min)| distance < min) {
+                    closest = Some((i, distance));
+                }
+                continue;
+            }
+            if let Some((lap, _)) = closest.take() {
+                laps.push(lap);
+                away = false;
+            }
+            if distance > 2.0 * radius {
+                away = true;
+            }
+        }
+        laps.extend(closest.map(|(lap, _)| lap));
+        laps
+    }
+
+    /// Gives the distance (in meters) between every point of this track and
+    /// every point of `other`, with a row for each point of this track and a
+    /// column for each point of `other`, such as to compare tracks with
+    /// dynamic time warping or the Fréchet distance.
+    ///
+    /// The matrix takes memory in proportion to the product of the numbers
+    /// of points: two tracks of 10,000 points each give 100 million
+    /// distances, or 800 MB. Simplify larger tracks before comparing them.
+    pub fn distance_matrix(&self, other: &Track) -> Vec<Vec<f64>> {
+        let others: Vec<Point<f64>> = other.points().map(Waypoint::point).collect();
+        self.points()
+            .map(|wpt| {
+                let point = wpt.point();
+                others
+                    .iter()
+                    .map(|other| point.haversine_distance(other))
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// Checks whether all points of the track are within `radius` meters of
+    /// their centroid, as happens for indoor activities like treadmill runs,
+    /// where the GPS position barely changes. A track without points is
+    /// considered stationary.
+    pub fn is_stationary(&self, radius: f64) -> bool {
+        self.bounding_circle()
+            .is_none_or(|(_, max_distance)| max_distance <= radius)
+    }
+
+    /// Gives a circle covering all points of the track, as its center and
+    /// radius (in meters), such as for proximity queries or to zoom a map to
+    /// the track. The center is the centroid of the points, so the circle is
+    /// not always the smallest one. Returns `None` if the track has no points.
+    pub fn bounding_circle(&self) -> Option<(Point<f64>, f64)> {
+        let points: Vec<Point<f64>> = self.points().map(|wpt| wpt.point()).collect();
+        if points.is_empty() {
+            return None;
+        }
+        let count = points.len() as f64;
+        let center = Point::new(
+            points.iter().map(|p| p.lng()).sum::<f64>() / count,
+            points.iter().map(|p| p.lat()).sum::<f64>() / count,
+        );
+        let radius = points
+            .iter()
+            .map(|p| center.haversine_distance(p))
+            .fold(0.0, f64::max);
+        Some((center, radius))
+    }
+
+    /// Gives the smallest rectangle, at any rotation, that contains all points
+    /// of the track. For a track running diagonally this frames the points
+    /// much more tightly than the axis-aligned bounds.
+    ///
+    /// Like the rest of geo, this works on longitude and latitude as planar
+    /// coordinates. The polygon is empty if the track has no points.
+    pub fn min_area_rect(&self) -> Polygon<f64> {
+        let hull = self.multilinestring().convex_hull();
+        let coords = &hull.exterior().0;
+
+        // The smallest rectangle has a side on one of the edges of the convex
+        // hull, so try the orientation of each edge in turn.
+        let mut best: Option<(f64, [Coordinate<f64>; 4])> = None;
+        for edge in coords.windows(2) {
+            let (dx, dy) = (edge[1].x - edge[0].x, edge[1].y - edge[0].y);
+            let length = dx.hypot(dy);
+            if length == 0.0 {
+                continue;
+            }
+            let (ux, uy) = (dx / length, dy / length);
+
+            let (mut min_u, mut max_u) = (f64::INFINITY, f64::NEG_INFINITY);
+            let (mut min_v, mut max_v) = (f64::INFINITY, f64::NEG_INFINITY);
+            for c in coords {
+                let u = c.x * ux + c.y * uy;
+                let v = c.y * ux - c.x * uy;
+                min_u = min_u.min(u);
+                max_u = max_u.max(u);
+                min_v = min_v.min(v);
+                max_v = max_v.max(v);
+            }
+
+            let area = (max_u - min_u) * (max_v - min_v);
+            if !matches!(best, Some((min_area, _)) if min_area <= area) {
+                let corner = |u: f64, v: f64| Coordinate {
+                    x: u * ux - v * uy,
+                    y: u * uy + v * ux,
+                };
+                let corners = [
+                    corner(min_u, min_v),
+                    corner(max_u, min_v),
+                    corner(max_u, max_v),
+                    corner(min_u, max_v),
+                ];
+                best = Some((area, corners));
+            }
+        }
+
+        match best {
+            Some((_, corners)) => {
+                let mut exterior = corners.to_vec();
+                exterior.push(corners[0]);
+                Polygon::new(LineString(exterior), vec![])
+            }
+            // A single point, repeated or not, is its own rectangle.
+            None => match coords.first() {
+                Some(&c) => Polygon::new(LineString(vec![c; 5]), vec![]),
+                None => Polygon::new(LineString(vec![]), vec![]),
+            },
+        }
+    }
+
+    /// Gives the predominant direction of travel of the track, as a bearing
+    /// in degrees clockwise from north in [0, 360), such as to tell the
+    /// morning commute from the evening one.
+    ///
+    /// This is the circular mean of the initial bearings of the stretches
+    /// between consecutive points, weighted by their length, so that bearings
+    /// on either side of north average to north rather than south. Returns
+    /// `None` if the track has no length.
+    pub fn predominant_bearing(&self) -> Option<f64> {
+        let (mut east, mut north) = (0.0, 0.0);
+        for (from, to) in self.segments.iter().flat_map(|seg| seg.point_pairs()) {
+            let (a, b) = (from.point(), to.point());
+            let length = a.haversine_distance(&b);
+            if length <= 0.0 {
+                continue;
+            }
+            let (lat1, lat2) = (a.lat().to_radians(), b.lat().to_radians());
+            let delta = (b.lng() - a.lng()).to_radians();
+            let bearing = (delta.sin() * lat2.cos())
+                .atan2(lat1.cos() * lat2.sin() - lat1.sin() * lat2.cos() * delta.cos());
+            east += length * bearing.sin();
+            north += length * bearing.cos();
+        }
+        if east == 0.0 && north == 0.0 {
+            return None;
+        }
+        Some(east.atan2(north).to_degrees().rem_euclid(360.0))
+    }
+
+    /// Gives the jumps between the segments of the track, such as to find
+    /// where a merged track teleports between recordings: for each segment,
+    /// the index of the segment and the distance (in meters) from the end of
+    /// the segment before it to its start. Segments without points are
+    /// skipped.
+    pub fn segment_gaps(&self) -> Vec<(usize, f64)> {
+        let mut gaps = Vec::new();
+        let mut previous_end: Option<Point<f64>> = None;
+        for (i, segment) in self.segments.iter().enumerate() {
+            let (first, last) = match (segment.points.first(), segment.points.last()) {
+                (Some(first), Some(last)) => (first.point(), last.point()),
+                _ => continue,
+            };
+            if let Some(end) = previous_end {
+                gaps.push((i, end.haversine_distance(&first)));
+            }
+            previous_end = Some(last);
+        }
+        gaps
+    }
+
+    /// Gives the lowest and highest elevation of the points of the track, in
+    /// that order, such as to scale the axis of an elevation profile. Points
+    /// without an elevation are skipped. Returns `None` if no point has one.
+    pub fn elevation_range(&self) -> Option<(f64, f64)> {
+        self.points()
+            .filter_map(|wpt| wpt.elevation)
+            .fold(None, |range, elevation| match range {
+                Some((min, max)) => Some((f64::min(min, elevation), f64::max(max, elevation))),
+                None => Some((elevation, elevation)),
+            })
+    }
+
+    /// Gives the moving time spent at each elevation, in bands of
+    /// `band_size` meters, such as for altitude training. Band `n` covers the
+    /// elevations from `n * band_size` up to `(n + 1) * band_size`, so bands
+    /// below sea level are negative.
+    ///
+    /// Each stretch between consecutive points counts towards the band of its
+    /// average elevation, if it was moving faster than `MIN_MOVING_SPEED`.
+    /// Only stretches between points with both elevation and time count, the
+    /// elevation being the one given by `Waypoint::best_elevation`. Returns no
+    /// bands if `band_size` is not positive.
+    pub fn time_in_elevation_bands(&self, band_size: f64) -> BTreeMap<i64, Duration> {
+        let mut bands = BTreeMap::new();
+        if band_size <= 0.0 {
+            return bands;
+        }
+        for (from, to) in self.segments.iter().flat_map(|seg| seg.point_pairs()) {
+            let elevations = (from.best_elevation(), to.best_elevation());
+            let (start, end, low, high) = match (from.time, to.time, elevations) {
+                (Some(start), Some(end), (Some(low), Some(high))) if end > start => {
+                    (start, end, low, high)
+                }
+                _ => continue,
+            };
+            let elapsed = end - start;
+            let distance = from.point().haversine_distance(&to.point());
+            if distance / (elapsed.num_milliseconds() as f64 / 1000.0) < MIN_MOVING_SPEED {
+                continue;
+            }
+            let band = ((low + high) / 2.0 / band_size).floor() as i64;
+            *bands.entry(band).or_insert_with(Duration::zero) += elapsed;
+        }
+        bands
+    }
+
+    /// Gives the moving time spent in each heart rate zone, such as for
+    /// training by intensity. The zones are split at the heart rates of
+    /// `bounds`, given in increasing order, so there is one zone more than
+    /// bounds: zone 0 is below the first bound, and zone `n` goes from bound
+    /// `n - 1` up to bound `n`.
+    ///
+    /// Each stretch between consecutive points counts towards the zone of its
+    /// average heart rate, from the `TrackPointExtension` of the points, if it
+    /// was moving faster than `MIN_MOVING_SPEED`. Only stretches between
+    /// points with both heart rate and time count.
+    pub fn hr_zones(&self, bounds: &[u8]) -> Vec<Duration> {
+        let mut zones = vec![Duration::zero(); bounds.len() + 1];
+        for (from, to) in self.segments.iter().flat_map(|seg| seg.point_pairs()) {
+            let hr = |wpt: &Waypoint| wpt.track_point_extension.as_ref()?.hr;
+            let (start, end, low, high) = match (from.time, to.time, hr(from), hr(to)) {
+                (Some(start), Some(end), Some(low), Some(high)) if end > start => {
+                    (start, end, low, high)
+                }
+                _ => continue,
+            };
+            let elapsed = end - start;
+            let distance = from.point().haversine_distance(&to.point());
+            if distance / (elapsed.num_milliseconds() as f64 / 1000.0) < MIN_MOVING_SPEED {
+                continue;
+            }
+            let average = (f64::from(low) + f64::from(high)) / 2.0;
+            let zone = bounds
+                .iter()
+                .take_while(|&&bound| average >= f64::from(bound))
+                .count();
+            zones[zone] += elapsed;
+        }
+        zones
+    }
+
+    /// Gives the fraction of the points of the track with each type of fix,
+    /// such as to tell how good the GPS reception was. Points without a fix
+    /// are counted under `None`, as their fix is unknown, while
+    /// `Some(Fix::None)` counts the points known to have had no fix. The
+    /// fractions add up to 1, and a track without points gives no fractions.
+    pub fn fix_distribution(&self) -> HashMap<Option<Fix>, f64> {
+        let mut counts: HashMap<Option<Fix>, usize> = HashMap::new();
+        for wpt in self.points() {
+            *counts.entry(wpt.fix.clone()).or_insert(0) += 1;
+        }
+        let total: usize = counts.values().sum();
+        counts
+            .into_iter()
+            .map(|(fix, count)| (fix, count as f64 / total as f64))
+            .collect()
+    }
+
+    /// Gives the median time between consecutive timed points of each
+    /// segment, such as to tell how often the device recorded a point.
+    /// Unlike the mean, pauses in the recording barely change it.
+    ///
+    /// Points without a time are skipped, and intervals going back in time
+    /// are not counted. Returns `None` if there are no intervals.
+    pub fn median_sample_interval(&self) -> Option<Duration> {
+        let mut intervals: Vec<Duration> = self
+            .segments
+            .iter()
+            .flat_map(|segment| {
+                let times: Vec<DateTime<Utc>> =
+                    segment.points.iter().filter_map(|wpt| wpt.time).collect();
+                times
+                    .windows(2)
+                    .map(|pair| pair[1] - pair[0])
+                    .collect::<Vec<Duration>>()
+            })
+            .filter(|interval| *interval >= Duration::zero())
+            .collect();
+        if intervals.is_empty() {
+            return None;
+        }
+        intervals.sort();
+        let middle = intervals.len() / 2;
+        if intervals.len() % 2 == 1 {
+            Some(intervals[middle])
+        } else {
+            Some((intervals[middle - 1] + intervals[middle]) / 2)
+        }
+    }
+
+    /// Merges the segments with fewer than `min_points` points into the
+    /// segment before them, or after them for the first one, such as to
+    /// clean up the tiny segments left by splitting a track at its gaps. The
+    /// points keep their order.
+    ///
+    /// Only a track whose points all add up to fewer than `min_points` is
+    /// left with a short segment, which is then its only one.
+    pub fn merge_short_segments(&mut self, min_points: usize) {
+        let mut merged: Vec<TrackSegment> = Vec::with_capacity(self.segments.len());
+        for segment in self.segments.drain(..) {
+            match merged.last_mut() {
+                Some(last)
+                    if last.points.len() < min_points || segment.points.len() < min_points =>
+                {
+                    last.points.extend(segment.points)
+                }
+                _ => merged.push(segment),
+            }
+        }
+        self.segments = merged;
+    }
+
+    /// Gives the part of the track recorded between `start` and `end`, such
+    /// as a single lap of a run.
+    ///
+    /// Points outside the time window are dropped, and points are
+    /// interpolated where the track crosses `start` and `end`, so that the
+    /// clipped track begins and ends exactly on them. Points without a time
+    /// are dropped too, and segments left without points are removed.
+    pub fn clip_time(&self, start: DateTime<Utc>, end: DateTime<Utc>) -> Track {
+        let mut clipped = Track {
+            segments: Vec::new(),
+            ..self.clone()
+        };
+        for segment in &self.segments {
+            let timed: Vec<&Waypoint> = segment
+                .points
+                .iter()
+                .filter(|wpt| wpt.time.is_some())
+                .collect();
+            let mut points = Vec::new();
+            for (i, wpt) in timed.iter().enumerate() {
+                let time = wpt.time.unwrap();
+                if let Some(prev) = i.checked_sub(1).map(|j| timed[j]) {
+                    let prev_time = prev.time.unwrap();
+                    if prev_time < start && time > start {
+                        points.push(prev.interpolate(wpt, start));
+                    }
+                    if prev_time < end && time > end {
+                        points.push(prev.interpolate(wpt, end));
+                    }
+                }
+                if start <= time && time <= end {
+                    points.push((*wpt).clone());
+                }
+            }
+            if !points.is_empty() {
+                clipped.segments.push(TrackSegment { points });
+            }
+        }
+        clipped
+    }
+
+    /// Gives the part of the track recorded within `max` of its first timed
+    /// point, such as the first hour of a long recording, with a point
+    /// interpolated where that time is reached. See `clip_time`, which this
+    /// uses: points without a time are dropped, so a track without any gives
+    /// no segments. A `max` reaching past the latest time a date can hold
+    /// keeps all the timed points.
+    pub fn clip_to_duration(&self, max: Duration) -> Track {
+        match self.points().find_map(|wpt| wpt.time) {
+            Some(start) => {
+                let end = start
+                    .checked_add_signed(max)
+                    .unwrap_or(if max < Duration::zero() {
+                        DateTime::<Utc>::MIN_UTC
+                    } else {
+                        DateTime::<Utc>::MAX_UTC
+                    });
+                self.clip_time(start, end)
+            }
+            None => Track {
+                segments: Vec::new(),
+                ..self.clone()
+            },
+        }
+    }
+
+    /// Estimates the energy (in kilocalories) spent over the track by someone
+    /// weighing `weight_kg` kilograms, such as for a fitness summary.
+    ///
+    /// This is a rough estimate, using the ACSM metabolic equation for
+    /// running: the oxygen taken up for resting, for the horizontal distance
+    /// and for the ascent, at 5 kilocalories per liter of oxygen. It does not
+    /// account for the terrain, the fitness of the athlete or descents.
+    /// Points without elevation add no ascent.
+    ///
+    /// Returns `None` if the track has no distance or no elapsed time.
+    pub fn estimate_calories(&self, weight_kg: f64) -> Option<f64> {
+        let distance: f64 = self
+            .segments
+            .iter()
+            .map(|seg| seg.linestring().haversine_length())
+            .sum();
+        let elapsed = self.elapsed_time()?;
+        if distance <= 0.0 || elapsed <= Duration::zero() {
+            return None;
+        }
+        let ascent: f64 = self
+            .segments
+            .iter()
+            .map(|seg| {
+                let elevations: Vec<f64> = seg
+                    .points
+                    .iter()
+                    .filter_map(|wpt| wpt.best_elevation())
+                    .collect();
+                elevations
+                    .windows(2)
+                    .map(|pair| (pair[1] - pair[0]).max(0.0))
+                    .sum::<f64>()
+            })
+            .sum();
+
+        // Oxygen uptake in milliliters per kilogram of body weight.
+        let minutes = elapsed.num_milliseconds() as f64 / 60_000.0;
+        let oxygen = 3.5 * minutes + 0.2 * distance + 0.9 * ascent;
+        Some(oxygen * weight_kg / 1000.0 * 5.0)
+    }
+
+    /// Gives the points of all segments, in order.
+    fn points(&self) -> impl Iterator<Item = &Waypoint> {
+        self.segments.iter().flat_map(|seg| seg.points.iter())
+    }
+
+    /// Gives the time between the first and the last timed point of the track.
+    pub(crate) fn elapsed_time(&self) -> Option<Duration> {
+        let mut times = self
+            .segments
+            .iter()
+            .flat_map(|seg| seg.points.iter())
+            .filter_map(|wpt| wpt.time);
+        let first = times.next()?;
+        let last = times.next_back()?;
+        Some(last - first)
+    }
 }
 
 impl From<Track> for Geometry<f64> {
@@ -192,6 +1452,87 @@ impl From<Track> for Geometry<f64> {
     }
 }
 
+/// Split is a stretch of a track, as given by `Track::splits`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Split {
+    /// Distance covered in the split, in meters.
+    pub distance: f64,
+
+    /// Time taken to cover the split.
+    pub elapsed: Duration,
+
+    /// Average pace over the split, as the time it takes to cover the split
+    /// distance that was asked for (e.g. time per kilometer for 1 km splits).
+    /// This makes a shorter last split comparable to the others.
+    pub pace: Duration,
+}
+
+impl Split {
+    fn new(distance: f64, seconds: f64, split_distance: f64) -> Split {
+        let to_duration = |seconds: f64| Duration::milliseconds((seconds * 1000.0).round() as i64);
+        Split {
+            distance,
+            elapsed: to_duration(seconds),
+            pace: to_duration(seconds * split_distance / distance),
+        }
+    }
+}
+
+/// The descent (in meters) from the high point of a climb that ends it, see
+/// `Track::categorized_climbs`.
+const CLIMB_TOLERANCE: f64 = 10.0;
+
+/// Climb is a sustained climb of a track, as given by
+/// `Track::categorized_climbs`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Climb {
+    /// The low point where the climb starts.
+    pub start: Point<f64>,
+
+    /// The high point where the climb ends.
+    pub end: Point<f64>,
+
+    /// Elevation gained (in meters) from start to end.
+    pub gain: f64,
+
+    /// Distance (in meters) along the track from start to end.
+    pub length: f64,
+
+    /// Average grade of the climb, as a fraction.
+    pub grade: f64,
+}
+
+/// LinearReference is a position along a track, as given by
+/// `Track::locate_point`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LinearReference {
+    /// Distance (in meters) along the track from its start.
+    pub distance: f64,
+
+    /// Distance (in meters) of the located point from the track.
+    pub offset: f64,
+
+    /// The point on the track closest to the located point.
+    pub point: Point<f64>,
+}
+
+/// Stop is a stretch of a track segment where little movement happened, as
+/// given by `TrackSegment::detect_stops`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Stop {
+    /// The centroid of the points of the stop.
+    pub center: Point<f64>,
+
+    /// Index of the first point of the stop in the segment.
+    pub start: usize,
+
+    /// Index of the last point of the stop in the segment.
+    pub end: usize,
+
+    /// Time between the first and the last point of the stop.
+    pub duration: Duration,
+}
+
 /// TrackSegment represents a list of track points.
 ///
 /// This TrackSegment holds a list of Track Points which are logically
@@ -199,6 +1540,7 @@ impl From<Track> for Geometry<f64> {
 /// was lost, or the GPS receiver was turned off, start a new Track Segment
 /// for each continuous span of track data.
 #[derive(Clone, Default, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct TrackSegment {
     /// Each Waypoint holds the coordinates, elevation, timestamp, and metadata
     /// for a single point in a track.
@@ -206,6 +1548,10 @@ pub struct TrackSegment {
     /* extensions */
 }
 
+/// The speed (in meters per second) below which a segment is considered to be
+/// standing still, for metrics that only count moving time.
+const MIN_MOVING_SPEED: f64 = 0.5;
+
 impl TrackSegment {
     /// Gives the linestring of the segment's points, the sequence of points that
     /// comprises the track segment.
@@ -231,6 +1577,517 @@ impl TrackSegment {
     pub fn new() -> TrackSegment {
         Default::default()
     }
+
+    /// Gives the point farthest away from the first point of the segment,
+    /// along with its haversine distance (in meters) from the first point.
+    ///
+    /// This is the turnaround of an out-and-back route. Returns `None` if the
+    /// segment has no points.
+    pub fn farthest_point(&self) -> Option<(&Waypoint, f64)> {
+        let start = self.points.first()?.point();
+        self.points
+            .iter()
+            .map(|wpt| (wpt, start.haversine_distance(&wpt.point())))
+            .fold(None, |farthest, (wpt, distance)| match farthest {
+                Some((_, max)) if max >= distance => farthest,
+                _ => Some((wpt, distance)),
+            })
+    }
+
+    /// Gives a new segment with the points from index `start` up to, but not
+    /// including, index `end`, such as to cut out a lap. The points are copied
+    /// with all their fields.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `start` is greater than `end`, or `end` is greater than the
+    /// number of points, like slicing a `Vec`.
+    pub fn slice(&self, start: usize, end: usize) -> TrackSegment {
+        assert!(
+            start <= end && end <= self.points.len(),
+            "range {}..{} out of bounds for a segment of {} points",
+            start,
+            end,
+            self.points.len()
+        );
+        TrackSegment {
+            points: self.points[start..end].to_vec(),
+        }
+    }
+
+    /// Gives a copy of the segment without the positions some devices repeat
+    /// while they lose the GPS signal: each run of more than `min_repeats`
+    /// consecutive points with the very same coordinates is cut down to its
+    /// first point, the last actual fix. Shorter runs, such as from standing
+    /// still for a moment, are kept.
+    pub fn remove_frozen_points(&self, min_repeats: usize) -> TrackSegment {
+        let mut points = Vec::with_capacity(self.points.len());
+        let mut start = 0;
+        while start < self.points.len() {
+            let position = self.points[start].point();
+            let run = self.points[start..]
+                .iter()
+                .take_while(|wpt| wpt.point() == position)
+                .count();
+            if run > min_repeats {
+                points.push(self.points[start].clone());
+            } else {
+                points.extend_from_slice(&self.points[start..start + run]);
+            }
+            start += run;
+        }
+        TrackSegment { points }
+    }
+
+    /// Gives each pair of consecutive points of the segment, the building
+    /// block for computing distances, speeds or bearings between points.
+    ///
+    /// ```
+    /// extern crate geo;
+    /// extern crate geo_types;
+    /// extern crate gpx;
+    ///
+    /// use geo::algorithm::haversine_distance::HaversineDistance;
+    /// use geo_types::Point;
+    /// use gpx::{TrackSegment, Waypoint};
+    ///
+    /// fn main() {
+    ///     let mut trkseg = TrackSegment::new();
+    ///     trkseg.points.push(Waypoint::new(Point::new(8.0, 47.0)));
+    ///     trkseg.points.push(Waypoint::new(Point::new(8.0, 47.01)));
+    ///     trkseg.points.push(Waypoint::new(Point::new(8.0, 47.02)));
+    ///
+    ///     let longest_step = trkseg
+    ///         .point_pairs()
+    ///         .map(|(from, to)| from.point().haversine_distance(&to.point()))
+    ///         .fold(0.0, f64::max);
+    ///
+    ///     assert_eq!(trkseg.point_pairs().count(), 2);
+    ///     assert!(longest_step > 1000.0);
+    /// }
+    /// ```
+    pub fn point_pairs(&self) -> impl Iterator<Item = (&Waypoint, &Waypoint)> {
+        self.points.windows(2).map(|pair| (&pair[0], &pair[1]))
+    }
+
+    /// Gives the time since the start of the segment at each point, such as
+    /// to chart the segment against distance. The start is the time of the
+    /// first timed point, so that point is at zero. Points without a time
+    /// give `None`.
+    pub fn elapsed_times(&self) -> Vec<Option<Duration>> {
+        let start = self.points.iter().find_map(|wpt| wpt.time);
+        self.points
+            .iter()
+            .map(|wpt| Some(wpt.time? - start?))
+            .collect()
+    }
+
+    /// Gives the distance (in meters) in a straight line, over the Earth,
+    /// from the first point of the segment to each point, rather than along
+    /// the segment, such as to find where an out-and-back route turns
+    /// around: the point farthest from the start.
+    pub fn crow_fly_distances(&self) -> Vec<f64> {
+        let start = match self.points.first() {
+            Some(first) => first.point(),
+            None => return Vec::new(),
+        };
+        self.points
+            .iter()
+            .map(|wpt| start.haversine_distance(&wpt.point()))
+            .collect()
+    }
+
+    /// Gives the fraction of the length of the segment traveled at each
+    /// point, from 0 at the first point to 1 at the last, such as to fill a
+    /// progress bar or color the segment with a gradient. All points are at 0
+    /// if the segment has no length.
+    pub fn progress(&self) -> Vec<f64> {
+        let mut distance = 0.0;
+        let distances: Vec<f64> = self
+            .point_pairs()
+            .map(|(from, to)| {
+                distance += from.point().haversine_distance(&to.point());
+                distance
+            })
+            .collect();
+        let mut progress = Vec::with_capacity(self.points.len());
+        if !self.points.is_empty() {
+            progress.push(0.0);
+        }
+        progress.extend(
+            distances
+                .iter()
+                .map(|d| if distance > 0.0 { d / distance } else { 0.0 }),
+        );
+        progress
+    }
+
+    /// Gives a copy of the segment with points every `spacing` meters along
+    /// it, starting at its first point, such as to draw an elevation profile
+    /// against evenly spaced distances. The position, elevation and time of
+    /// each point are interpolated between the points around it, and the
+    /// other fields are left out.
+    ///
+    /// The end of the segment is left out unless it is a multiple of
+    /// `spacing` away from the start.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `spacing` is not positive.
+    pub fn resample_by_distance(&self, spacing: f64) -> TrackSegment {
+        assert!(spacing > 0.0, "resampling spacing must be positive");
+        let mut resampled = TrackSegment::new();
+        let first = match self.points.first() {
+            Some(first) => first,
+            None => return resampled,
+        };
+        resampled
+            .points
+            .push(first.interpolate_fraction(first, 0.0));
+
+        let mut traveled = 0.0;
+        for (from, to) in self.point_pairs() {
+            let length = from.point().haversine_distance(&to.point());
+            let mut next = spacing * resampled.points.len() as f64;
+            while length > 0.0 && next <= traveled + length {
+                let fraction = (next - traveled) / length;
+                resampled
+                    .points
+                    .push(from.interpolate_fraction(to, fraction));
+                next = spacing * resampled.points.len() as f64;
+            }
+            traveled += length;
+        }
+        resampled
+    }
+
+    /// Gives the position on the segment at each of `times`, such as to move
+    /// a marker along the segment while scrubbing through a playback. Points
+    /// are interpolated between the timed points around each time, and `None`
+    /// is given for times outside of the segment.
+    ///
+    /// The points of the segment are gone through once for all times, which
+    /// may be given in any order. Points without a time are skipped.
+    pub fn positions_at_times(&self, times: &[DateTime<Utc>]) -> Vec<Option<Waypoint>> {
+        let timed: Vec<&Waypoint> = self
+            .points
+            .iter()
+            .filter(|wpt| wpt.time.is_some())
+            .collect();
+        let mut order: Vec<usize> = (0..times.len()).collect();
+        order.sort_by_key(|&i| times[i]);
+
+        let mut positions = vec![None; times.len()];
+        let mut current = 0;
+        for i in order {
+            let time = times[i];
+            while current + 1 < timed.len() && timed[current + 1].time.unwrap() <= time {
+                current += 1;
+            }
+            let wpt = match timed.get(current) {
+                Some(wpt) => wpt,
+                None => break,
+            };
+            let wpt_time = wpt.time.unwrap();
+            positions[i] = if wpt_time == time {
+                Some((*wpt).clone())
+            } else if wpt_time < time {
+                timed
+                    .get(current + 1)
+                    .map(|next| wpt.interpolate(next, time))
+            } else {
+                None
+            };
+        }
+        positions
+    }
+
+    /// Gives the heart rate at `time`, interpolated between the points around
+    /// it, such as to line the segment up with the data of another sensor.
+    ///
+    /// Only points with both a time and a heart rate in their
+    /// `track_point_extension` count. Returns `None` if `time` is not between
+    /// two such points, or on one.
+    pub fn hr_at(&self, time: DateTime<Utc>) -> Option<u8> {
+        let samples: Vec<(DateTime<Utc>, u8)> = self
+            .points
+            .iter()
+            .filter_map(|wpt| Some((wpt.time?, wpt.track_point_extension.as_ref()?.hr?)))
+            .collect();
+        if let Some(&(_, hr)) = samples.iter().find(|&&(at, _)| at == time) {
+            return Some(hr);
+        }
+        samples.windows(2).find_map(|pair| {
+            let ((from, low), (to, high)) = (pair[0], pair[1]);
+            if !(from < time && time < to) {
+                return None;
+            }
+            let fraction =
+                (time - from).num_milliseconds() as f64 / (to - from).num_milliseconds() as f64;
+            let hr = f64::from(low) + (f64::from(high) - f64::from(low)) * fraction;
+            Some(hr.round() as u8)
+        })
+    }
+
+    /// Gives the speed (in meters per second) at each point averaged over a
+    /// time window of `window` centered on it, such as to chart a pace that
+    /// is not as noisy as the speed between each pair of points. The average
+    /// is the distance along the segment between the earliest and latest
+    /// points of the window over the time between them. The sign of `window`
+    /// is ignored.
+    ///
+    /// Points whose times go back are put in their place in time. Points
+    /// without a time give `None`, and so do those alone in their window
+    /// unless they have a recorded speed.
+    pub fn smoothed_speeds(&self, window: Duration) -> Vec<Option<f64>> {
+        let mut distances = Vec::with_capacity(self.points.len());
+        let mut distance = 0.0;
+        for (i, wpt) in self.points.iter().enumerate() {
+            if i > 0 {
+                distance += self.points[i - 1].point().haversine_distance(&wpt.point());
+            }
+            distances.push(distance);
+        }
+        let mut timed: Vec<(usize, DateTime<Utc>)> = self
+            .points
+            .iter()
+            .enumerate()
+            .filter_map(|(i, wpt)| Some((i, wpt.time?)))
+            .collect();
+        timed.sort_by_key(|&(_, at)| at);
+
+        let half = window.abs() / 2;
+        self.points
+            .iter()
+            .map(|wpt| {
+                let time = wpt.time?;
+                // A window reaching past the times a date can hold takes in
+                // all the points on that side.
+                let from = match time.checked_sub_signed(half) {
+                    Some(start) => timed.partition_point(|&(_, at)| at < start),
+                    None => 0,
+                };
+                let to = match time.checked_add_signed(half) {
+                    Some(end) => timed.partition_point(|&(_, at)| at <= end),
+                    None => timed.len(),
+                };
+                let (first, start) = timed[from];
+                let (last, end) = timed[to - 1];
+                let seconds = (end - start).num_milliseconds() as f64 / 1000.0;
+                if seconds <= 0.0 {
+                    return wpt.speed;
+                }
+                Some((distances[last] - distances[first]).abs() / seconds)
+            })
+            .collect()
+    }
+
+    /// Finds the stops of the segment, such as breaks taken on a trip: the
+    /// runs of consecutive points that stay within `radius` meters of their
+    /// first point for at least `min_duration`.
+    ///
+    /// Stops are found greedily from the start of the segment and do not
+    /// overlap. A stop starts and ends on timed points.
+    pub fn detect_stops(&self, radius: f64, min_duration: Duration) -> Vec<Stop> {
+        let mut stops = Vec::new();
+        let mut start = 0;
+        while start < self.points.len() {
+            let first = &self.points[start];
+            let anchor = first.point();
+            let within = self.points[start..]
+                .iter()
+                .take_while(|wpt| anchor.haversine_distance(&wpt.point()) <= radius)
+                .count();
+            // The stop ends at the last timed point within the radius.
+            let stop = first.time.and_then(|start_time| {
+                let (offset, end_time) = self.points[start..start + within]
+                    .iter()
+                    .enumerate()
+                    .rev()
+                    .find_map(|(offset, wpt)| Some((offset, wpt.time?)))?;
+                let duration = end_time - start_time;
+                if duration < min_duration {
+                    return None;
+                }
+                let points = &self.points[start..=start + offset];
+                let count = points.len() as f64;
+                Some(Stop {
+                    center: Point::new(
+                        points.iter().map(|wpt| wpt.point().lng()).sum::<f64>() / count,
+                        points.iter().map(|wpt| wpt.point().lat()).sum::<f64>() / count,
+                    ),
+                    start,
+                    end: start + offset,
+                    duration,
+                })
+            });
+            match stop {
+                Some(stop) => {
+                    start = stop.end + 1;
+                    stops.push(stop);
+                }
+                None => start += 1,
+            }
+        }
+        stops
+    }
+
+    /// Gives a color for each point of the segment according to its speed,
+    /// for drawing the speed along the segment: blue at `min_speed`, through
+    /// green, to red at `max_speed` (in meters per second). Colors are RGB
+    /// triples with components between 0 and 1, and speeds outside of the
+    /// range get the color of the nearest end.
+    ///
+    /// Points whose speed is unknown, because they lack a time, are colored
+    /// as `min_speed`.
+    pub fn speed_colors(&self, min_speed: f64, max_speed: f64) -> Vec<(f64, f64, f64)> {
+        self.point_speeds()
+            .into_iter()
+            .map(|speed| {
+                let range = max_speed - min_speed;
+                let fraction = match speed {
+                    Some(speed) if range > 0.0 => ((speed - min_speed) / range).clamp(0.0, 1.0),
+                    Some(speed) if speed > min_speed => 1.0,
+                    _ => 0.0,
+                };
+                if fraction < 0.5 {
+                    (0.0, fraction * 2.0, 1.0 - fraction * 2.0)
+                } else {
+                    (fraction * 2.0 - 1.0, 2.0 - fraction * 2.0, 0.0)
+                }
+            })
+            .collect()
+    }
+
+    /// Gives the speed (in meters per second) at each point: the recorded one
+    /// if there is, or else the one since the previous point, or towards the
+    /// next point for the first one.
+    fn point_speeds(&self) -> Vec<Option<f64>> {
+        let speed = |from: &Waypoint, to: &Waypoint| {
+            let seconds = (to.time? - from.time?).num_milliseconds() as f64 / 1000.0;
+            if seconds <= 0.0 {
+                return None;
+            }
+            Some(from.point().haversine_distance(&to.point()) / seconds)
+        };
+        (0..self.points.len())
+            .map(|i| {
+                let wpt = &self.points[i];
+                wpt.speed.or_else(|| match i {
+                    0 => self.points.get(1).and_then(|next| speed(wpt, next)),
+                    _ => speed(&self.points[i - 1], wpt),
+                })
+            })
+            .collect()
+    }
+
+    /// Gives the VAM (velocità ascensionale media) of the segment: the meters
+    /// of ascent per hour of moving time, where moving means going faster than
+    /// `MIN_MOVING_SPEED`.
+    ///
+    /// Returns `None` if the segment lacks the elevations or times to tell.
+    pub fn vam(&self) -> Option<f64> {
+        let elevations: Vec<f64> = self
+            .points
+            .iter()
+            .filter_map(|wpt| wpt.best_elevation())
+            .collect();
+        if elevations.len() < 2 {
+            return None;
+        }
+        let ascent: f64 = elevations
+            .windows(2)
+            .map(|pair| (pair[1] - pair[0]).max(0.0))
+            .sum();
+
+        let moving = self.moving_time(MIN_MOVING_SPEED);
+        if moving <= Duration::zero() {
+            return None;
+        }
+        let hours = moving.num_milliseconds() as f64 / 3_600_000.0;
+        Some(ascent / hours)
+    }
+
+    /// Picks the points of the segment that navigation needs, such as for
+    /// turning a dense recording into route points: the turns where the
+    /// segment deviates more than `max_deviation` meters from a straight line.
+    ///
+    /// The points are picked with the Ramer-Douglas-Peucker algorithm, so the
+    /// first and last points are always kept, and every dropped point is
+    /// within `max_deviation` of the line between the kept points around it.
+    pub fn to_waypoints(&self, max_deviation: f64) -> Vec<Waypoint> {
+        // Distance from `point` to the line from `a` to `b`, in a local flat
+        // approximation around `a`.
+        let deviation = |point: Point<f64>, a: Point<f64>, b: Point<f64>| {
+            let scale = a.lat().to_radians().cos();
+            let (dx, dy) = ((b.lng() - a.lng()) * scale, b.lat() - a.lat());
+            let (px, py) = ((point.lng() - a.lng()) * scale, point.lat() - a.lat());
+            let squared = dx * dx + dy * dy;
+            let fraction = if squared > 0.0 {
+                ((px * dx + py * dy) / squared).clamp(0.0, 1.0)
+            } else {
+                0.0
+            };
+            point.haversine_distance(&Point::new(
+                a.lng() + fraction * (b.lng() - a.lng()),
+                a.lat() + fraction * (b.lat() - a.lat()),
+            ))
+        };
+
+        let last = match self.points.len() {
+            0 => return Vec::new(),
+            len => len - 1,
+        };
+        let mut keep = vec![false; self.points.len()];
+        keep[0] = true;
+        keep[last] = true;
+        let mut stretches = vec![(0, last)];
+        while let Some((start, end)) = stretches.pop() {
+            let (a, b) = (self.points[start].point(), self.points[end].point());
+            let farthest = (start + 1..end)
+                .map(|i| (i, deviation(self.points[i].point(), a, b)))
+                .fold(
+                    None,
+                    |farthest: Option<(usize, f64)>, (i, distance)| match farthest {
+                        Some((_, max)) if max >= distance => farthest,
+                        _ => Some((i, distance)),
+                    },
+                );
+            if let Some((i, distance)) = farthest {
+                if distance > max_deviation {
+                    keep[i] = true;
+                    stretches.push((start, i));
+                    stretches.push((i, end));
+                }
+            }
+        }
+
+        self.points
+            .iter()
+            .zip(keep)
+            .filter(|(_, keep)| *keep)
+            .map(|(wpt, _)| wpt.clone())
+            .collect()
+    }
+
+    /// Gives the total time between consecutive timed points where the speed
+    /// was at least `min_speed` (in meters per second).
+    fn moving_time(&self, min_speed: f64) -> Duration {
+        let mut moving = Duration::zero();
+        for (from, to) in self.point_pairs() {
+            let (start, end) = match (from.time, to.time) {
+                (Some(start), Some(end)) if end > start => (start, end),
+                _ => continue,
+            };
+            let elapsed = end - start;
+            let distance = from.point().haversine_distance(&to.point());
+            let seconds = elapsed.num_milliseconds() as f64 / 1000.0;
+            if distance / seconds >= min_speed {
+                moving += elapsed;
+            }
+        }
+        moving
+    }
 }
 
 impl From<TrackSegment> for Geometry<f64> {
@@ -243,6 +2100,11 @@ impl From<TrackSegment> for Geometry<f64> {
 // allows us to initialise the GpxPoint with default values compactly
 // in the Waypoint::new function below
 #[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(from = "LatLon", into = "LatLon")
+)]
 struct GpxPoint(Point<f64>);
 
 impl Default for GpxPoint {
@@ -251,11 +2113,39 @@ impl Default for GpxPoint {
     }
 }
 
+// The serialized form of a GpxPoint, `{ "lat": .., "lon": .. }` like the
+// attributes of a GPX point, rather than the `(x, y)` of geo_types.
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct LatLon {
+    lat: f64,
+    lon: f64,
+}
+
+#[cfg(feature = "serde")]
+impl From<LatLon> for GpxPoint {
+    fn from(point: LatLon) -> GpxPoint {
+        GpxPoint(Point::new(point.lon, point.lat))
+    }
+}
+
+#[cfg(feature = "serde")]
+impl From<GpxPoint> for LatLon {
+    fn from(point: GpxPoint) -> LatLon {
+        LatLon {
+            lat: point.0.lat(),
+            lon: point.0.lng(),
+        }
+    }
+}
+
 /// Waypoint represents a waypoint, point of interest, or named feature on a
 /// map.
 #[derive(Clone, Default, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Waypoint {
-    /// The geographical point.
+    /// The geographical point. Like everywhere in geo, the longitude is its
+    /// x coordinate and the latitude its y coordinate.
     point: GpxPoint,
 
     /// Elevation (in meters) of the point.
@@ -264,6 +2154,14 @@ pub struct Waypoint {
     /// Speed (in meters per second) (only in GPX 1.0)
     pub speed: Option<f64>,
 
+    /// Course (in degrees clockwise from true north) (only in GPX 1.0)
+    ///
+    /// When reading, points without a `<course>` element get the course
+    /// stored in their extensions by many trackers, as a `course`, `heading`
+    /// or `bearing` element of any namespace. The `<course>` element takes
+    /// precedence when there are both.
+    pub course: Option<f64>,
+
     /// Creation/modification timestamp for element. Date and time in are in
     /// Univeral Coordinated Time (UTC), not local time! Conforms to ISO 8601
     /// specification for date/time representation. Fractional seconds are
@@ -328,9 +2226,25 @@ pub struct Waypoint {
 
     /// ID of DGPS station used in differential correction, in the range [0, 1023].
     pub dgpsid: Option<u16>,
-    // <extensions> extensionsType </extensions> [0..1] ?
+
+    /// Additional data from other schemas, such as the power recorded by
+    /// some fitness devices.
+    pub extensions: Option<Extensions>,
+
+    /// Fitness data from the Garmin TrackPointExtension, which most fitness
+    /// devices write. It is read out of the extensions, so `extensions` only
+    /// holds what is left.
+    pub track_point_extension: Option<TrackPointExtension>,
 }
 
+/// Local names of the extension elements that hold barometric elevation, see
+/// `Waypoint::best_elevation`.
+const BAROMETRIC_ELEVATION_NAMES: &[&str] = &["baro_ele", "barometric_elevation"];
+
+/// Local names of the extension elements that hold the course, see
+/// `Waypoint::course`.
+const COURSE_NAMES: &[&str] = &["course", "heading", "bearing"];
+
 impl Waypoint {
     /// Gives the geographical point of the waypoint.
     ///
@@ -353,8 +2267,147 @@ impl Waypoint {
         self.point.0 //.0 to extract the geo_types::Point from the tuple struct GpxPoint
     }
 
+    /// Gives the latitude (in degrees) of the waypoint.
+    ///
+    /// ```
+    /// use gpx::Waypoint;
+    /// use geo_types::Point;
+    ///
+    /// let wpt = Waypoint::new(Point::new(-121.97, 37.24));
+    /// assert_eq!(wpt.lat(), 37.24);
+    /// ```
+    pub fn lat(&self) -> f64 {
+        self.point.0.lat()
+    }
+
+    /// Gives the longitude (in degrees) of the waypoint.
+    ///
+    /// ```
+    /// use gpx::Waypoint;
+    /// use geo_types::Point;
+    ///
+    /// let wpt = Waypoint::new(Point::new(-121.97, 37.24));
+    /// assert_eq!(wpt.lon(), -121.97);
+    /// ```
+    pub fn lon(&self) -> f64 {
+        self.point.0.lng()
+    }
+
+    /// Gives the best known elevation (in meters) of the waypoint, preferring
+    /// the barometric elevation stored in its extensions by devices with a
+    /// barometric altimeter, as a `baro_ele` or `barometric_elevation` element
+    /// of any namespace, over the GPS elevation. Barometric elevation is much
+    /// less noisy, so the metrics based on elevation change, like `vam`,
+    /// `categorized_climbs` and `graded_pace`, use this.
+    ///
+    /// ```
+    /// use gpx::WaypointBuilder;
+    /// use geo_types::Point;
+    ///
+    /// let wpt = WaypointBuilder::new(Point::new(-121.97, 37.24))
+    ///     .elevation(553.21)
+    ///     .extension("baro_ele", 548.5)
+    ///     .build();
+    /// assert_eq!(wpt.best_elevation(), Some(548.5));
+    /// ```
+    pub fn best_elevation(&self) -> Option<f64> {
+        self.barometric_elevation().or(self.elevation)
+    }
+
+    /// Gives the barometric elevation stored in the extensions of the
+    /// waypoint, see `best_elevation`.
+    pub(crate) fn barometric_elevation(&self) -> Option<f64> {
+        self.extension_value(BAROMETRIC_ELEVATION_NAMES)
+    }
+
+    /// Gives the course stored in the extensions of the waypoint, see
+    /// `course`.
+    pub(crate) fn extension_course(&self) -> Option<f64> {
+        self.extension_value(COURSE_NAMES)
+    }
+
+    /// Gives the number in the first extension element, at any depth, with
+    /// one of the local `names`.
+    fn extension_value(&self, names: &[&str]) -> Option<f64> {
+        fn find(nodes: &[ExtensionNode], names: &[&str]) -> Option<f64> {
+            nodes.iter().find_map(|node| match node {
+                ExtensionNode::Element(element) if names.contains(&element.name.as_str()) => {
+                    element.children.iter().find_map(|child| match child {
+                        ExtensionNode::Text(text) => text.trim().parse().ok(),
+                        _ => None,
+                    })
+                }
+                ExtensionNode::Element(element) => find(&element.children, names),
+                ExtensionNode::Text(_) => None,
+            })
+        }
+        self.extensions
+            .as_ref()
+            .and_then(|extensions| find(&extensions.children, names))
+    }
+
+    /// Gives the links to external media, such as geotagged photos, that
+    /// apps store in the extensions of the waypoint: every element of any
+    /// namespace, at any depth, with an `href` attribute (`xlink:href`
+    /// included), like `<photo href="IMG_0042.jpg" type="image/jpeg"/>`.
+    ///
+    /// The text of a link is taken from a `<text>` child of the element, or
+    /// else from its own text, and its type from a `type` attribute or a
+    /// `<type>` child. The links of `links` are not included.
+    pub fn media_links(&self) -> Vec<Link> {
+        fn attribute<'a>(element: &'a ExtensionElement, name: &str) -> Option<&'a str> {
+            element.attributes.iter().find_map(|(key, value)| {
+                let local = key.rsplit(':').next().unwrap_or(key);
+                Some(value.as_str()).filter(|_| local == name)
+            })
+        }
+        fn text(nodes: &[ExtensionNode]) -> Option<String> {
+            let text: String = nodes
+                .iter()
+                .filter_map(|node| match node {
+                    ExtensionNode::Text(text) => Some(text.as_str()),
+                    ExtensionNode::Element(_) => None,
+                })
+                .collect();
+            Some(text.trim().to_owned()).filter(|text| !text.is_empty())
+        }
+        fn child_text(element: &ExtensionElement, name: &str) -> Option<String> {
+            element.children.iter().find_map(|node| match node {
+                ExtensionNode::Element(child) if child.name == name => text(&child.children),
+                _ => None,
+            })
+        }
+        fn find(nodes: &[ExtensionNode], links: &mut Vec<Link>) {
+            for node in nodes {
+                let element = match node {
+                    ExtensionNode::Element(element) => element,
+                    ExtensionNode::Text(_) => continue,
+                };
+                match attribute(element, "href") {
+                    Some(href) => links.push(Link {
+                        href: href.to_owned(),
+                        text: child_text(element, "text").or_else(|| text(&element.children)),
+                        _type: attribute(element, "type")
+                            .map(str::to_owned)
+                            .or_else(|| child_text(element, "type")),
+                    }),
+                    None => find(&element.children, links),
+                }
+            }
+        }
+        let mut links = Vec::new();
+        if let Some(ref extensions) = self.extensions {
+            find(&extensions.children, &mut links);
+        }
+        links
+    }
+
     /// Creates a new Waypoint from a given geographical point.
     ///
+    /// The point takes the longitude first, as x, and the latitude second, as
+    /// y. Debug builds panic on a latitude outside of [-90, 90], which is the
+    /// usual sign of the two being swapped.
+    ///
     /// ```
     /// extern crate geo_types;
     /// extern crate gpx;
@@ -370,11 +2423,129 @@ impl Waypoint {
     /// }
     /// ```
     pub fn new(point: Point<f64>) -> Waypoint {
+        debug_assert!(
+            (-90.0..=90.0).contains(&point.lat()),
+            "latitude {} is out of range, is the point in (longitude, latitude) order?",
+            point.lat()
+        );
         Waypoint {
             point: GpxPoint(point),
             ..Default::default()
         }
     }
+
+    /// Moves the waypoint to `point`, keeping everything else.
+    pub(crate) fn set_point(&mut self, point: Point<f64>) {
+        self.point = GpxPoint(point);
+    }
+
+    /// Gives the point at `time` on the straight line from this waypoint to
+    /// `next`, with the elevation interpolated if both have one. Both
+    /// waypoints must have a time.
+    fn interpolate(&self, next: &Waypoint, time: DateTime<Utc>) -> Waypoint {
+        let (from, to) = (self.time.unwrap(), next.time.unwrap());
+        let fraction =
+            (time - from).num_milliseconds() as f64 / (to - from).num_milliseconds() as f64;
+        let mut wpt = self.interpolate_fraction(next, fraction);
+        wpt.time = Some(time);
+        wpt
+    }
+
+    /// Gives the point `fraction` of the way on the straight line from this
+    /// waypoint to `next`, with the elevation and time interpolated if both
+    /// have one.
+    fn interpolate_fraction(&self, next: &Waypoint, fraction: f64) -> Waypoint {
+        let between = |a: f64, b: f64| a + (b - a) * fraction;
+
+        let mut wpt = Waypoint::new(Point::new(
+            between(self.lon(), next.lon()),
+            between(self.lat(), next.lat()),
+        ));
+        wpt.elevation = match (self.elevation, next.elevation) {
+            (Some(low), Some(high)) => Some(between(low, high)),
+            _ => None,
+        };
+        wpt.time = match (self.time, next.time) {
+            (Some(from), Some(to)) => {
+                let millis = (to - from).num_milliseconds() as f64 * fraction;
+                Some(from + Duration::milliseconds(millis.round() as i64))
+            }
+            _ => None,
+        };
+        wpt
+    }
+}
+
+/// WaypointBuilder creates a Waypoint one field at a time.
+///
+/// ```
+/// extern crate geo_types;
+/// extern crate gpx;
+///
+/// use gpx::WaypointBuilder;
+/// use geo_types::Point;
+///
+/// fn main() {
+///     let wpt = WaypointBuilder::new(Point::new(-121.97, 37.24))
+///         .elevation(553.21)
+///         .extension("heartrate", 142)
+///         .build();
+///
+///     assert_eq!(wpt.elevation, Some(553.21));
+/// }
+/// ```
+#[derive(Clone, Debug)]
+pub struct WaypointBuilder {
+    waypoint: Waypoint,
+}
+
+impl WaypointBuilder {
+    /// Starts building a waypoint at the given geographical point.
+    pub fn new(point: Point<f64>) -> WaypointBuilder {
+        WaypointBuilder {
+            waypoint: Waypoint::new(point),
+        }
+    }
+
+    /// Sets the elevation (in meters) of the waypoint.
+    pub fn elevation(mut self, elevation: f64) -> WaypointBuilder {
+        self.waypoint.elevation = Some(elevation);
+        self
+    }
+
+    /// Sets the timestamp of the waypoint.
+    pub fn time(mut self, time: DateTime<Utc>) -> WaypointBuilder {
+        self.waypoint.time = Some(time);
+        self
+    }
+
+    /// Sets the name of the waypoint.
+    pub fn name<S: Into<String>>(mut self, name: S) -> WaypointBuilder {
+        self.waypoint.name = Some(name.into());
+        self
+    }
+
+    /// Adds an element called `name` holding `value` as text to the
+    /// extensions of the waypoint. The element is written without a
+    /// namespace.
+    pub fn extension<S: Into<String>, V: ToString>(mut self, name: S, value: V) -> WaypointBuilder {
+        let element = ExtensionElement {
+            name: name.into(),
+            children: vec![ExtensionNode::Text(value.to_string())],
+            ..Default::default()
+        };
+        self.waypoint
+            .extensions
+            .get_or_insert_with(Default::default)
+            .children
+            .push(ExtensionNode::Element(element));
+        self
+    }
+
+    /// Gives the built waypoint.
+    pub fn build(self) -> Waypoint {
+        self.waypoint
+    }
 }
 
 impl From<Waypoint> for Geometry<f64> {
@@ -383,8 +2554,121 @@ impl From<Waypoint> for Geometry<f64> {
     }
 }
 
+/// Extensions holds the content of an `<extensions>` element, where GPX allows
+/// applications to store any additional data as XML from their own namespace.
+///
+/// The content is kept as a tree of XML nodes, so that it is written back out
+/// unchanged.
+#[derive(Clone, Default, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Extensions {
+    /// The nodes inside the `<extensions>` element.
+    pub children: Vec<ExtensionNode>,
+}
+
+impl Extensions {
+    /// Gives the elements directly inside `<extensions>` that are in the
+    /// `namespace` URI, such as the Garmin TrackPointExtension one, whatever
+    /// prefix the document uses for it. Elements from other namespaces in the
+    /// same block are left out, but are still kept in `children`.
+    ///
+    /// ```
+    /// use gpx::{ExtensionElement, ExtensionNode, Extensions};
+    ///
+    /// let element = ExtensionElement {
+    ///     name: String::from("hr"),
+    ///     namespace: Some(String::from("urn:example")),
+    ///     ..Default::default()
+    /// };
+    /// let extensions = Extensions {
+    ///     children: vec![ExtensionNode::Element(element)],
+    /// };
+    ///
+    /// assert_eq!(extensions.elements_in("urn:example").count(), 1);
+    /// assert_eq!(extensions.elements_in("urn:other").count(), 0);
+    /// ```
+    pub fn elements_in<'a>(
+        &'a self,
+        namespace: &'a str,
+    ) -> impl Iterator<Item = &'a ExtensionElement> + 'a {
+        self.children.iter().filter_map(move |node| match node {
+            ExtensionNode::Element(element) if element.namespace.as_deref() == Some(namespace) => {
+                Some(element)
+            }
+            _ => None,
+        })
+    }
+}
+
+/// ExtensionNode is a piece of XML content found inside `<extensions>`.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ExtensionNode {
+    /// A child element, with its own attributes and content.
+    Element(ExtensionElement),
+    /// Text content. Whitespace between elements is only kept in mixed
+    /// content, where elements are interleaved with other text.
+    Text(String),
+}
+
+/// ExtensionElement is an XML element found inside `<extensions>`.
+#[derive(Clone, Default, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ExtensionElement {
+    /// Local name of the element, e.g. `hr` for `<gpxtpx:hr>`.
+    pub name: String,
+
+    /// Namespace prefix of the element, e.g. `gpxtpx` for `<gpxtpx:hr>`.
+    pub prefix: Option<String>,
+
+    /// Namespace URI of the element.
+    pub namespace: Option<String>,
+
+    /// Attributes of the element, as pairs of qualified name and value.
+    pub attributes: Vec<(String, String)>,
+
+    /// The nodes inside the element.
+    pub children: Vec<ExtensionNode>,
+}
+
+/// TrackPointExtension holds the fitness data of a track point, from the
+/// `TrackPointExtension` element defined by Garmin.
+#[derive(Clone, Default, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TrackPointExtension {
+    /// Heart rate in beats per minute.
+    pub hr: Option<u8>,
+
+    /// Cadence in revolutions per minute.
+    pub cad: Option<u8>,
+
+    /// Air temperature in degrees Celsius.
+    pub atemp: Option<f64>,
+
+    /// Water temperature in degrees Celsius.
+    pub wtemp: Option<f64>,
+
+    /// Depth in meters.
+    pub depth: Option<f64>,
+
+    /// Whether the fields are wrapped in a further `Extensions` element
+    /// inside the `TrackPointExtension` one, as some Garmin devices write
+    /// them. They are written back out wrapped the same way.
+    pub wrapped: bool,
+
+    /// Namespace URI of the `TrackPointExtension` element the fields were
+    /// read from, that of version 1 or 2 of the extension. They are written
+    /// back in the same namespace, or in that of version 1 if there is none.
+    pub namespace: Option<String>,
+
+    /// Namespace prefix of the `TrackPointExtension` element the fields were
+    /// read from. Without a namespace, they are written with `gpxtpx`.
+    pub prefix: Option<String>,
+}
+
 /// Person represents a person or organization.
 #[derive(Clone, Default, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Person {
     /// Name of person or organization.
     pub name: Option<String>,
@@ -396,11 +2680,27 @@ pub struct Person {
     pub link: Option<Link>,
 }
 
+/// Copyright represents who holds the copyright of a file, and the license
+/// under which it may be used.
+#[derive(Clone, Default, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Copyright {
+    /// Copyright holder, e.g. "OpenStreetMap contributors".
+    pub author: String,
+
+    /// Year of copyright.
+    pub year: Option<i32>,
+
+    /// Link to the license, e.g. <https://opendatacommons.org/licenses/odbl/>.
+    pub license: Option<String>,
+}
+
 /// Link represents a link to an external resource.
 ///
 /// An external resource could be a web page, digital photo,
 /// video clip, etc., with additional information.
 #[derive(Clone, Default, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Link {
     /// URL of hyperlink.
     pub href: String,
@@ -413,7 +2713,8 @@ pub struct Link {
 }
 
 /// Type of the GPS fix.
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Fix {
     /// The GPS had no fix. To signify "the fix info is unknown", leave out the Fix entirely.
     None,
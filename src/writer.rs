@@ -1,10 +1,18 @@
 //! Writes an activity to GPX format.
 
+use std::borrow::Cow;
+use std::collections::HashSet;
 use std::io::Write;
 
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, Duration, DurationRound, SecondsFormat, Utc};
 use error_chain::bail;
+use geo::algorithm::haversine_distance::HaversineDistance;
+use geo::algorithm::haversine_length::HaversineLength;
 use geo_types::Rect;
+use xml::attribute::Attribute;
+use xml::common::XmlVersion;
+use xml::name::Name;
+use xml::namespace::Namespace;
 use xml::writer::{EmitterConfig, EventWriter, XmlEvent};
 
 use crate::errors::*;
@@ -16,6 +24,9 @@ use crate::{Gpx, GpxVersion};
 /// Takes any `std::io::Write` as its writer, and returns a
 /// `std::io::Result<()>`.
 ///
+/// A document of unknown version, such as a default `Gpx`, is written as GPX
+/// 1.1.
+///
 /// ```
 /// use gpx::write;
 /// use gpx::Gpx;
@@ -28,66 +39,819 @@ use crate::{Gpx, GpxVersion};
 /// write(&data, std::io::stdout()).unwrap();
 /// ```
 pub fn write<W: Write>(gpx: &Gpx, writer: W) -> Result<()> {
-    let mut writer = EmitterConfig::new()
+    write_with_options(gpx, writer, &Default::default())
+}
+
+/// Writes an activity to GPX format, and gives the resulting bytes.
+///
+/// ```
+/// use gpx::{write_to_bytes, Gpx, GpxVersion};
+///
+/// let mut data: Gpx = Default::default();
+/// data.version = GpxVersion::Gpx11;
+///
+/// let bytes: Vec<u8> = write_to_bytes(&data).unwrap();
+/// assert!(bytes.starts_with(b"<?xml"));
+/// ```
+pub fn write_to_bytes(gpx: &Gpx) -> Result<Vec<u8>> {
+    let mut buffer = Vec::new();
+    write(gpx, &mut buffer)?;
+    Ok(buffer)
+}
+
+/// Options that change how a GPX document is written, see `write_with_options`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct WriteOptions {
+    /// An XML comment to emit right after the XML declaration, before the
+    /// `<gpx>` element, e.g. "Generated by MyApp". Any `--` in the text is
+    /// escaped so that the document stays well-formed.
+    pub header_comment: Option<String>,
+
+    /// The namespace URI of the `<gpx>` element, written as its `xmlns`
+    /// attribute, for schema variants that need another one. Defaults to
+    /// `Gpx::namespace` unless that is the standard namespace of another GPX
+    /// version, or else to the standard namespace of the GPX version, such
+    /// as `http://www.topografix.com/GPX/1/1` for GPX 1.1.
+    pub namespace: Option<String>,
+
+    /// The order to write the top-level waypoints in, for viewers that show
+    /// them in file order. Defaults to the order of `Gpx::waypoints`.
+    pub sort_waypoints_by: Option<WaypointSort>,
+
+    /// Write descriptions as CDATA sections instead of escaping their special
+    /// characters, for viewers that show HTML descriptions as such.
+    pub cdata_descriptions: bool,
+
+    /// The number of decimal places to write the latitude and longitude of
+    /// points and bounds with, e.g. 6 for about 10 cm. Defaults to as many as
+    /// needed to read back the exact coordinates.
+    pub coord_precision: Option<usize>,
+
+    /// The number of decimal places to write the elevation of points with.
+    /// Defaults to as many as needed to read back the exact elevation.
+    pub elevation_precision: Option<usize>,
+
+    /// Write the distance and the elapsed time of each track in its
+    /// `<extensions>`, as a Garmin `TrackStatsExtension`, so that other tools
+    /// can show them without computing them. Any such extension the track
+    /// already has is replaced. Only done for GPX 1.1.
+    pub embed_stats: bool,
+
+    /// Write the fraction of the length of its track traveled at each track
+    /// point in its `<extensions>`, from 0 at the first point of the track to
+    /// 1 at the last, for playback tools that animate the track by it. It is
+    /// written as a `progress` element in the namespace of
+    /// `PLAYBACK_EXTENSION_NAMESPACE`, replacing any such element the point
+    /// already has. Only done for GPX 1.1.
+    pub embed_progress: bool,
+
+    /// Write the document in a canonical form, so that documents with the
+    /// same data give the same bytes, such as to keep GPX files in version
+    /// control with meaningful diffs: text is trimmed, whitespace between
+    /// extension elements is dropped, attributes and root namespaces are
+    /// sorted by name, and coordinates (of points and bounds) and elevations
+    /// are written with 7 and 2 decimal places unless `coord_precision` or
+    /// `elevation_precision` say otherwise. Elements are always written in
+    /// the order of the schema.
+    pub canonical: bool,
+
+    /// Split track segments with more points than this into several
+    /// consecutive `<trkseg>` elements, for devices that cannot load longer
+    /// ones. All points are kept. A limit of zero is ignored.
+    pub max_points_per_segment: Option<usize>,
+
+    /// What to write as the time of the metadata. Defaults to the time the
+    /// metadata has, if any.
+    pub metadata_time: MetadataTimePolicy,
+
+    /// Write an empty `<metadata>` element for documents without metadata,
+    /// for consumers that expect one. GPX 1.0 has no such element, so it is
+    /// ignored there.
+    pub emit_empty_metadata: bool,
+
+    /// Round the time of points to the nearest multiple of this interval
+    /// since the Unix epoch, such as to give recordings of devices that log
+    /// at different moments the same cadence. Intervals that are not
+    /// positive are ignored.
+    pub time_grid: Option<Duration>,
+
+    /// Write an `xsi:schemaLocation` attribute pointing at the schema of the
+    /// GPX version on the root element, for validators that need one. The
+    /// `Gpx::schema_location` of the document is written instead if it has
+    /// one, as it always is.
+    pub emit_schema_location: bool,
+}
+
+/// The decimal places of coordinates in canonical output, about 1 cm.
+const CANONICAL_COORD_PRECISION: usize = 7;
+
+/// The decimal places of elevations in canonical output.
+const CANONICAL_ELEVATION_PRECISION: usize = 2;
+
+/// The orders waypoints can be written in, see `WriteOptions`.
+///
+/// Waypoints missing the field sorted on come last, and waypoints that are
+/// equal on it keep their order.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum WaypointSort {
+    /// Alphabetical order of the waypoint names.
+    Name,
+    /// Chronological order of the waypoint times.
+    Time,
+}
+
+/// The times that can be written for the metadata, see `WriteOptions`.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum MetadataTimePolicy {
+    /// The time of the metadata, as it is.
+    #[default]
+    Preserve,
+    /// The time of writing, such as to record when the file was last saved.
+    /// Metadata is added if there is none.
+    Now,
+    /// No time, even if the metadata has one.
+    Omit,
+}
+
+/// Writes an activity to GPX format, using the given `WriteOptions`.
+///
+/// ```
+/// use gpx::{write_with_options, Gpx, GpxVersion, WriteOptions};
+///
+/// let mut data: Gpx = Default::default();
+/// data.version = GpxVersion::Gpx11;
+///
+/// let options = WriteOptions {
+///     header_comment: Some(String::from("Generated by MyApp")),
+///     ..Default::default()
+/// };
+///
+/// let mut buffer: Vec<u8> = Vec::new();
+/// write_with_options(&data, &mut buffer, &options).unwrap();
+/// ```
+pub fn write_with_options<W: Write>(gpx: &Gpx, writer: W, options: &WriteOptions) -> Result<()> {
+    let writer = EmitterConfig::new()
+        .perform_indent(true)
+        .create_writer(writer);
+    write_document(gpx, options, false, &mut GpxWriter::new(writer, None))
+}
+
+/// An element about to be written, as given to the hook of `write_with_hook`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct WrittenElement {
+    /// The name of the element, with its namespace prefix if it has one, such
+    /// as `name` or `gpxtpx:hr`.
+    pub name: String,
+
+    /// The attributes of the element, as pairs of name and value, in the order
+    /// they are written. Namespace declarations are not among them and cannot
+    /// be changed.
+    pub attributes: Vec<(String, String)>,
+
+    /// The text of the element, if it holds no other elements, such as the
+    /// name in `<name>Lake</name>`. It is empty for elements that hold
+    /// nothing, and `None` for elements that hold other elements, whose text
+    /// between them, if any, is written as it is.
+    pub text: Option<String>,
+}
+
+/// Writes an activity to GPX format, using the given `WriteOptions`, and lets
+/// `hook` change or leave out each element before it is written, for changes
+/// that the options cannot make.
+///
+/// The hook is called once for each element, in the order they start in the
+/// document, so a parent element comes before its children. It is called
+/// after the options are applied, and before anything of the element is
+/// written, so the changes it makes to the `WrittenElement` are what is
+/// written. Returning `false` leaves out the element along with everything in
+/// it, whose elements are then not given to the hook. Leaving out the `<gpx>`
+/// root element leaves an empty document.
+///
+/// The hook is responsible for the changes it makes keeping the document
+/// valid GPX.
+///
+/// ```
+/// use gpx::{write_with_hook, Gpx, GpxVersion, Track};
+///
+/// let mut data: Gpx = Default::default();
+/// data.version = GpxVersion::Gpx11;
+/// data.creator = Some(String::from("MyApp"));
+/// data.tracks.push(Track::new());
+///
+/// let mut buffer: Vec<u8> = Vec::new();
+/// write_with_hook(&data, &mut buffer, &Default::default(), |element| {
+///     // Leave out empty tracks.
+///     element.name != "trk"
+/// })
+/// .unwrap();
+/// assert!(!String::from_utf8(buffer).unwrap().contains("<trk"));
+/// ```
+pub fn write_with_hook<W, F>(gpx: &Gpx, writer: W, options: &WriteOptions, hook: F) -> Result<()>
+where
+    W: Write,
+    F: FnMut(&mut WrittenElement) -> bool,
+{
+    let writer = EmitterConfig::new()
+        .perform_indent(true)
+        .create_writer(writer);
+    let mut writer = GpxWriter::new(writer, Some(Box::new(hook)));
+    write_document(gpx, options, false, &mut writer)
+}
+
+/// Writes an activity to GPX format, but leaves its last track segment open,
+/// so that points recorded later can be added with `append_track_points`.
+/// This allows logging live to a file without rewriting it as it grows. A new
+/// track is started if the activity has none.
+///
+/// The written document is not valid GPX until `finalize` has been called on
+/// it. Since routes and extensions of the activity would come after the
+/// tracks, activities that have them cannot be written this way.
+///
+/// ```
+/// use gpx::{append_track_points, finalize, read, write_unfinalized, Gpx, GpxVersion, Waypoint};
+/// use geo_types::Point;
+///
+/// let mut data: Gpx = Default::default();
+/// data.version = GpxVersion::Gpx11;
+///
+/// let mut file: Vec<u8> = Vec::new();
+/// write_unfinalized(&data, &mut file).unwrap();
+/// append_track_points(&[Waypoint::new(Point::new(-121.97, 37.24))], &mut file).unwrap();
+/// finalize(&mut file).unwrap();
+///
+/// let gpx = read(file.as_slice()).unwrap();
+/// assert_eq!(gpx.tracks[0].segments[0].points.len(), 1);
+/// ```
+pub fn write_unfinalized<W: Write>(gpx: &Gpx, writer: W) -> Result<()> {
+    let writer = EmitterConfig::new()
         .perform_indent(true)
         .create_writer(writer);
+    write_document(
+        gpx,
+        &Default::default(),
+        true,
+        &mut GpxWriter::new(writer, None),
+    )
+}
+
+/// Adds points to the open track segment of a document written by
+/// `write_unfinalized`, such as by appending to its file.
+pub fn append_track_points<W: Write>(points: &[Waypoint], mut writer: W) -> Result<()> {
+    writer
+        .write_all(b"\n")
+        .chain_err(|| "error while writing gpx event")?;
+    let writer = EmitterConfig::new()
+        .perform_indent(true)
+        .write_document_declaration(false)
+        .create_writer(writer);
+    let mut writer = GpxWriter::new(writer, None);
+    for point in points {
+        // The version of the document is not known here, so the GPX 1.0 fields
+        // are left out.
+        write_waypoint(
+            "trkpt",
+            point,
+            None,
+            GpxVersion::Gpx11,
+            &Default::default(),
+            &mut writer,
+        )?;
+    }
+    Ok(())
+}
+
+/// Closes the open track segment, track and root element of a document
+/// written by `write_unfinalized`, which makes it valid GPX.
+pub fn finalize<W: Write>(mut writer: W) -> Result<()> {
+    writer
+        .write_all(b"\n</trkseg></trk></gpx>\n")
+        .chain_err(|| "error while writing gpx event")
+}
+
+/// Writes the whole document, or all of it up to and including the points of
+/// the last track segment with `open`.
+fn write_document<W: Write>(
+    gpx: &Gpx,
+    options: &WriteOptions,
+    open: bool,
+    writer: &mut GpxWriter<W>,
+) -> Result<()> {
+    if options.canonical {
+        let options = WriteOptions {
+            coord_precision: options.coord_precision.or(Some(CANONICAL_COORD_PRECISION)),
+            elevation_precision: options
+                .elevation_precision
+                .or(Some(CANONICAL_ELEVATION_PRECISION)),
+            canonical: false,
+            ..options.clone()
+        };
+        return write_document(&canonicalize(gpx), &options, open, writer);
+    }
+    // Documents of unknown version are written as the latest one.
+    let version = match gpx.version {
+        GpxVersion::Gpx10 => GpxVersion::Gpx10,
+        GpxVersion::Gpx11 | GpxVersion::Unknown => GpxVersion::Gpx11,
+    };
+    if open && !gpx.routes.is_empty() {
+        bail!("cannot leave the document open when it has routes");
+    }
+    if open && version == GpxVersion::Gpx11 && gpx.summary_extensions.is_some() {
+        bail!("cannot leave the document open when it has extensions");
+    }
+    write_xml_event(
+        XmlEvent::StartDocument {
+            version: XmlVersion::Version10,
+            encoding: Some("UTF-8"),
+            standalone: None,
+        },
+        writer,
+    )?;
+    for instruction in &gpx.processing_instructions {
+        let mut parts = instruction.splitn(2, char::is_whitespace);
+        let name = parts.next().unwrap_or_default();
+        write_xml_event(XmlEvent::processing_instruction(name, parts.next()), writer)?;
+    }
+    if let Some(ref comment) = options.header_comment {
+        write_xml_event(XmlEvent::comment(comment), writer)?;
+    }
     let creator: &str = gpx
         .creator
         .as_deref()
         .unwrap_or("https://github.com/georust/gpx");
-    write_xml_event(
-        XmlEvent::start_element("gpx")
-            .attr("version", version_to_version_string(gpx.version)?)
-            .attr("creator", creator),
-        &mut writer,
-    )?;
-    write_metadata(gpx, &mut writer)?;
-    for point in &gpx.waypoints {
-        write_waypoint("wpt", point, &mut writer)?;
+    // The namespace read from a document of another version is not reused.
+    let read_namespace = gpx
+        .namespace
+        .as_ref()
+        .filter(|namespace| is_namespace_of(namespace, version));
+    let namespace = match options.namespace.as_ref().or(read_namespace) {
+        Some(namespace) => namespace.as_str(),
+        None => version_to_namespace(version),
+    };
+    let version_string = match gpx.declared_version {
+        Some(ref declared) if version == GpxVersion::Gpx11 => declared.as_str(),
+        _ => version_to_version_string(version),
+    };
+    let namespaces = used_namespaces(gpx, version, options);
+    let mut root = XmlEvent::start_element("gpx").default_ns(namespace);
+    for (prefix, uri) in &namespaces {
+        root = root.ns(prefix.as_str(), uri.as_str());
     }
-    for track in &gpx.tracks {
-        write_track(track, &mut writer)?;
+    let schema_location = match gpx.schema_location {
+        Some(ref schema_location) => Some(schema_location.as_str()),
+        None if options.emit_schema_location => Some(version_to_schema_location(version)),
+        None => None,
+    };
+    if schema_location.is_some() && !namespaces.iter().any(|(prefix, _)| prefix == "xsi") {
+        root = root.ns("xsi", XSI_NAMESPACE);
+    }
+    root = root
+        .attr("version", version_string)
+        .attr("creator", creator);
+    if let Some(schema_location) = schema_location {
+        root = root.attr("xsi:schemaLocation", schema_location);
+    }
+    write_xml_event(root, writer)?;
+    write_metadata(gpx, version, options, writer)?;
+    let mut waypoints: Vec<&Waypoint> = gpx.waypoints.iter().collect();
+    match options.sort_waypoints_by {
+        Some(WaypointSort::Name) => {
+            waypoints.sort_by_key(|wpt| (wpt.name.is_none(), wpt.name.as_ref()))
+        }
+        Some(WaypointSort::Time) => waypoints.sort_by_key(|wpt| (wpt.time.is_none(), wpt.time)),
+        None => {}
+    }
+    for point in waypoints {
+        write_waypoint("wpt", point, None, version, options, writer)?;
+    }
+    match gpx.tracks.split_last() {
+        Some((last, tracks)) if open => {
+            for track in tracks {
+                write_track(track, false, version, options, writer)?;
+            }
+            return write_track(last, true, version, options, writer);
+        }
+        None if open => return write_track(&Track::new(), true, version, options, writer),
+        _ => {
+            for track in &gpx.tracks {
+                write_track(track, false, version, options, writer)?;
+            }
+        }
     }
     for route in &gpx.routes {
-        write_route(route, &mut writer)?;
+        write_route(route, version, options, writer)?;
+    }
+    // GPX 1.0 has no extensions element.
+    if version == GpxVersion::Gpx11 {
+        write_extensions_if_exists(&gpx.summary_extensions, writer)?;
     }
-    write_xml_event(XmlEvent::end_element(), &mut writer)?;
+    write_xml_event(XmlEvent::end_element(), writer)?;
     Ok(())
 }
 
-fn write_xml_event<'a, W, E>(event: E, writer: &mut EventWriter<W>) -> Result<()>
+/// Gives a copy of `gpx` with its text trimmed, its extensions stripped of
+/// formatting, and its attributes and namespaces sorted, see
+/// `WriteOptions::canonical`.
+fn canonicalize(gpx: &Gpx) -> Gpx {
+    fn trim(text: &mut Option<String>) {
+        if let Some(text) = text {
+            *text = text.trim().to_owned();
+        }
+    }
+    fn trim_link(link: &mut Link) {
+        link.href = link.href.trim().to_owned();
+        trim(&mut link.text);
+        trim(&mut link._type);
+    }
+    fn canonicalize_extensions(extensions: &mut Option<Extensions>) {
+        if let Some(extensions) = extensions {
+            canonicalize_nodes(&mut extensions.children);
+        }
+    }
+    fn canonicalize_nodes(nodes: &mut Vec<ExtensionNode>) {
+        let mixed = nodes
+            .iter()
+            .any(|node| matches!(node, ExtensionNode::Text(text) if !text.trim().is_empty()));
+        if !mixed {
+            nodes.retain(|node| matches!(node, ExtensionNode::Element(_)));
+        }
+        for node in nodes {
+            match node {
+                ExtensionNode::Element(element) => {
+                    element.attributes.sort();
+                    canonicalize_nodes(&mut element.children);
+                }
+                ExtensionNode::Text(text) if !mixed => *text = text.trim().to_owned(),
+                ExtensionNode::Text(_) => {}
+            }
+        }
+    }
+    fn canonicalize_waypoint(wpt: &mut Waypoint) {
+        trim(&mut wpt.name);
+        trim(&mut wpt.comment);
+        trim(&mut wpt.description);
+        trim(&mut wpt.source);
+        trim(&mut wpt.symbol);
+        trim(&mut wpt._type);
+        wpt.links.iter_mut().for_each(trim_link);
+        canonicalize_extensions(&mut wpt.extensions);
+    }
+
+    let mut gpx = gpx.clone();
+    trim(&mut gpx.creator);
+    gpx.namespaces.sort();
+    if let Some(ref mut metadata) = gpx.metadata {
+        trim(&mut metadata.name);
+        trim(&mut metadata.description);
+        trim(&mut metadata.keywords);
+        metadata.links.iter_mut().for_each(trim_link);
+        if let Some(ref mut author) = metadata.author {
+            trim(&mut author.name);
+            trim(&mut author.email);
+            author.link.iter_mut().for_each(trim_link);
+        }
+        if let Some(ref mut copyright) = metadata.copyright {
+            copyright.author = copyright.author.trim().to_owned();
+            trim(&mut copyright.license);
+        }
+    }
+    gpx.waypoints.iter_mut().for_each(canonicalize_waypoint);
+    for track in &mut gpx.tracks {
+        trim(&mut track.name);
+        trim(&mut track.comment);
+        trim(&mut track.description);
+        trim(&mut track.source);
+        trim(&mut track._type);
+        track.links.iter_mut().for_each(trim_link);
+        canonicalize_extensions(&mut track.extensions);
+        track
+            .segments
+            .iter_mut()
+            .flat_map(|seg| seg.points.iter_mut())
+            .for_each(canonicalize_waypoint);
+    }
+    for route in &mut gpx.routes {
+        trim(&mut route.name);
+        trim(&mut route.comment);
+        trim(&mut route.description);
+        trim(&mut route.source);
+        trim(&mut route._type);
+        route.links.iter_mut().for_each(trim_link);
+        canonicalize_extensions(&mut route.extensions);
+        route.points.iter_mut().for_each(canonicalize_waypoint);
+    }
+    canonicalize_extensions(&mut gpx.summary_extensions);
+    gpx
+}
+
+/// The hook of `write_with_hook`.
+type Hook<'h> = Box<dyn FnMut(&mut WrittenElement) -> bool + 'h>;
+
+/// GpxWriter writes the events of a document, giving each element to the
+/// hook of `write_with_hook`, if there is one, before writing it.
+struct GpxWriter<'h, W: Write> {
+    events: EventWriter<W>,
+    hook: Option<Hook<'h>>,
+
+    /// The element started last, held back until it is known whether it only
+    /// holds text.
+    pending: Option<PendingElement>,
+
+    /// The depth within a left out element, whose events are skipped.
+    skipped: usize,
+}
+
+/// An element held back by `GpxWriter`.
+struct PendingElement {
+    element: WrittenElement,
+    namespace: Namespace,
+
+    /// Whether its text was given as CDATA, to be written the same way.
+    cdata: bool,
+}
+
+impl<'h, W: Write> GpxWriter<'h, W> {
+    fn new(events: EventWriter<W>, hook: Option<Hook<'h>>) -> GpxWriter<'h, W> {
+        GpxWriter {
+            events,
+            hook,
+            pending: None,
+            skipped: 0,
+        }
+    }
+
+    fn write(&mut self, event: XmlEvent) -> Result<()> {
+        if self.hook.is_none() {
+            return self.emit(event);
+        }
+        if let Some(mut pending) = self.pending.take() {
+            match event {
+                XmlEvent::Characters(text) | XmlEvent::CData(text) => {
+                    pending.cdata |= matches!(event, XmlEvent::CData(_));
+                    let held = pending.element.text.get_or_insert_with(String::new);
+                    held.push_str(text);
+                    self.pending = Some(pending);
+                    return Ok(());
+                }
+                XmlEvent::EndElement { .. } => return self.flush(pending, true),
+                _ => self.flush(pending, false)?,
+            }
+        }
+        if self.skipped > 0 {
+            match event {
+                XmlEvent::StartElement { .. } => self.skipped += 1,
+                XmlEvent::EndElement { .. } => self.skipped -= 1,
+                _ => {}
+            }
+            return Ok(());
+        }
+        match event {
+            XmlEvent::StartElement {
+                name,
+                attributes,
+                namespace,
+            } => {
+                let element = WrittenElement {
+                    name: name.to_repr(),
+                    attributes: attributes
+                        .iter()
+                        .map(|attr| (attr.name.to_repr(), attr.value.to_owned()))
+                        .collect(),
+                    text: None,
+                };
+                self.pending = Some(PendingElement {
+                    element,
+                    namespace: namespace.into_owned(),
+                    cdata: false,
+                });
+                Ok(())
+            }
+            event => self.emit(event),
+        }
+    }
+
+    /// Gives a held back element to the hook, and writes its start unless the
+    /// hook leaves it out. With `complete`, the element only holds its text,
+    /// which is written along with its end.
+    fn flush(&mut self, mut pending: PendingElement, complete: bool) -> Result<()> {
+        // Text between the elements the element holds is written as it is.
+        let between = if complete {
+            None
+        } else {
+            pending.element.text.take()
+        };
+        if let Some(ref mut hook) = self.hook {
+            if !hook(&mut pending.element) {
+                self.skipped = if complete { 0 } else { 1 };
+                return Ok(());
+            }
+        }
+        let element = pending.element;
+        let attributes: Vec<Attribute> = element
+            .attributes
+            .iter()
+            .map(|(name, value)| Attribute {
+                name: Name::from(name.as_str()),
+                value,
+            })
+            .collect();
+        self.emit(XmlEvent::StartElement {
+            name: Name::from(element.name.as_str()),
+            attributes: Cow::Owned(attributes),
+            namespace: Cow::Owned(pending.namespace),
+        })?;
+        let text = if complete { element.text } else { between };
+        match text {
+            Some(ref text) if pending.cdata => {
+                for section in cdata_sections(text) {
+                    self.emit(XmlEvent::cdata(section))?;
+                }
+            }
+            Some(ref text) => self.emit(XmlEvent::characters(text))?,
+            None => {}
+        }
+        if complete {
+            self.emit(XmlEvent::end_element().into())?;
+        }
+        Ok(())
+    }
+
+    fn emit(&mut self, event: XmlEvent) -> Result<()> {
+        self.events
+            .write(event)
+            .chain_err(|| Error::from("error while writing gpx event"))
+    }
+}
+
+/// Splits `text` into the parts to write as consecutive CDATA sections, since
+/// a CDATA section cannot hold its own end marker.
+fn cdata_sections(text: &str) -> impl Iterator<Item = &str> {
+    let mut rest = Some(text);
+    std::iter::from_fn(move || {
+        let current = rest?;
+        match current.find("]]>") {
+            Some(end) => {
+                rest = Some(&current[end + 2..]);
+                Some(&current[..end + 2])
+            }
+            None => rest.take(),
+        }
+    })
+}
+
+fn write_xml_event<'a, W, E>(event: E, writer: &mut GpxWriter<W>) -> Result<()>
 where
     W: Write,
     E: Into<XmlEvent<'a>>,
 {
-    writer
-        .write(event)
-        .chain_err(|| Error::from("error while writing gpx event"))
+    writer.write(event.into())
+}
+
+/// Namespace URI of XML Schema instances, for the `xsi:schemaLocation`
+/// attribute.
+const XSI_NAMESPACE: &str = "http://www.w3.org/2001/XMLSchema-instance";
+
+/// Gives the namespaces of `Gpx::namespaces` that the document uses, which
+/// are the only ones declared on the root element: those of the extension
+/// elements written, as well as those whose prefix is used by an attribute.
+fn used_namespaces<'a>(
+    gpx: &'a Gpx,
+    version: GpxVersion,
+    options: &WriteOptions,
+) -> Vec<&'a (String, String)> {
+    fn visit<'a>(
+        nodes: &'a [ExtensionNode],
+        uris: &mut HashSet<&'a str>,
+        prefixes: &mut HashSet<&'a str>,
+    ) {
+        for node in nodes {
+            if let ExtensionNode::Element(element) = node {
+                uris.extend(element.namespace.as_deref());
+                prefixes.extend(
+                    element
+                        .attributes
+                        .iter()
+                        .filter_map(|(key, _)| Some(key.split_once(':')?.0)),
+                );
+                visit(&element.children, uris, prefixes);
+            }
+        }
+    }
+
+    let mut uris = HashSet::new();
+    let mut prefixes = HashSet::new();
+    if gpx.schema_location.is_some() {
+        prefixes.insert("xsi");
+    }
+    if options.embed_stats && version == GpxVersion::Gpx11 && !gpx.tracks.is_empty() {
+        uris.insert(TRACK_STATS_EXTENSION_NAMESPACE);
+    }
+    if options.embed_progress && version == GpxVersion::Gpx11 && !gpx.tracks.is_empty() {
+        uris.insert(PLAYBACK_EXTENSION_NAMESPACE);
+    }
+    // GPX 1.0 has no extensions element, so they are not written.
+    if version == GpxVersion::Gpx11 {
+        let extensions = gpx
+            .tracks
+            .iter()
+            .map(|track| &track.extensions)
+            .chain(gpx.routes.iter().map(|route| &route.extensions))
+            .chain(gpx.points().map(|wpt| &wpt.extensions));
+        for extensions in extensions.chain(Some(&gpx.summary_extensions)).flatten() {
+            visit(&extensions.children, &mut uris, &mut prefixes);
+        }
+        for fields in gpx
+            .points()
+            .filter_map(|wpt| wpt.track_point_extension.as_ref())
+        {
+            uris.insert(
+                fields
+                    .namespace
+                    .as_deref()
+                    .unwrap_or(TRACK_POINT_EXTENSION_NAMESPACE),
+            );
+        }
+    }
+    gpx.namespaces
+        .iter()
+        .filter(|(prefix, uri)| uris.contains(uri.as_str()) || prefixes.contains(prefix.as_str()))
+        .collect()
+}
+
+fn version_to_version_string(version: GpxVersion) -> &'static str {
+    match version {
+        GpxVersion::Gpx10 => "1.0",
+        GpxVersion::Gpx11 | GpxVersion::Unknown => "1.1",
+    }
 }
 
-fn version_to_version_string(version: GpxVersion) -> Result<&'static str> {
+fn version_to_namespace(version: GpxVersion) -> &'static str {
     match version {
-        GpxVersion::Gpx10 => Ok("1.0"),
-        GpxVersion::Gpx11 => Ok("1.1"),
-        version => Err(Error::from(format!("Unknown version {:?}", version))),
+        GpxVersion::Gpx10 => "http://www.topografix.com/GPX/1/0",
+        GpxVersion::Gpx11 | GpxVersion::Unknown => "http://www.topografix.com/GPX/1/1",
     }
 }
 
-fn write_metadata<W: Write>(gpx: &Gpx, writer: &mut EventWriter<W>) -> Result<()> {
-    match gpx.version {
-        GpxVersion::Gpx10 => write_gpx10_metadata(gpx, writer),
-        GpxVersion::Gpx11 => write_gpx11_metadata(gpx, writer),
-        version => Err(Error::from(format!("Unknown version {:?}", version))),
+/// Tells whether `namespace` may be the namespace of a document of `version`,
+/// which is the case for all but the standard namespaces of other versions.
+fn is_namespace_of(namespace: &str, version: GpxVersion) -> bool {
+    [GpxVersion::Gpx10, GpxVersion::Gpx11]
+        .iter()
+        .all(|&other| other == version || namespace != version_to_namespace(other))
+}
+
+/// Gives the `xsi:schemaLocation` pointing at the schema of `version`.
+fn version_to_schema_location(version: GpxVersion) -> &'static str {
+    match version {
+        GpxVersion::Gpx10 => {
+            "http://www.topografix.com/GPX/1/0 http://www.topografix.com/GPX/1/0/gpx.xsd"
+        }
+        GpxVersion::Gpx11 | GpxVersion::Unknown => {
+            "http://www.topografix.com/GPX/1/1 http://www.topografix.com/GPX/1/1/gpx.xsd"
+        }
     }
 }
 
-fn write_gpx10_metadata<W: Write>(gpx: &Gpx, writer: &mut EventWriter<W>) -> Result<()> {
-    if gpx.metadata.is_none() {
-        return Ok(());
+/// Writes the metadata where `version` places it: GPX 1.1 has a `<metadata>`
+/// element for it, while in GPX 1.0 its fields, the bounds among them, are
+/// children of the root element.
+fn write_metadata<W: Write>(
+    gpx: &Gpx,
+    version: GpxVersion,
+    options: &WriteOptions,
+    writer: &mut GpxWriter<W>,
+) -> Result<()> {
+    let metadata = match (options.metadata_time, &gpx.metadata) {
+        (MetadataTimePolicy::Preserve, _) | (MetadataTimePolicy::Omit, None) => {
+            Cow::Borrowed(&gpx.metadata)
+        }
+        (MetadataTimePolicy::Now, metadata) => Cow::Owned(Some(Metadata {
+            time: Some(Utc::now()),
+            ..metadata.clone().unwrap_or_default()
+        })),
+        (MetadataTimePolicy::Omit, Some(metadata)) => Cow::Owned(Some(Metadata {
+            time: None,
+            ..metadata.clone()
+        })),
+    };
+    let empty = Metadata::default();
+    let metadata = match metadata.as_ref() {
+        Some(metadata) => metadata,
+        None if options.emit_empty_metadata => &empty,
+        None => return Ok(()),
+    };
+    match version {
+        GpxVersion::Gpx10 => write_gpx10_metadata(metadata, options, writer),
+        GpxVersion::Gpx11 | GpxVersion::Unknown => write_gpx11_metadata(metadata, options, writer),
     }
-    let metadata = gpx.metadata.as_ref().unwrap();
+}
+
+fn write_gpx10_metadata<W: Write>(
+    metadata: &Metadata,
+    options: &WriteOptions,
+    writer: &mut GpxWriter<W>,
+) -> Result<()> {
     write_string_if_exists("name", &metadata.name, writer)?;
-    write_string_if_exists("description", &metadata.description, writer)?;
+    write_description_if_exists("description", &metadata.description, options, writer)?;
     if let Some(author) = metadata.author.as_ref() {
         write_string_if_exists("author", &author.name, writer)?;
         write_email_if_exists(&author.email, writer)?;
@@ -96,32 +860,33 @@ fn write_gpx10_metadata<W: Write>(gpx: &Gpx, writer: &mut EventWriter<W>) -> Res
             write_string_if_exists("urlname", &link.text, writer)?;
         }
     }
-    write_string_if_exists("keywords", &metadata.keywords, writer)?;
     write_time_if_exists(&metadata.time, writer)?;
-    write_bounds_if_exists(&metadata.bounds, writer)?;
+    write_string_if_exists("keywords", &metadata.keywords, writer)?;
+    write_bounds_if_exists(&metadata.bounds, options, writer)?;
     Ok(())
 }
 
-fn write_gpx11_metadata<W: Write>(gpx: &Gpx, writer: &mut EventWriter<W>) -> Result<()> {
-    if gpx.metadata.is_none() {
-        return Ok(());
-    }
-    let metadata = gpx.metadata.as_ref().unwrap();
+fn write_gpx11_metadata<W: Write>(
+    metadata: &Metadata,
+    options: &WriteOptions,
+    writer: &mut GpxWriter<W>,
+) -> Result<()> {
     write_xml_event(XmlEvent::start_element("metadata"), writer)?;
     write_string_if_exists("name", &metadata.name, writer)?;
-    write_string_if_exists("description", &metadata.description, writer)?;
+    write_description_if_exists("description", &metadata.description, options, writer)?;
     write_person_if_exists("author", &metadata.author, writer)?;
-    write_string_if_exists("keywords", &metadata.keywords, writer)?;
-    write_time_if_exists(&metadata.time, writer)?;
+    write_copyright_if_exists(&metadata.copyright, writer)?;
     for link in &metadata.links {
-        write_link(&link, writer)?;
+        write_link(link, writer)?;
     }
-    write_bounds_if_exists(&metadata.bounds, writer)?;
+    write_time_if_exists(&metadata.time, writer)?;
+    write_string_if_exists("keywords", &metadata.keywords, writer)?;
+    write_bounds_if_exists(&metadata.bounds, options, writer)?;
     write_xml_event(XmlEvent::end_element(), writer)?;
     Ok(())
 }
 
-fn write_string<W: Write>(key: &str, value: &str, writer: &mut EventWriter<W>) -> Result<()> {
+fn write_string<W: Write>(key: &str, value: &str, writer: &mut GpxWriter<W>) -> Result<()> {
     write_xml_event(XmlEvent::start_element(key), writer)?;
     write_xml_event(XmlEvent::characters(value), writer)?;
     write_xml_event(XmlEvent::end_element(), writer)?;
@@ -131,7 +896,7 @@ fn write_string<W: Write>(key: &str, value: &str, writer: &mut EventWriter<W>) -
 fn write_string_if_exists<W: Write>(
     key: &str,
     value: &Option<String>,
-    writer: &mut EventWriter<W>,
+    writer: &mut GpxWriter<W>,
 ) -> Result<()> {
     if let Some(ref value) = value {
         write_string(key, value, writer)?;
@@ -139,10 +904,51 @@ fn write_string_if_exists<W: Write>(
     Ok(())
 }
 
+/// Writes a description, as a CDATA section with
+/// `WriteOptions::cdata_descriptions`.
+fn write_description_if_exists<W: Write>(
+    key: &str,
+    value: &Option<String>,
+    options: &WriteOptions,
+    writer: &mut GpxWriter<W>,
+) -> Result<()> {
+    match value {
+        Some(ref value) if options.cdata_descriptions => {
+            write_xml_event(XmlEvent::start_element(key), writer)?;
+            for section in cdata_sections(value) {
+                write_xml_event(XmlEvent::cdata(section), writer)?;
+            }
+            write_xml_event(XmlEvent::end_element(), writer)?;
+            Ok(())
+        }
+        _ => write_string_if_exists(key, value, writer),
+    }
+}
+
+/// Formats `value` with at most `precision` decimal places, without trailing
+/// zeros, or else with as many as needed to read it back exactly. Either way
+/// the value is never written in scientific notation.
+fn format_decimal(value: f64, precision: Option<usize>) -> String {
+    let precision = match precision {
+        Some(precision) => precision,
+        None => return value.to_string(),
+    };
+    let mut formatted = format!("{:.*}", precision, value);
+    if formatted.contains('.') {
+        let trimmed = formatted.trim_end_matches('0').trim_end_matches('.').len();
+        formatted.truncate(trimmed);
+    }
+    // Small negative values round to zero.
+    if formatted == "-0" {
+        formatted.remove(0);
+    }
+    formatted
+}
+
 fn write_value_if_exists<W: Write, T: ToString>(
     key: &str,
     value: &Option<T>,
-    writer: &mut EventWriter<W>,
+    writer: &mut GpxWriter<W>,
 ) -> Result<()> {
     if let Some(ref value) = value {
         write_xml_event(XmlEvent::start_element(key), writer)?;
@@ -155,7 +961,7 @@ fn write_value_if_exists<W: Write, T: ToString>(
 
 fn write_email_if_exists<W: Write>(
     email: &Option<String>,
-    writer: &mut EventWriter<W>,
+    writer: &mut GpxWriter<W>,
 ) -> Result<()> {
     if let Some(ref email) = email {
         let mut parts = email.split('@');
@@ -178,7 +984,7 @@ fn write_email_if_exists<W: Write>(
     Ok(())
 }
 
-fn write_link<W: Write>(link: &Link, writer: &mut EventWriter<W>) -> Result<()> {
+fn write_link<W: Write>(link: &Link, writer: &mut GpxWriter<W>) -> Result<()> {
     write_xml_event(
         XmlEvent::start_element("link").attr("href", &link.href),
         writer,
@@ -189,7 +995,7 @@ fn write_link<W: Write>(link: &Link, writer: &mut EventWriter<W>) -> Result<()>
     Ok(())
 }
 
-fn write_link_if_exists<W: Write>(link: &Option<Link>, writer: &mut EventWriter<W>) -> Result<()> {
+fn write_link_if_exists<W: Write>(link: &Option<Link>, writer: &mut GpxWriter<W>) -> Result<()> {
     if let Some(ref link) = link {
         write_link(link, writer)?;
     }
@@ -199,7 +1005,7 @@ fn write_link_if_exists<W: Write>(link: &Option<Link>, writer: &mut EventWriter<
 fn write_person_if_exists<W: Write>(
     key: &str,
     value: &Option<Person>,
-    writer: &mut EventWriter<W>,
+    writer: &mut GpxWriter<W>,
 ) -> Result<()> {
     if let Some(ref value) = value {
         write_xml_event(XmlEvent::start_element(key), writer)?;
@@ -211,13 +1017,31 @@ fn write_person_if_exists<W: Write>(
     Ok(())
 }
 
+fn write_copyright_if_exists<W: Write>(
+    copyright: &Option<Copyright>,
+    writer: &mut GpxWriter<W>,
+) -> Result<()> {
+    if let Some(ref copyright) = copyright {
+        write_xml_event(
+            XmlEvent::start_element("copyright").attr("author", &copyright.author),
+            writer,
+        )?;
+        write_value_if_exists("year", &copyright.year, writer)?;
+        write_string_if_exists("license", &copyright.license, writer)?;
+        write_xml_event(XmlEvent::end_element(), writer)?;
+    }
+    Ok(())
+}
+
 fn write_time_if_exists<W: Write>(
     time: &Option<DateTime<Utc>>,
-    writer: &mut EventWriter<W>,
+    writer: &mut GpxWriter<W>,
 ) -> Result<()> {
     if let Some(ref time) = time {
         write_xml_event(XmlEvent::start_element("time"), writer)?;
-        write_xml_event(XmlEvent::characters(&time.to_rfc3339()), writer)?;
+        // Fractional seconds are only written when there are some.
+        let time = time.to_rfc3339_opts(SecondsFormat::AutoSi, false);
+        write_xml_event(XmlEvent::characters(&time), writer)?;
         write_xml_event(XmlEvent::end_element(), writer)?;
     }
     Ok(())
@@ -225,15 +1049,17 @@ fn write_time_if_exists<W: Write>(
 
 fn write_bounds_if_exists<W: Write>(
     bounds: &Option<Rect<f64>>,
-    writer: &mut EventWriter<W>,
+    options: &WriteOptions,
+    writer: &mut GpxWriter<W>,
 ) -> Result<()> {
     if let Some(ref bounds) = bounds {
+        let precision = options.coord_precision;
         write_xml_event(
             XmlEvent::start_element("bounds")
-                .attr("minlat", &bounds.min().y.to_string())
-                .attr("maxlat", &bounds.max().y.to_string())
-                .attr("minlon", &bounds.min().x.to_string())
-                .attr("maxlon", &bounds.max().x.to_string()),
+                .attr("minlat", &format_decimal(bounds.min().y, precision))
+                .attr("maxlat", &format_decimal(bounds.max().y, precision))
+                .attr("minlon", &format_decimal(bounds.min().x, precision))
+                .attr("maxlon", &format_decimal(bounds.max().x, precision)),
             writer,
         )?;
         write_xml_event(XmlEvent::end_element(), writer)?;
@@ -241,7 +1067,7 @@ fn write_bounds_if_exists<W: Write>(
     Ok(())
 }
 
-fn write_fix_if_exists<W: Write>(fix: &Option<Fix>, writer: &mut EventWriter<W>) -> Result<()> {
+fn write_fix_if_exists<W: Write>(fix: &Option<Fix>, writer: &mut GpxWriter<W>) -> Result<()> {
     if let Some(ref fix) = fix {
         write_xml_event(XmlEvent::start_element("fix"), writer)?;
         let fix_str = match fix {
@@ -258,71 +1084,218 @@ fn write_fix_if_exists<W: Write>(fix: &Option<Fix>, writer: &mut EventWriter<W>)
     Ok(())
 }
 
-fn write_track<W: Write>(track: &Track, writer: &mut EventWriter<W>) -> Result<()> {
+fn write_extensions_if_exists<W: Write>(
+    extensions: &Option<Extensions>,
+    writer: &mut GpxWriter<W>,
+) -> Result<()> {
+    if let Some(ref extensions) = extensions {
+        write_xml_event(XmlEvent::start_element("extensions"), writer)?;
+        for node in &extensions.children {
+            write_extension_node(node, false, writer)?;
+        }
+        write_xml_event(XmlEvent::end_element(), writer)?;
+    }
+    Ok(())
+}
+
+/// Writes an extension node and its content. `unqualified` tells whether the
+/// default namespace in scope is the empty one, rather than the GPX one.
+fn write_extension_node<W: Write>(
+    node: &ExtensionNode,
+    unqualified: bool,
+    writer: &mut GpxWriter<W>,
+) -> Result<()> {
+    match node {
+        ExtensionNode::Text(text) => write_xml_event(XmlEvent::characters(text), writer),
+        ExtensionNode::Element(element) => {
+            let name = Name {
+                local_name: &element.name,
+                namespace: element.namespace.as_deref(),
+                prefix: element.prefix.as_deref(),
+            };
+            let mut event = XmlEvent::start_element(name);
+            // Declare the namespace of the element, which the writer skips if
+            // it is already in scope.
+            let mut children_unqualified = unqualified;
+            match (&element.prefix, &element.namespace) {
+                (Some(prefix), Some(namespace)) => {
+                    event = event.ns(prefix.as_str(), namespace.as_str());
+                }
+                (None, Some(namespace)) => {
+                    event = event.default_ns(namespace.as_str());
+                    children_unqualified = false;
+                }
+                // Keep an element without a namespace out of the GPX one. The
+                // writer never declares the empty namespace itself.
+                (None, None) if !unqualified => {
+                    event = event.attr("xmlns", "");
+                    children_unqualified = true;
+                }
+                _ => {}
+            }
+            for (key, value) in &element.attributes {
+                event = event.attr(key.as_str(), value);
+            }
+            write_xml_event(event, writer)?;
+            for child in &element.children {
+                write_extension_node(child, children_unqualified, writer)?;
+            }
+            write_xml_event(XmlEvent::end_element(), writer)
+        }
+    }
+}
+
+/// Writes a track. With `open`, its last segment is left open after its
+/// points, and the track as well.
+fn write_track<W: Write>(
+    track: &Track,
+    open: bool,
+    version: GpxVersion,
+    options: &WriteOptions,
+    writer: &mut GpxWriter<W>,
+) -> Result<()> {
     write_xml_event(XmlEvent::start_element("trk"), writer)?;
     write_string_if_exists("name", &track.name, writer)?;
     write_string_if_exists("cmt", &track.comment, writer)?;
-    write_string_if_exists("desc", &track.description, writer)?;
+    write_description_if_exists("desc", &track.description, options, writer)?;
     write_string_if_exists("src", &track.source, writer)?;
     for link in &track.links {
         write_link(link, writer)?;
     }
     write_string_if_exists("type", &track._type, writer)?;
-    for segment in &track.segments {
-        write_track_segment(segment, writer)?;
+    if options.embed_stats && version == GpxVersion::Gpx11 {
+        write_xml_event(XmlEvent::start_element("extensions"), writer)?;
+        let nodes = track.extensions.iter().flat_map(|ext| &ext.children);
+        for node in nodes.filter(|node| !is_track_stats_extension(node)) {
+            write_extension_node(node, false, writer)?;
+        }
+        write_track_stats_extension(track, writer)?;
+        write_xml_event(XmlEvent::end_element(), writer)?;
+    } else if version == GpxVersion::Gpx11 {
+        write_extensions_if_exists(&track.extensions, writer)?;
+    }
+    if open {
+        let (last, segments) = match track.segments.split_last() {
+            Some((last, segments)) => (&last.points[..], segments),
+            None => (&[][..], &[][..]),
+        };
+        for segment in segments {
+            write_track_segment(segment, &[], version, options, writer)?;
+        }
+        write_xml_event(XmlEvent::start_element("trkseg"), writer)?;
+        for point in last {
+            write_waypoint("trkpt", point, None, version, options, writer)?;
+        }
+        // The writer holds back the end of a start tag until it knows whether
+        // the element is empty, so make it finish the tag now.
+        return write_xml_event(XmlEvent::characters(""), writer);
+    }
+    let progress = if options.embed_progress && version == GpxVersion::Gpx11 {
+        track_progress(track)
+    } else {
+        Vec::new()
+    };
+    for (i, segment) in track.segments.iter().enumerate() {
+        let progress = progress.get(i).map_or(&[][..], Vec::as_slice);
+        write_track_segment(segment, progress, version, options, writer)?;
     }
     write_xml_event(XmlEvent::end_element(), writer)?;
     Ok(())
 }
 
-fn write_route<W: Write>(route: &Route, writer: &mut EventWriter<W>) -> Result<()> {
+fn write_route<W: Write>(
+    route: &Route,
+    version: GpxVersion,
+    options: &WriteOptions,
+    writer: &mut GpxWriter<W>,
+) -> Result<()> {
     write_xml_event(XmlEvent::start_element("rte"), writer)?;
     write_string_if_exists("name", &route.name, writer)?;
     write_string_if_exists("cmt", &route.comment, writer)?;
-    write_string_if_exists("desc", &route.description, writer)?;
+    write_description_if_exists("desc", &route.description, options, writer)?;
     write_string_if_exists("src", &route.source, writer)?;
     for link in &route.links {
         write_link(link, writer)?;
     }
     write_value_if_exists("number", &route.number, writer)?;
     write_string_if_exists("type", &route._type, writer)?;
+    // GPX 1.0 has no extensions element.
+    if version == GpxVersion::Gpx11 {
+        write_extensions_if_exists(&route.extensions, writer)?;
+    }
     for point in &route.points {
-        write_waypoint("rtept", point, writer)?;
+        write_waypoint("rtept", point, None, version, options, writer)?;
     }
     write_xml_event(XmlEvent::end_element(), writer)?;
     Ok(())
 }
 
+/// Writes a track segment, with the `progress` of its points, if any, see
+/// `WriteOptions::embed_progress`.
 fn write_track_segment<W: Write>(
     segment: &TrackSegment,
-    writer: &mut EventWriter<W>,
+    progress: &[f64],
+    version: GpxVersion,
+    options: &WriteOptions,
+    writer: &mut GpxWriter<W>,
 ) -> Result<()> {
-    write_xml_event(XmlEvent::start_element("trkseg"), writer)?;
-    for point in &segment.points {
-        write_waypoint("trkpt", point, writer)?;
+    let chunks: Vec<&[Waypoint]> = match options.max_points_per_segment {
+        Some(max) if max > 0 && !segment.points.is_empty() => segment.points.chunks(max).collect(),
+        _ => vec![&segment.points],
+    };
+    let mut progress = progress.iter().copied();
+    for points in chunks {
+        write_xml_event(XmlEvent::start_element("trkseg"), writer)?;
+        for point in points {
+            write_waypoint("trkpt", point, progress.next(), version, options, writer)?;
+        }
+        write_xml_event(XmlEvent::end_element(), writer)?;
     }
-    write_xml_event(XmlEvent::end_element(), writer)?;
     Ok(())
 }
 
+/// Writes a waypoint, with its `progress` along its track, if given, see
+/// `WriteOptions::embed_progress`.
 fn write_waypoint<W: Write>(
     tagname: &str,
     waypoint: &Waypoint,
-    writer: &mut EventWriter<W>,
+    progress: Option<f64>,
+    version: GpxVersion,
+    options: &WriteOptions,
+    writer: &mut GpxWriter<W>,
 ) -> Result<()> {
     write_xml_event(
         XmlEvent::start_element(tagname)
-            .attr("lat", &waypoint.point().lat().to_string())
-            .attr("lon", &waypoint.point().lng().to_string()),
+            .attr(
+                "lat",
+                &format_decimal(waypoint.point().lat(), options.coord_precision),
+            )
+            .attr(
+                "lon",
+                &format_decimal(waypoint.point().lng(), options.coord_precision),
+            ),
         writer,
     )?;
-    write_value_if_exists("ele", &waypoint.elevation, writer)?;
-    // TODO: write speed if GPX version == 1.0
-    write_time_if_exists(&waypoint.time, writer)?;
+    if let Some(elevation) = waypoint.elevation {
+        let elevation = format_decimal(elevation, options.elevation_precision);
+        write_string("ele", &elevation, writer)?;
+    }
+    let time = match options.time_grid {
+        Some(grid) => waypoint
+            .time
+            .map(|time| time.duration_round(grid).unwrap_or(time)),
+        None => waypoint.time,
+    };
+    write_time_if_exists(&time, writer)?;
+    // GPX 1.1 has no course or speed.
+    if version == GpxVersion::Gpx10 {
+        write_value_if_exists("course", &waypoint.course, writer)?;
+        write_value_if_exists("speed", &waypoint.speed, writer)?;
+    }
     write_value_if_exists("geoidheight", &waypoint.geoidheight, writer)?;
     write_string_if_exists("name", &waypoint.name, writer)?;
     write_string_if_exists("cmt", &waypoint.comment, writer)?;
-    write_string_if_exists("desc", &waypoint.description, writer)?;
+    write_description_if_exists("desc", &waypoint.description, options, writer)?;
     write_string_if_exists("src", &waypoint.source, writer)?;
     for link in &waypoint.links {
         write_link(link, writer)?;
@@ -336,6 +1309,209 @@ fn write_waypoint<W: Write>(
     write_value_if_exists("pdop", &waypoint.pdop, writer)?;
     write_value_if_exists("ageofdgpsdata", &waypoint.dgps_age, writer)?;
     write_value_if_exists("dgpsid", &waypoint.dgpsid, writer)?;
+    // GPX 1.0 has no extensions element.
+    let has_extensions = waypoint.extensions.is_some()
+        || waypoint.track_point_extension.is_some()
+        || progress.is_some();
+    if has_extensions && version == GpxVersion::Gpx11 {
+        write_xml_event(XmlEvent::start_element("extensions"), writer)?;
+        let nodes = waypoint.extensions.iter().flat_map(|ext| &ext.children);
+        // The fields go back into the element they were read from, if some
+        // of its content was left in the extensions.
+        let mut fields = waypoint.track_point_extension.as_ref();
+        for node in nodes.filter(|node| progress.is_none() || !is_progress_extension(node)) {
+            match (fields, node) {
+                (Some(tpx), ExtensionNode::Element(element)) if holds_fields(element, tpx) => {
+                    write_track_point_extension(tpx, Some(element), writer)?;
+                    fields = None;
+                }
+                _ => write_extension_node(node, false, writer)?,
+            }
+        }
+        if let Some(fields) = fields {
+            write_track_point_extension(fields, None, writer)?;
+        }
+        if let Some(progress) = progress {
+            write_xml_event(
+                XmlEvent::start_element("playback:progress")
+                    .ns("playback", PLAYBACK_EXTENSION_NAMESPACE),
+                writer,
+            )?;
+            write_xml_event(XmlEvent::characters(&progress.to_string()), writer)?;
+            write_xml_event(XmlEvent::end_element(), writer)?;
+        }
+        write_xml_event(XmlEvent::end_element(), writer)?;
+    }
+    write_xml_event(XmlEvent::end_element(), writer)?;
+    Ok(())
+}
+
+/// Namespace URI of the Garmin TrackStatsExtension, which is written with the
+/// `gpxtrkx` prefix as is customary.
+const TRACK_STATS_EXTENSION_NAMESPACE: &str =
+    "http://www.garmin.com/xmlschemas/TrackStatsExtension/v1";
+
+fn is_track_stats_extension(node: &ExtensionNode) -> bool {
+    match node {
+        ExtensionNode::Element(element) => {
+            element.name == "TrackStatsExtension"
+                && element.namespace.as_deref() == Some(TRACK_STATS_EXTENSION_NAMESPACE)
+        }
+        ExtensionNode::Text(_) => false,
+    }
+}
+
+/// Writes the distance (in meters) and the elapsed time (in seconds) of the
+/// track, the latter only if it is known.
+fn write_track_stats_extension<W: Write>(track: &Track, writer: &mut GpxWriter<W>) -> Result<()> {
+    let distance: f64 = track
+        .segments
+        .iter()
+        .map(|seg| seg.linestring().haversine_length())
+        .sum();
+    let elapsed = track
+        .elapsed_time()
+        .map(|elapsed| elapsed.num_milliseconds() as f64 / 1000.0);
+
+    write_xml_event(
+        XmlEvent::start_element("gpxtrkx:TrackStatsExtension")
+            .ns("gpxtrkx", TRACK_STATS_EXTENSION_NAMESPACE),
+        writer,
+    )?;
+    // In the order of the schema.
+    write_string("gpxtrkx:Distance", &distance.to_string(), writer)?;
+    write_value_if_exists("gpxtrkx:TotalElapsedTime", &elapsed, writer)?;
+    write_xml_event(XmlEvent::end_element(), writer)?;
+    Ok(())
+}
+
+/// Namespace URI of the `progress` extension element written with
+/// `WriteOptions::embed_progress`, which is written with the `playback`
+/// prefix.
+pub const PLAYBACK_EXTENSION_NAMESPACE: &str =
+    "https://github.com/georust/gpx/PlaybackExtension/v1";
+
+fn is_progress_extension(node: &ExtensionNode) -> bool {
+    match node {
+        ExtensionNode::Element(element) => {
+            element.name == "progress"
+                && element.namespace.as_deref() == Some(PLAYBACK_EXTENSION_NAMESPACE)
+        }
+        ExtensionNode::Text(_) => false,
+    }
+}
+
+/// Gives the fraction of the length of the track traveled at each point of
+/// each of its segments, see `WriteOptions::embed_progress`. The gaps between
+/// segments are not part of the length, and all points are at 0 if the track
+/// has no length.
+fn track_progress(track: &Track) -> Vec<Vec<f64>> {
+    let mut traveled = 0.0;
+    let mut progress: Vec<Vec<f64>> = track
+        .segments
+        .iter()
+        .map(|seg| {
+            let mut previous = None;
+            seg.points
+                .iter()
+                .map(|wpt| {
+                    let point = wpt.point();
+                    if let Some(previous) = previous {
+                        traveled += point.haversine_distance(&previous);
+                    }
+                    previous = Some(point);
+                    traveled
+                })
+                .collect()
+        })
+        .collect();
+    for distance in progress.iter_mut().flatten() {
+        *distance = if traveled > 0.0 {
+            *distance / traveled
+        } else {
+            0.0
+        };
+    }
+    progress
+}
+
+/// Namespace URI of version 1 of the Garmin TrackPointExtension, in which
+/// fields without a namespace are written with the `gpxtpx` prefix as is
+/// customary.
+const TRACK_POINT_EXTENSION_NAMESPACE: &str =
+    "http://www.garmin.com/xmlschemas/TrackPointExtension/v1";
+
+/// Tells whether `element` is the TrackPointExtension element that `fields`
+/// were read from.
+fn holds_fields(element: &ExtensionElement, fields: &TrackPointExtension) -> bool {
+    element.name == "TrackPointExtension"
+        && fields.namespace.is_some()
+        && element.namespace == fields.namespace
+        && element.prefix == fields.prefix
+}
+
+/// Writes the TrackPointExtension fields in the namespace they were read
+/// from, along with the rest of the `original` element they were read from.
+fn write_track_point_extension<W: Write>(
+    fields: &TrackPointExtension,
+    original: Option<&ExtensionElement>,
+    writer: &mut GpxWriter<W>,
+) -> Result<()> {
+    let namespace = fields
+        .namespace
+        .as_deref()
+        .unwrap_or(TRACK_POINT_EXTENSION_NAMESPACE);
+    // Fields read from a default namespace are written back without a prefix.
+    let prefix = match (&fields.prefix, &fields.namespace) {
+        (Some(prefix), _) => Some(prefix.as_str()),
+        (None, Some(_)) => None,
+        (None, None) => Some("gpxtpx"),
+    };
+    let qualified = |local_name: &str| match prefix {
+        Some(prefix) => format!("{}:{}", prefix, local_name),
+        None => local_name.to_string(),
+    };
+    let children = original.map_or(&[][..], |element| &element.children);
+    let wrapper = children.iter().position(|child| {
+        matches!(child, ExtensionNode::Element(element)
+            if element.name == "Extensions" && element.namespace.as_deref() == Some(namespace))
+    });
+
+    let name = qualified("TrackPointExtension");
+    let mut event = XmlEvent::start_element(name.as_str());
+    event = match prefix {
+        Some(prefix) => event.ns(prefix, namespace),
+        None => event.default_ns(namespace),
+    };
+    for (key, value) in original.iter().flat_map(|element| &element.attributes) {
+        event = event.attr(key.as_str(), value);
+    }
+    write_xml_event(event, writer)?;
+    if fields.wrapped {
+        write_xml_event(
+            XmlEvent::start_element(qualified("Extensions").as_str()),
+            writer,
+        )?;
+    }
+    // In the order of the schema.
+    write_value_if_exists(&qualified("atemp"), &fields.atemp, writer)?;
+    write_value_if_exists(&qualified("wtemp"), &fields.wtemp, writer)?;
+    write_value_if_exists(&qualified("depth"), &fields.depth, writer)?;
+    write_value_if_exists(&qualified("hr"), &fields.hr, writer)?;
+    write_value_if_exists(&qualified("cad"), &fields.cad, writer)?;
+    if fields.wrapped {
+        if let Some(ExtensionNode::Element(element)) = wrapper.map(|i| &children[i]) {
+            for child in &element.children {
+                write_extension_node(child, false, writer)?;
+            }
+        }
+        write_xml_event(XmlEvent::end_element(), writer)?;
+    }
+    for (i, child) in children.iter().enumerate() {
+        if !fields.wrapped || Some(i) != wrapper {
+            write_extension_node(child, false, writer)?;
+        }
+    }
     write_xml_event(XmlEvent::end_element(), writer)?;
     Ok(())
 }
@@ -0,0 +1,1517 @@
+// Tests for the analysis helpers on tracks, segments and documents.
+
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::BufReader;
+
+use assert_approx_eq::assert_approx_eq;
+use chrono::{Duration, FixedOffset, TimeZone, Utc};
+use geo::algorithm::area::Area;
+use geo::algorithm::haversine_distance::HaversineDistance;
+use geo::algorithm::haversine_length::HaversineLength;
+use geo_types::Point;
+
+use gpx::{
+    read, Fix, Metadata, Route, Track, TrackPointExtension, TrackSegment, Waypoint, WaypointBuilder,
+};
+
+/// Creates a waypoint at the given position, `seconds` after a fixed start.
+fn timed_point(lon: f64, lat: f64, seconds: i64) -> Waypoint {
+    let mut wpt = Waypoint::new(Point::new(lon, lat));
+    wpt.time =
+        Some(Utc.with_ymd_and_hms(2020, 6, 1, 8, 0, 0).unwrap() + Duration::seconds(seconds));
+    wpt
+}
+
+fn track_from_points(points: Vec<Waypoint>) -> Track {
+    let mut segment = TrackSegment::new();
+    segment.points = points;
+    let mut track = Track::new();
+    track.segments.push(segment);
+    track
+}
+
+#[test]
+fn track_start_and_end_points() {
+    let mut track = Track::new();
+    assert_eq!(track.start_point(), None);
+    assert_eq!(track.end_point(), None);
+
+    // Empty segments at either end are skipped.
+    track.segments.push(TrackSegment::new());
+    for points in &[[(8.0, 47.0), (8.1, 47.1)], [(8.2, 47.2), (8.3, 47.3)]] {
+        let mut segment = TrackSegment::new();
+        for &(lon, lat) in points {
+            segment.points.push(Waypoint::new(Point::new(lon, lat)));
+        }
+        track.segments.push(segment);
+    }
+    track.segments.push(TrackSegment::new());
+
+    assert_eq!(track.start_point(), Some(Point::new(8.0, 47.0)));
+    assert_eq!(track.end_point(), Some(Point::new(8.3, 47.3)));
+}
+
+#[test]
+fn track_moving_ratio_with_pause() {
+    // Two minutes of walking north (about 1.1 m/s) around a two minute pause.
+    let track = track_from_points(vec![
+        timed_point(0.0, 0.0, 0),
+        timed_point(0.0, 0.0006, 60),
+        timed_point(0.0, 0.0006, 120),
+        timed_point(0.0, 0.0006, 180),
+        timed_point(0.0, 0.0012, 240),
+    ]);
+
+    assert_approx_eq!(track.moving_ratio(0.5).unwrap(), 0.5);
+    // Nothing is fast enough at 10 m/s.
+    assert_approx_eq!(track.moving_ratio(10.0).unwrap(), 0.0);
+}
+
+#[test]
+fn track_moving_ratio_needs_time() {
+    let track = track_from_points(vec![
+        Waypoint::new(Point::new(0.0, 0.0)),
+        Waypoint::new(Point::new(0.0, 0.001)),
+    ]);
+
+    assert_eq!(track.moving_ratio(0.5), None);
+}
+
+#[test]
+fn track_distance_matrix() {
+    let track = track_from_points(vec![
+        Waypoint::new(Point::new(8.0, 47.0)),
+        Waypoint::new(Point::new(8.0, 47.001)),
+    ]);
+    let other = track_from_points(vec![
+        Waypoint::new(Point::new(8.0, 47.0)),
+        Waypoint::new(Point::new(8.0, 47.001)),
+        Waypoint::new(Point::new(8.0, 47.002)),
+    ]);
+
+    let matrix = track.distance_matrix(&other);
+    assert_eq!(matrix.len(), 2);
+    assert!(matrix.iter().all(|row| row.len() == 3));
+    assert_eq!(matrix[0][0], 0.0);
+    assert_eq!(matrix[1][1], 0.0);
+    assert_approx_eq!(matrix[0][1], 111.2, 0.1);
+    assert_approx_eq!(matrix[0][2], 222.4, 0.1);
+    assert_approx_eq!(matrix[1][0], matrix[0][1]);
+
+    assert!(track
+        .distance_matrix(&Track::new())
+        .iter()
+        .all(Vec::is_empty));
+    assert!(Track::new().distance_matrix(&other).is_empty());
+}
+
+#[test]
+fn track_fix_distribution() {
+    let fixes = [
+        Some(Fix::ThreeDimensional),
+        Some(Fix::ThreeDimensional),
+        Some(Fix::TwoDimensional),
+        Some(Fix::None),
+        Some(Fix::Other(String::from("unknown"))),
+        None,
+    ];
+    let track = track_from_points(
+        fixes
+            .iter()
+            .map(|fix| {
+                let mut wpt = Waypoint::new(Point::new(8.0, 47.0));
+                wpt.fix = fix.clone();
+                wpt
+            })
+            .collect(),
+    );
+
+    let distribution = track.fix_distribution();
+    assert_eq!(distribution.len(), 5);
+    assert_approx_eq!(distribution[&Some(Fix::ThreeDimensional)], 2.0 / 6.0);
+    assert_approx_eq!(distribution[&Some(Fix::TwoDimensional)], 1.0 / 6.0);
+    assert_approx_eq!(distribution[&Some(Fix::None)], 1.0 / 6.0);
+    // A fix named unknown is kept apart from points without a fix.
+    assert_approx_eq!(
+        distribution[&Some(Fix::Other(String::from("unknown")))],
+        1.0 / 6.0
+    );
+    assert_approx_eq!(distribution[&None], 1.0 / 6.0);
+    assert_approx_eq!(distribution.values().sum::<f64>(), 1.0);
+
+    assert!(Track::new().fix_distribution().is_empty());
+}
+
+#[test]
+fn track_median_sample_interval() {
+    // Every second, with a long pause.
+    let mut track = track_from_points(vec![
+        timed_point(8.0, 47.0, 0),
+        timed_point(8.0, 47.0, 1),
+        timed_point(8.0, 47.0, 2),
+        Waypoint::new(Point::new(8.0, 47.0)),
+        timed_point(8.0, 47.0, 600),
+        timed_point(8.0, 47.0, 601),
+    ]);
+    assert_eq!(track.median_sample_interval(), Some(Duration::seconds(1)));
+
+    // With an even number of intervals, the two in the middle are averaged.
+    let mut segment = TrackSegment::new();
+    segment.points.push(timed_point(8.0, 47.0, 700));
+    segment.points.push(timed_point(8.0, 47.0, 703));
+    segment.points.push(timed_point(8.0, 47.0, 708));
+    track.segments.push(segment);
+    assert_eq!(
+        track.median_sample_interval(),
+        Some(Duration::milliseconds(2_000))
+    );
+
+    let untimed = track_from_points(vec![Waypoint::new(Point::new(8.0, 47.0))]);
+    assert_eq!(untimed.median_sample_interval(), None);
+}
+
+#[test]
+fn track_merge_short_segments() {
+    let segment = |first: i64, count: i64| {
+        let mut segment = TrackSegment::new();
+        for i in first..first + count {
+            segment.points.push(timed_point(8.0, 47.0, i));
+        }
+        segment
+    };
+    let mut track = Track::new();
+    track.segments = vec![
+        segment(0, 1),
+        segment(1, 4),
+        segment(5, 2),
+        segment(7, 5),
+        segment(12, 1),
+    ];
+
+    track.merge_short_segments(3);
+    let lengths: Vec<usize> = track.segments.iter().map(|seg| seg.points.len()).collect();
+    assert_eq!(lengths, vec![7, 6]);
+    let times: Vec<_> = track
+        .segments
+        .iter()
+        .flat_map(|seg| seg.points.iter())
+        .map(|wpt| wpt.time)
+        .collect();
+    assert_eq!(times.len(), 13);
+    assert!(times.windows(2).all(|pair| pair[0] < pair[1]));
+
+    // A track too short as a whole ends up with a single segment.
+    let mut track = Track::new();
+    track.segments = vec![segment(0, 1), segment(1, 1)];
+    track.merge_short_segments(3);
+    assert_eq!(track.segments.len(), 1);
+    assert_eq!(track.segments[0].points.len(), 2);
+}
+
+#[test]
+fn track_segment_farthest_point_out_and_back() {
+    let mut segment = TrackSegment::new();
+    for lat in &[0.0, 0.01, 0.02, 0.03, 0.02, 0.01, 0.0] {
+        segment.points.push(Waypoint::new(Point::new(0.0, *lat)));
+    }
+
+    let (farthest, distance) = segment.farthest_point().unwrap();
+    assert_eq!(farthest.point(), Point::new(0.0, 0.03));
+    // 0.03 degrees of latitude is roughly 3.3 km.
+    assert!(distance > 3300.0 && distance < 3350.0);
+
+    assert!(TrackSegment::new().farthest_point().is_none());
+}
+
+#[test]
+fn gpx_geometry_eq_ignores_metadata() {
+    let file = File::open("tests/fixtures/wikipedia_example.gpx").unwrap();
+    let original = read(BufReader::new(file)).unwrap();
+
+    let mut edited = original.clone();
+    edited.creator = Some(String::from("another app"));
+    edited.metadata = Some(Metadata {
+        name: Some(String::from("renamed")),
+        ..Default::default()
+    });
+    edited.tracks[0].name = None;
+    edited.tracks[0].segments[0].points[0].elevation = Some(1000.0);
+
+    assert_ne!(original, edited);
+    assert!(original.geometry_eq(&edited));
+
+    // Moving a point changes the geometry.
+    edited.tracks[0].segments[0].points[0] = Waypoint::new(Point::new(0.0, 0.0));
+    assert!(!original.geometry_eq(&edited));
+
+    // So does dropping a segment.
+    let mut fewer_segments = original.clone();
+    fewer_segments.tracks[0].segments.clear();
+    assert!(!original.geometry_eq(&fewer_segments));
+}
+
+#[test]
+fn gpx_snap_to_grid() {
+    let file = File::open("tests/fixtures/garmin-activity.gpx").unwrap();
+    let mut gpx = read(BufReader::new(file)).unwrap();
+    let grid = 0.01;
+
+    gpx.snap_to_grid(grid);
+
+    let is_on_grid = |value: f64| {
+        let cells = value / grid;
+        (cells - cells.round()).abs() < 1e-6
+    };
+    let points = &gpx.tracks[0].segments[0].points;
+    assert!(!points.is_empty());
+    for point in points {
+        assert!(is_on_grid(point.point().lng()));
+        assert!(is_on_grid(point.point().lat()));
+    }
+    // The activity is around (-121.97, 37.24), so it stays at most one cell away.
+    assert!((points[0].point().lng() + 121.97).abs() <= grid + 1e-9);
+    assert!((points[0].point().lat() - 37.24).abs() <= grid + 1e-9);
+}
+
+#[test]
+fn gpx_shift_times() {
+    let file = File::open("tests/fixtures/garmin-activity.gpx").unwrap();
+    let mut gpx = read(BufReader::new(file)).unwrap();
+    gpx.waypoints.push(timed_point(8.0, 47.0, 0));
+    let mut route = Route::new();
+    route.points.push(timed_point(8.0, 47.0, 60));
+    route.points.push(Waypoint::new(Point::new(8.1, 47.1)));
+    gpx.routes.push(route);
+    let original = gpx.clone();
+    let times = |gpx: &gpx::Gpx| {
+        let mut times = vec![gpx.metadata.as_ref().unwrap().time];
+        times.extend(gpx.waypoints.iter().map(|wpt| wpt.time));
+        times.extend(gpx.tracks[0].segments[0].points.iter().map(|wpt| wpt.time));
+        times.extend(
+            gpx.routes
+                .iter()
+                .flat_map(|route| route.points.iter().map(|wpt| wpt.time)),
+        );
+        times
+    };
+    assert!(original.metadata.as_ref().unwrap().time.is_some());
+
+    gpx.shift_times(Duration::hours(1)).unwrap();
+    for (shifted, time) in times(&gpx).into_iter().zip(times(&original)) {
+        assert_eq!(shifted, time.map(|time| time + Duration::hours(1)));
+    }
+    // Points without a time stay so.
+    assert_eq!(gpx.routes.last().unwrap().points[1].time, None);
+
+    gpx.shift_times(Duration::hours(-1)).unwrap();
+    assert_eq!(gpx, original);
+
+    // Nothing is shifted if any time would go out of range.
+    assert!(gpx.shift_times(Duration::MAX).is_err());
+    assert_eq!(gpx, original);
+}
+
+#[test]
+fn gpx_refresh_bounds() {
+    let file = File::open("tests/fixtures/garmin-activity.gpx").unwrap();
+    let mut gpx = read(BufReader::new(file)).unwrap();
+    gpx.refresh_bounds();
+    let before = gpx.metadata.as_ref().unwrap().bounds.unwrap();
+
+    // Move a point north-east of everything else.
+    gpx.tracks[0].segments[0].points[3] =
+        Waypoint::new(Point::new(before.max().x + 0.5, before.max().y + 0.25));
+    gpx.refresh_bounds();
+
+    let after = gpx.metadata.as_ref().unwrap().bounds.unwrap();
+    assert_eq!(after.min(), before.min());
+    assert_eq!(after.max().x, before.max().x + 0.5);
+    assert_eq!(after.max().y, before.max().y + 0.25);
+
+    gpx.tracks.clear();
+    gpx.refresh_bounds();
+    assert_eq!(gpx.metadata.unwrap().bounds, None);
+}
+
+#[test]
+fn gpx_extent_km() {
+    let mut gpx = gpx::Gpx::default();
+    assert_eq!(gpx.extent_km(), None);
+
+    // At 60 degrees of latitude, a degree of longitude is half as long as one
+    // of latitude, about 55.6 km.
+    gpx.waypoints.push(Waypoint::new(Point::new(10.0, 59.5)));
+    gpx.waypoints.push(Waypoint::new(Point::new(11.0, 60.5)));
+    let (width, height) = gpx.extent_km().unwrap();
+    assert_approx_eq!(width, 55.6, 0.1);
+    assert_approx_eq!(height, 111.2, 0.1);
+}
+
+#[test]
+fn gpx_symbols_used() {
+    let xml = r#"<gpx version="1.1">
+    <wpt lat="1.0" lon="1.0"><sym>Flag, Blue</sym></wpt>
+    <wpt lat="2.0" lon="2.0"><sym>Summit</sym></wpt>
+    <wpt lat="3.0" lon="3.0"><sym>Flag, Blue</sym></wpt>
+    <wpt lat="4.0" lon="4.0"></wpt>
+    <rte>
+        <rtept lat="5.0" lon="5.0"><sym>Parking Area</sym></rtept>
+    </rte>
+</gpx>"#;
+    let gpx = read(xml.as_bytes()).unwrap();
+
+    let symbols = gpx.symbols_used();
+    let expected: HashSet<String> = ["Flag, Blue", "Summit", "Parking Area"]
+        .iter()
+        .map(|s| s.to_string())
+        .collect();
+    assert_eq!(symbols, expected);
+}
+
+#[test]
+fn gpx_counts() {
+    let xml = r#"<gpx version="1.1">
+    <wpt lat="1.0" lon="1.0"></wpt>
+    <wpt lat="2.0" lon="2.0"></wpt>
+    <trk>
+        <trkseg><trkpt lat="1.0" lon="1.0"></trkpt></trkseg>
+        <trkseg><trkpt lat="1.0" lon="1.0"></trkpt></trkseg>
+    </trk>
+    <trk>
+        <trkseg></trkseg>
+    </trk>
+    <trk></trk>
+</gpx>"#;
+    let gpx = read(xml.as_bytes()).unwrap();
+
+    assert_eq!(gpx.track_count(), 3);
+    assert_eq!(gpx.segment_count(), 3);
+    assert_eq!(gpx.waypoint_count(), 2);
+}
+
+#[test]
+fn track_splits_per_kilometer() {
+    // Along the equator, 0.01 degrees of longitude is about 1112 m.
+    let track = track_from_points(vec![
+        timed_point(0.0, 0.0, 0),
+        timed_point(0.01, 0.0, 300),
+        timed_point(0.02, 0.0, 900),
+        timed_point(0.025, 0.0, 1200),
+    ]);
+
+    let splits = track.splits(1000.0);
+    assert_eq!(splits.len(), 3);
+
+    // The first kilometer is covered at a constant 300 s per 0.01 degrees.
+    assert_approx_eq!(splits[0].distance, 1000.0);
+    assert!((splits[0].elapsed.num_seconds() - 270).abs() <= 1);
+    assert_eq!(splits[0].pace, splits[0].elapsed);
+
+    // The second one starts at the end of the first point pair, and slows down.
+    assert_approx_eq!(splits[1].distance, 1000.0);
+    assert!(splits[1].elapsed > splits[0].elapsed);
+
+    // The rest of the total 2780 m, which is a partial split.
+    assert!(splits[2].distance > 770.0 && splits[2].distance < 790.0);
+    assert!(splits[2].pace > splits[2].elapsed);
+
+    let total: i64 = splits.iter().map(|s| s.elapsed.num_milliseconds()).sum();
+    assert!((total - 1_200_000).abs() <= 2);
+}
+
+#[test]
+fn track_is_stationary() {
+    // GPS noise of a few meters around a treadmill.
+    let treadmill = track_from_points(vec![
+        Waypoint::new(Point::new(8.54, 47.37)),
+        Waypoint::new(Point::new(8.54003, 47.37002)),
+        Waypoint::new(Point::new(8.53998, 47.36999)),
+        Waypoint::new(Point::new(8.54001, 47.37003)),
+    ]);
+    assert!(treadmill.is_stationary(10.0));
+    assert!(!treadmill.is_stationary(1.0));
+
+    let run = track_from_points(vec![
+        Waypoint::new(Point::new(8.54, 47.37)),
+        Waypoint::new(Point::new(8.55, 47.37)),
+    ]);
+    assert!(!run.is_stationary(10.0));
+}
+
+#[test]
+fn track_segment_vam() {
+    let mut segment = TrackSegment::new();
+    for (i, &ele) in [100.0, 150.0, 140.0, 200.0].iter().enumerate() {
+        let mut wpt = timed_point(8.0, 47.0 + i as f64 * 0.001, i as i64 * 60);
+        wpt.elevation = Some(ele);
+        segment.points.push(wpt);
+    }
+    // A ten minute break at the top does not count as moving time.
+    let mut rest = timed_point(8.0, 47.003, 780);
+    rest.elevation = Some(200.0);
+    segment.points.push(rest);
+
+    // 110 meters of ascent in three minutes of moving.
+    assert_approx_eq!(segment.vam().unwrap(), 2200.0);
+
+    for wpt in segment.points.iter_mut() {
+        wpt.elevation = None;
+    }
+    assert_eq!(segment.vam(), None);
+}
+
+#[test]
+fn track_min_area_rect() {
+    let track = track_from_points(vec![
+        Waypoint::new(Point::new(0.0, 0.0)),
+        Waypoint::new(Point::new(1.0, 1.0)),
+        Waypoint::new(Point::new(1.0, 1.2)),
+        Waypoint::new(Point::new(2.0, 2.0)),
+    ]);
+    let rect = track.min_area_rect();
+    assert_eq!(rect.exterior().0.len(), 5);
+
+    // The rectangle runs along the diagonal: it is as long as the line and
+    // as wide as the offset of the point off it.
+    let width = 0.2 / 2f64.sqrt();
+    let length = 2.0 * 2f64.sqrt();
+    assert_approx_eq!(rect.unsigned_area(), width * length);
+    // The axis-aligned bounds would cover four square degrees.
+    assert!(rect.unsigned_area() < 1.0);
+
+    let empty = Track::new().min_area_rect();
+    assert!(empty.exterior().0.is_empty());
+}
+
+#[test]
+fn track_graded_pace() {
+    // Ten steps of about 111 meters, thirty seconds each.
+    let run = |climb: f64| {
+        track_from_points(
+            (0..=10)
+                .map(|i| {
+                    let mut wpt = timed_point(8.0, 47.0 + i as f64 * 0.001, i * 30);
+                    wpt.elevation = Some(400.0 + i as f64 * climb);
+                    wpt
+                })
+                .collect(),
+        )
+    };
+
+    let flat = run(0.0).graded_pace().unwrap();
+    let step = Point::new(8.0, 47.0).haversine_distance(&Point::new(8.0, 47.001));
+    assert_approx_eq!(
+        flat.num_milliseconds() as f64 / 1000.0,
+        30.0 * 1000.0 / step,
+        0.01
+    );
+
+    // The same pace uphill took more effort, so it is worth a faster pace on
+    // flat ground.
+    let hilly = run(10.0).graded_pace().unwrap();
+    assert!(hilly < flat);
+
+    assert_eq!(Track::new().graded_pace(), None);
+}
+
+#[test]
+fn track_segment_detect_stops() {
+    let mut segment = TrackSegment::new();
+    // Walking north, a minute per 100 meters or so.
+    for i in 0..5 {
+        segment
+            .points
+            .push(timed_point(8.0, 47.0 + i as f64 * 0.001, i * 60));
+    }
+    // A quarter of an hour at a cafe, with a few meters of GPS noise.
+    for i in 0..4 {
+        let noise = if i % 2 == 0 { 0.00002 } else { -0.00002 };
+        segment
+            .points
+            .push(timed_point(8.0 + noise, 47.004, 300 + i * 300));
+    }
+    // And onwards.
+    for i in 1..4 {
+        segment
+            .points
+            .push(timed_point(8.0, 47.004 + i as f64 * 0.001, 1200 + i * 60));
+    }
+
+    let stops = segment.detect_stops(20.0, Duration::minutes(5));
+    assert_eq!(stops.len(), 1);
+    let stop = &stops[0];
+    // The last point of the walk is where the stop begins.
+    assert_eq!((stop.start, stop.end), (4, 8));
+    assert_eq!(stop.duration, Duration::minutes(16));
+    assert_approx_eq!(stop.center.lat(), 47.004);
+
+    assert!(segment.detect_stops(20.0, Duration::minutes(30)).is_empty());
+}
+
+#[test]
+fn track_locate_point() {
+    // North for about 1.1 km, then east.
+    let track = track_from_points(vec![
+        Waypoint::new(Point::new(8.0, 47.0)),
+        Waypoint::new(Point::new(8.0, 47.01)),
+        Waypoint::new(Point::new(8.01, 47.01)),
+    ]);
+    let first_edge = Point::new(8.0, 47.0).haversine_distance(&Point::new(8.0, 47.01));
+
+    // Some 76 meters east of the middle of the first edge.
+    let reference = track.locate_point(Point::new(8.001, 47.005)).unwrap();
+    assert_approx_eq!(reference.distance, first_edge / 2.0, 1.0);
+    assert_approx_eq!(reference.offset, 75.8, 1.0);
+    assert_approx_eq!(reference.point.lng(), 8.0);
+
+    // Past the end, the closest point is the end itself.
+    let reference = track.locate_point(Point::new(8.02, 47.01)).unwrap();
+    assert_approx_eq!(reference.point.lng(), 8.01);
+    assert!(reference.distance > first_edge);
+
+    assert_eq!(Track::new().locate_point(Point::new(8.0, 47.0)), None);
+}
+
+#[test]
+fn track_straightness() {
+    let straight = track_from_points(
+        (0..5)
+            .map(|i| Waypoint::new(Point::new(8.0, 47.0 + i as f64 * 0.001)))
+            .collect(),
+    );
+    assert_approx_eq!(straight.straightness().unwrap(), 1.0, 1e-6);
+
+    // Zigzagging north at 45 degrees covers about 1.4 times the distance:
+    // at this latitude, 0.001 degrees of longitude are as long as 0.000682
+    // degrees of latitude.
+    let zigzag = track_from_points(
+        (0..5)
+            .map(|i| {
+                let lon = if i % 2 == 0 {
+                    8.0
+                } else {
+                    8.001 / 0.682 * 0.682
+                };
+                Waypoint::new(Point::new(lon, 47.0 + i as f64 * 0.000682))
+            })
+            .collect(),
+    );
+    let straightness = zigzag.straightness().unwrap();
+    assert_approx_eq!(straightness, 1.0 / 2f64.sqrt(), 0.01);
+
+    assert_eq!(Track::new().straightness(), None);
+}
+
+#[test]
+fn track_categorized_climbs() {
+    // A climb of 110 m over twelve steps with a short dip, a descent of 80 m,
+    // a gentler climb of 60 m and a flat finish. Each step is about 111 m.
+    let mut profile = vec![100.0];
+    profile.extend((1..=10).map(|i| 100.0 + i as f64 * 10.0));
+    profile.extend(&[195.0, 210.0]);
+    profile.extend((1..=4).map(|i| 210.0 - i as f64 * 20.0));
+    profile.extend((1..=10).map(|i| 130.0 + i as f64 * 6.0));
+    profile.extend(&[190.0, 190.0]);
+    let track = track_from_points(
+        profile
+            .iter()
+            .enumerate()
+            .map(|(i, &ele)| {
+                let mut wpt = Waypoint::new(Point::new(8.0, 47.0 + i as f64 * 0.001));
+                wpt.elevation = Some(ele);
+                wpt
+            })
+            .collect(),
+    );
+    let step = Point::new(8.0, 47.0).haversine_distance(&Point::new(8.0, 47.001));
+
+    let climbs = track.categorized_climbs(50.0, 0.03);
+    assert_eq!(climbs.len(), 2);
+    // The dip of 5 m does not end the first climb.
+    assert_approx_eq!(climbs[0].gain, 110.0);
+    assert_approx_eq!(climbs[0].length, 12.0 * step, 1.0);
+    assert_eq!(climbs[0].start, Point::new(8.0, 47.0));
+    assert_approx_eq!(climbs[1].gain, 60.0);
+    assert_approx_eq!(climbs[1].grade, 6.0 / step, 1e-3);
+
+    // Only the first climb is steep enough.
+    assert_eq!(track.categorized_climbs(50.0, 0.07).len(), 1);
+    assert!(track.categorized_climbs(150.0, 0.0).is_empty());
+}
+
+#[test]
+#[cfg(feature = "rstar")]
+fn gpx_index_query_radius() {
+    let mut gpx = gpx::Gpx::default();
+    // Points about 111 meters apart northwards, in two tracks.
+    gpx.tracks.push(track_from_points(vec![
+        Waypoint::new(Point::new(8.0, 47.0)),
+        Waypoint::new(Point::new(8.0, 47.001)),
+        Waypoint::new(Point::new(8.0, 47.002)),
+    ]));
+    gpx.tracks
+        .push(track_from_points(vec![Waypoint::new(Point::new(
+            8.0, 47.003,
+        ))]));
+    gpx.waypoints.push(Waypoint::new(Point::new(8.0, 47.001)));
+
+    let index = gpx.build_index();
+    assert_eq!(index.len(), 4);
+
+    let mut near: Vec<_> = index
+        .query_radius(Point::new(8.0, 47.0015), 100.0)
+        .into_iter()
+        .map(|found| (found.track, found.point))
+        .collect();
+    near.sort();
+    assert_eq!(near, vec![(0, 1), (0, 2)]);
+
+    let all = index.query_radius(Point::new(8.0, 47.0015), 500.0);
+    assert_eq!(all.len(), 4);
+    assert!(index.query_radius(Point::new(9.0, 47.0), 1000.0).is_empty());
+
+    let nearest = index.nearest(Point::new(8.001, 47.0029)).unwrap();
+    assert_eq!((nearest.track, nearest.segment, nearest.point), (1, 0, 0));
+    assert!(gpx::Gpx::default()
+        .build_index()
+        .nearest(Point::new(0.0, 0.0))
+        .is_none());
+}
+
+#[test]
+#[cfg(feature = "geojson")]
+fn track_to_simplified_geojson() {
+    // A straight line with a little noise, and a turn at the end.
+    let mut track = track_from_points(vec![
+        Waypoint::new(Point::new(8.0, 47.0)),
+        Waypoint::new(Point::new(8.00001, 47.001)),
+        Waypoint::new(Point::new(8.0, 47.002)),
+        Waypoint::new(Point::new(7.99999, 47.003)),
+        Waypoint::new(Point::new(8.0, 47.004)),
+        Waypoint::new(Point::new(8.004, 47.004)),
+    ]);
+    track.name = Some(String::from("Commute"));
+
+    let feature = track.to_simplified_geojson(0.0001);
+    assert_eq!(
+        feature.property("name"),
+        Some(&geojson::JsonValue::from("Commute"))
+    );
+    match feature.geometry.unwrap().value {
+        geojson::Value::LineString(coordinates) => assert_eq!(
+            coordinates,
+            vec![vec![8.0, 47.0], vec![8.0, 47.004], vec![8.004, 47.004]]
+        ),
+        value => panic!("unexpected geometry {:?}", value),
+    }
+}
+
+#[test]
+fn track_segment_smoothed_speeds() {
+    let mut segment = TrackSegment::new();
+    // About 111 meters at a time, alternately in 5 and 15 seconds.
+    for i in 0..20 {
+        let seconds = (i / 2) * 20 + (i % 2) * 5;
+        segment
+            .points
+            .push(timed_point(8.0, 47.0 + 0.001 * i as f64, seconds));
+    }
+    segment.points.push(Waypoint::new(Point::new(8.0, 47.02)));
+
+    let variance = |speeds: &[f64]| {
+        let mean = speeds.iter().sum::<f64>() / speeds.len() as f64;
+        speeds.iter().map(|s| (s - mean).powi(2)).sum::<f64>() / speeds.len() as f64
+    };
+    let raw: Vec<f64> = segment
+        .point_pairs()
+        .filter_map(|(from, to)| {
+            let seconds = (to.time? - from.time?).num_seconds() as f64;
+            Some(from.point().haversine_distance(&to.point()) / seconds)
+        })
+        .collect();
+
+    let smoothed = segment.smoothed_speeds(Duration::seconds(60));
+    assert_eq!(smoothed.len(), 21);
+    // Without a time, the last point has no speed.
+    assert_eq!(smoothed[20], None);
+    let smoothed: Vec<f64> = smoothed.into_iter().flatten().collect();
+    assert_eq!(smoothed.len(), 20);
+    // Away from the ends, the speeds are close to the average one.
+    for speed in &smoothed[3..17] {
+        assert_approx_eq!(speed, 11.1, 1.5);
+    }
+    assert!(variance(&smoothed) < variance(&raw) / 10.0);
+
+    // A window holding no other point gives no speed.
+    let alone = segment.smoothed_speeds(Duration::seconds(1));
+    assert!(alone.iter().all(Option::is_none));
+}
+
+#[test]
+fn track_segment_smoothed_speeds_negative_window() {
+    let mut segment = TrackSegment::new();
+    for i in 0..10 {
+        segment
+            .points
+            .push(timed_point(8.0, 47.0 + 0.001 * i as f64, i * 10));
+    }
+
+    // The sign of the window is ignored.
+    assert_eq!(
+        segment.smoothed_speeds(Duration::seconds(-30)),
+        segment.smoothed_speeds(Duration::seconds(30))
+    );
+}
+
+#[test]
+fn track_segment_smoothed_speeds_unbounded_window() {
+    let mut segment = TrackSegment::new();
+    for i in 0..10 {
+        segment
+            .points
+            .push(timed_point(8.0, 47.0 + 0.001 * i as f64, i * 10));
+    }
+
+    // A window longer than any date range covers the whole segment.
+    let whole = segment.smoothed_speeds(Duration::seconds(200));
+    assert_eq!(segment.smoothed_speeds(Duration::MAX), whole);
+    assert_eq!(segment.smoothed_speeds(Duration::MIN), whole);
+}
+
+#[test]
+fn track_segment_smoothed_speeds_backwards_times() {
+    let mut segment = TrackSegment::new();
+    // About 111 meters apart, with times going back.
+    segment.points.push(timed_point(8.0, 47.0, 100));
+    segment.points.push(timed_point(8.0, 47.001, 0));
+    segment.points.push(timed_point(8.0, 47.002, 50));
+
+    // The window holds all points: from the second one, at the earliest
+    // time, to the first one, at the latest.
+    let smoothed = segment.smoothed_speeds(Duration::seconds(1000));
+    assert_eq!(smoothed.len(), 3);
+    for speed in smoothed {
+        assert_approx_eq!(speed.unwrap(), 1.112, 0.001);
+    }
+
+    // Points alone in their window still give no speed.
+    let alone = segment.smoothed_speeds(Duration::seconds(1));
+    assert!(alone.iter().all(Option::is_none));
+}
+
+#[test]
+fn track_segment_crow_fly_distances() {
+    let mut segment = TrackSegment::new();
+    assert!(segment.crow_fly_distances().is_empty());
+
+    // Out and back, with a detour east on the way out.
+    segment.points.push(Waypoint::new(Point::new(8.0, 47.0)));
+    segment
+        .points
+        .push(Waypoint::new(Point::new(8.001, 47.001)));
+    segment.points.push(Waypoint::new(Point::new(8.0, 47.002)));
+    segment.points.push(Waypoint::new(Point::new(8.0, 47.001)));
+    segment.points.push(Waypoint::new(Point::new(8.0, 47.0)));
+
+    let distances = segment.crow_fly_distances();
+    assert_eq!(distances.len(), 5);
+    assert_eq!(distances[0], 0.0);
+    assert_approx_eq!(distances[2], 222.4, 0.1);
+    assert_approx_eq!(distances[3], 111.2, 0.1);
+    assert_approx_eq!(distances[4], 0.0);
+    // The turnaround is the farthest point from the start.
+    let turnaround = (0..distances.len())
+        .max_by(|&a, &b| distances[a].partial_cmp(&distances[b]).unwrap())
+        .unwrap();
+    assert_eq!(turnaround, 2);
+}
+
+#[test]
+fn track_segment_progress() {
+    let mut segment = TrackSegment::new();
+    assert!(segment.progress().is_empty());
+
+    segment.points.push(Waypoint::new(Point::new(8.0, 47.0)));
+    assert_eq!(segment.progress(), vec![0.0]);
+
+    segment.points.push(Waypoint::new(Point::new(8.0, 47.001)));
+    segment.points.push(Waypoint::new(Point::new(8.0, 47.001)));
+    segment.points.push(Waypoint::new(Point::new(8.0, 47.004)));
+    let progress = segment.progress();
+    assert_eq!(progress.len(), 4);
+    assert_eq!(progress[0], 0.0);
+    assert_eq!(progress[3], 1.0);
+    assert!(progress.windows(2).all(|pair| pair[0] <= pair[1]));
+    assert_approx_eq!(progress[1], 0.25, 0.001);
+}
+
+#[test]
+fn track_segment_resample_by_distance() {
+    let mut segment = TrackSegment::new();
+    // About 111 meters north in 100 seconds, then 333 meters further, with
+    // unevenly spaced points.
+    let mut start = timed_point(8.0, 47.0, 0);
+    start.elevation = Some(400.0);
+    let mut middle = timed_point(8.0, 47.001, 100);
+    middle.elevation = Some(500.0);
+    let mut end = timed_point(8.0, 47.004, 400);
+    end.elevation = Some(800.0);
+    segment.points = vec![start, middle, end];
+
+    let resampled = segment.resample_by_distance(50.0);
+    // 444.8 meters long, so points at 0, 50, ..., 400 meters.
+    assert_eq!(resampled.points.len(), 9);
+    for (from, to) in resampled.point_pairs() {
+        assert_approx_eq!(from.point().haversine_distance(&to.point()), 50.0, 0.01);
+    }
+    // Elevation and time grow with the distance from the start here.
+    let second = &resampled.points[1];
+    assert_approx_eq!(second.elevation.unwrap(), 445.0, 0.1);
+    let elapsed = second.time.unwrap() - segment.points[0].time.unwrap();
+    assert_approx_eq!(elapsed.num_milliseconds() as f64 / 1000.0, 45.0, 0.1);
+    assert_eq!(resampled.points[0].point(), segment.points[0].point());
+
+    assert!(TrackSegment::new()
+        .resample_by_distance(50.0)
+        .points
+        .is_empty());
+}
+
+#[test]
+fn track_segment_speed_colors() {
+    let mut segment = TrackSegment::new();
+    // About 111 meters in 100 seconds, then in 10 seconds, then in 18.
+    segment.points.push(timed_point(8.0, 47.0, 0));
+    segment.points.push(timed_point(8.0, 47.001, 100));
+    segment.points.push(timed_point(8.0, 47.002, 110));
+    segment.points.push(timed_point(8.0, 47.003, 128));
+    segment.points.push(Waypoint::new(Point::new(8.0, 47.004)));
+
+    let colors = segment.speed_colors(1.1, 11.1);
+    assert_eq!(colors.len(), 5);
+    // The slowest points are blue, the fastest red, and halfway is green.
+    let expected = [(0.0, 0.0, 1.0), (0.0, 0.0, 1.0), (1.0, 0.0, 0.0)];
+    for (color, expected) in colors.iter().zip(&expected) {
+        assert_approx_eq!(color.0, expected.0, 0.01);
+        assert_approx_eq!(color.1, expected.1, 0.01);
+        assert_approx_eq!(color.2, expected.2, 0.01);
+    }
+    assert!(colors[3].1 > 0.9);
+    // Without a time, the last point has no speed.
+    assert_eq!(colors[4], (0.0, 0.0, 1.0));
+}
+
+#[test]
+fn gpx_unique_track_names() {
+    let mut gpx = gpx::Gpx::default();
+    assert_eq!(gpx.unique_track_names(), 0);
+
+    for name in &[
+        Some("Morning run"),
+        Some("Commute"),
+        Some("Morning run "),
+        Some("Commute"),
+        Some(""),
+        None,
+    ] {
+        let mut track = Track::new();
+        track.name = name.map(String::from);
+        gpx.tracks.push(track);
+    }
+    assert_eq!(gpx.unique_track_names(), 2);
+}
+
+#[test]
+fn gpx_waypoints_only() {
+    let mut gpx = gpx::Gpx {
+        metadata: Some(Metadata {
+            name: Some("Trip".into()),
+            ..Default::default()
+        }),
+        ..Default::default()
+    };
+    gpx.waypoints.push(Waypoint::new(Point::new(8.0, 47.0)));
+    gpx.tracks.push(track_from_points(vec![
+        Waypoint::new(Point::new(8.0, 47.0)),
+        Waypoint::new(Point::new(8.1, 47.1)),
+    ]));
+    gpx.routes.push(Route::new());
+
+    let pois = gpx.waypoints_only();
+    assert_eq!(pois.waypoints, gpx.waypoints);
+    assert!(pois.tracks.is_empty());
+    assert!(pois.routes.is_empty());
+    assert_eq!(pois.metadata, gpx.metadata);
+    assert_eq!(pois.version, gpx.version);
+}
+
+#[test]
+fn gpx_active_days() {
+    let mut gpx = gpx::Gpx::default();
+    // Late on the first evening in UTC, and the next morning.
+    gpx.waypoints.push(timed_point(8.0, 47.0, 14 * 3600));
+    gpx.waypoints
+        .push(timed_point(8.0, 47.0, 15 * 3600 + 30 * 60));
+    gpx.tracks.push(track_from_points(vec![
+        timed_point(8.0, 47.0, 24 * 3600),
+        Waypoint::new(Point::new(8.0, 47.0)),
+    ]));
+
+    assert_eq!(gpx.active_days(), 2);
+    // At UTC-9, all of it happened on the first day.
+    let alaska = FixedOffset::west_opt(9 * 3600).unwrap();
+    assert_eq!(gpx.active_days_in(&alaska), 1);
+    // At UTC+3, all of it happened on the second day.
+    let moscow = FixedOffset::east_opt(3 * 3600).unwrap();
+    assert_eq!(gpx.active_days_in(&moscow), 1);
+
+    assert_eq!(gpx::Gpx::default().active_days(), 0);
+}
+
+#[test]
+fn gpx_sampling_histogram() {
+    let mut gpx = gpx::Gpx::default();
+    // Every second at first, then every 5 seconds, with a point lacking time.
+    gpx.tracks.push(track_from_points(vec![
+        timed_point(8.0, 47.0, 0),
+        timed_point(8.0, 47.0, 1),
+        timed_point(8.0, 47.0, 2),
+        Waypoint::new(Point::new(8.0, 47.0)),
+        timed_point(8.0, 47.0, 7),
+        timed_point(8.0, 47.0, 12),
+        timed_point(8.0, 47.0, 17),
+    ]));
+    // The gap between segments is not an interval.
+    gpx.tracks.push(track_from_points(vec![
+        timed_point(8.0, 47.0, 600),
+        timed_point(8.0, 47.0, 601),
+    ]));
+
+    let histogram = gpx.sampling_histogram();
+    let expected: Vec<(Duration, u32)> = vec![(Duration::seconds(1), 3), (Duration::seconds(5), 3)];
+    assert_eq!(histogram.into_iter().collect::<Vec<_>>(), expected);
+
+    assert!(gpx::Gpx::default().sampling_histogram().is_empty());
+}
+
+#[test]
+fn track_detect_laps() {
+    // Three laps round an oval of about 200 by 100 meters, starting at its
+    // eastern end, with the GPS a little off on each pass.
+    let mut points = Vec::new();
+    for i in 0..=108 {
+        let angle = i as f64 * 10f64.to_radians();
+        let jitter = if i % 36 == 0 {
+            0.000_02 * (i / 36) as f64
+        } else {
+            0.0
+        };
+        points.push(timed_point(
+            8.0 + 0.0013 * angle.cos() + jitter,
+            47.0 + 0.0005 * angle.sin(),
+            i as i64 * 5,
+        ));
+    }
+    // Standing around at the start before setting off.
+    points.insert(1, timed_point(8.0013, 47.000_05, 2));
+    points.insert(2, timed_point(8.001_35, 47.0, 3));
+    let track = track_from_points(points);
+
+    assert_eq!(track.detect_laps(15.0), vec![38, 74, 110]);
+    assert!(track_from_points(vec![timed_point(8.0, 47.0, 0)])
+        .detect_laps(15.0)
+        .is_empty());
+    assert!(Track::new().detect_laps(15.0).is_empty());
+}
+
+#[test]
+fn track_bounding_circle() {
+    let track = track_from_points(vec![
+        Waypoint::new(Point::new(8.0, 47.0)),
+        Waypoint::new(Point::new(8.0, 47.01)),
+        Waypoint::new(Point::new(8.0, 47.02)),
+    ]);
+
+    let (center, radius) = track.bounding_circle().unwrap();
+    assert_approx_eq!(center.lng(), 8.0);
+    assert_approx_eq!(center.lat(), 47.01);
+    let half = Point::new(8.0, 47.0).haversine_distance(&Point::new(8.0, 47.01));
+    assert_approx_eq!(radius, half, 1e-6);
+
+    assert_eq!(Track::new().bounding_circle(), None);
+}
+
+#[test]
+fn track_clip_time() {
+    let mut untimed = Waypoint::new(Point::new(0.0, 0.005));
+    untimed.elevation = Some(0.0);
+    let mut points = vec![
+        timed_point(0.0, 0.0, 0),
+        timed_point(0.0, 0.01, 100),
+        untimed,
+        timed_point(0.0, 0.02, 200),
+        timed_point(0.0, 0.03, 300),
+    ];
+    points[0].elevation = Some(100.0);
+    points[1].elevation = Some(200.0);
+    let mut track = track_from_points(points);
+    track.name = Some(String::from("Lap"));
+
+    let start = Utc.with_ymd_and_hms(2020, 6, 1, 8, 0, 50).unwrap();
+    let end = Utc.with_ymd_and_hms(2020, 6, 1, 8, 4, 0).unwrap();
+    let clipped = track.clip_time(start, end);
+    assert_eq!(clipped.name, track.name);
+    assert_eq!(clipped.segments.len(), 1);
+
+    let points = &clipped.segments[0].points;
+    assert_eq!(points.len(), 4);
+    assert_eq!(points[0].time, Some(start));
+    assert_approx_eq!(points[0].lat(), 0.005);
+    assert_eq!(points[0].elevation, Some(150.0));
+    assert_eq!(points[1], track.segments[0].points[1]);
+    assert_eq!(points[2], track.segments[0].points[3]);
+    assert_eq!(points[3].time, Some(end));
+    assert_approx_eq!(points[3].lat(), 0.024);
+    assert_eq!(points[3].elevation, None);
+
+    // A window between two points still gives its boundaries.
+    let start = Utc.with_ymd_and_hms(2020, 6, 1, 8, 0, 10).unwrap();
+    let end = Utc.with_ymd_and_hms(2020, 6, 1, 8, 0, 20).unwrap();
+    let clipped = track.clip_time(start, end);
+    assert_eq!(clipped.segments[0].points.len(), 2);
+
+    // Nothing was recorded before the track started.
+    let clipped = track.clip_time(start - Duration::hours(2), start - Duration::hours(1));
+    assert!(clipped.segments.is_empty());
+}
+
+#[test]
+fn track_clip_to_duration() {
+    let mut track = Track::new();
+    for first in &[0, 400] {
+        let mut segment = TrackSegment::new();
+        for i in 0..3 {
+            segment
+                .points
+                .push(timed_point(0.0, 0.01 * i as f64, first + i * 100));
+        }
+        track.segments.push(segment);
+    }
+
+    let clipped = track.clip_to_duration(Duration::seconds(450));
+    assert_eq!(clipped.segments.len(), 2);
+    assert_eq!(clipped.segments[0], track.segments[0]);
+    let points = &clipped.segments[1].points;
+    assert_eq!(points.len(), 2);
+    assert_eq!(points[0], track.segments[1].points[0]);
+    let start = track.segments[0].points[0].time.unwrap();
+    assert_eq!(points[1].time, Some(start + Duration::seconds(450)));
+    assert_approx_eq!(points[1].lat(), 0.005);
+
+    // Past the latest date, the whole track is kept.
+    assert_eq!(
+        track.clip_to_duration(Duration::MAX).segments,
+        track.segments
+    );
+
+    let untimed = track_from_points(vec![Waypoint::new(Point::new(8.0, 47.0))]);
+    assert!(untimed
+        .clip_to_duration(Duration::hours(1))
+        .segments
+        .is_empty());
+}
+
+#[test]
+fn track_elevation_range() {
+    let mut track = Track::new();
+    assert_eq!(track.elevation_range(), None);
+
+    let mut points = vec![
+        timed_point(0.0, 0.0, 0),
+        timed_point(0.0, 0.001, 60),
+        timed_point(0.0, 0.002, 120),
+        timed_point(0.0, 0.003, 180),
+    ];
+    track = track_from_points(points.clone());
+    assert_eq!(track.elevation_range(), None);
+
+    points[0].elevation = Some(12.5);
+    points[1].elevation = Some(-3.0);
+    points[3].elevation = Some(41.0);
+    track = track_from_points(points);
+    // Across all segments, skipping the point without an elevation.
+    let mut segment = TrackSegment::new();
+    segment.points.push(Waypoint::new(Point::new(0.0, 0.004)));
+    segment.points[0].elevation = Some(7.0);
+    track.segments.push(segment);
+    assert_eq!(track.elevation_range(), Some((-3.0, 41.0)));
+}
+
+#[test]
+fn track_time_in_elevation_bands() {
+    // About 111 m between points, one minute apart.
+    let mut points = vec![
+        timed_point(0.0, 0.0, 0),
+        timed_point(0.0, 0.001, 60),
+        timed_point(0.0, 0.002, 120),
+        timed_point(0.0, 0.003, 180),
+        // A break, which is not moving time.
+        timed_point(0.0, 0.003, 600),
+        timed_point(0.0, 0.004, 660),
+    ];
+    for (wpt, elevation) in points
+        .iter_mut()
+        .zip(&[40.0, 80.0, 120.0, 160.0, 160.0, 180.0])
+    {
+        wpt.elevation = Some(*elevation);
+    }
+    // Only a barometric elevation here, which counts all the same.
+    let last = timed_point(0.0, 0.005, 720);
+    points.push(
+        WaypointBuilder::new(last.point())
+            .time(last.time.unwrap())
+            .extension("baro_ele", 250.0)
+            .build(),
+    );
+    let track = track_from_points(points);
+
+    let bands = track.time_in_elevation_bands(100.0);
+    assert_eq!(bands.len(), 3);
+    assert_eq!(bands[&0], Duration::seconds(60));
+    assert_eq!(bands[&1], Duration::seconds(180));
+    assert_eq!(bands[&2], Duration::seconds(60));
+
+    assert!(track.time_in_elevation_bands(0.0).is_empty());
+}
+
+#[test]
+fn track_hr_zones() {
+    // About 111 m between points, one minute apart.
+    let with_hr = |lat, seconds, hr| {
+        let mut wpt = timed_point(0.0, lat, seconds);
+        wpt.track_point_extension = Some(TrackPointExtension {
+            hr: Some(hr),
+            ..Default::default()
+        });
+        wpt
+    };
+    let track = track_from_points(vec![
+        with_hr(0.0, 0, 100),
+        with_hr(0.001, 60, 110),
+        with_hr(0.002, 120, 130),
+        with_hr(0.003, 180, 140),
+        with_hr(0.004, 240, 160),
+        with_hr(0.005, 300, 170),
+        // A break, which is not moving time.
+        with_hr(0.005, 600, 170),
+        // No heart rate here.
+        timed_point(0.0, 0.006, 660),
+    ]);
+
+    let zones = track.hr_zones(&[120, 150]);
+    assert_eq!(
+        zones,
+        vec![
+            Duration::seconds(60),
+            Duration::seconds(120),
+            Duration::seconds(120)
+        ]
+    );
+
+    // Without bounds, all the moving time is in a single zone.
+    assert_eq!(track.hr_zones(&[]), vec![Duration::seconds(300)]);
+    assert_eq!(Track::new().hr_zones(&[120]), vec![Duration::zero(); 2]);
+}
+
+#[test]
+fn track_segment_to_waypoints() {
+    // North for about 1 km and then east for about 1 km, with GPS noise of
+    // a few meters.
+    let mut segment = TrackSegment::new();
+    for i in 0..=10 {
+        let noise = if i % 2 == 0 { 0.00002 } else { -0.00002 };
+        segment
+            .points
+            .push(Waypoint::new(Point::new(noise, i as f64 * 0.0009)));
+    }
+    for i in 1..=10 {
+        let noise = if i % 2 == 0 { 0.00002 } else { -0.00002 };
+        segment
+            .points
+            .push(Waypoint::new(Point::new(i as f64 * 0.0009, 0.009 + noise)));
+    }
+
+    let waypoints = segment.to_waypoints(10.0);
+    assert_eq!(waypoints.len(), 3);
+    assert_eq!(waypoints[0], segment.points[0]);
+    assert_eq!(waypoints[1], segment.points[10]);
+    assert_eq!(waypoints[2], segment.points[20]);
+
+    // Without any tolerance every noisy point counts as a turn.
+    assert_eq!(segment.to_waypoints(0.0).len(), segment.points.len());
+    assert!(TrackSegment::new().to_waypoints(10.0).is_empty());
+}
+
+#[test]
+fn waypoint_best_elevation() {
+    let data = "<gpx version=\"1.1\" xmlns:v=\"urn:example:vendor\"><trk><trkseg>
+        <trkpt lat=\"0.0\" lon=\"0.0\"><ele>110.0</ele><time>2020-06-01T08:00:00Z</time>
+            <extensions><v:sensors><v:baro_ele>100.0</v:baro_ele></v:sensors></extensions>
+        </trkpt>
+        <trkpt lat=\"0.01\" lon=\"0.0\"><ele>95.0</ele><time>2020-06-01T08:10:00Z</time>
+            <extensions><v:sensors><v:baro_ele>150.0</v:baro_ele></v:sensors></extensions>
+        </trkpt>
+        <trkpt lat=\"0.02\" lon=\"0.0\"><ele>130.0</ele><time>2020-06-01T08:20:00Z</time></trkpt>
+    </trkseg></trk></gpx>";
+    let gpx = read(data.as_bytes()).unwrap();
+    let segment = &gpx.tracks[0].segments[0];
+
+    let elevations: Vec<Option<f64>> = segment
+        .points
+        .iter()
+        .map(|wpt| wpt.best_elevation())
+        .collect();
+    assert_eq!(elevations, vec![Some(100.0), Some(150.0), Some(130.0)]);
+    assert_eq!(Waypoint::new(Point::new(0.0, 0.0)).best_elevation(), None);
+
+    // 50 m of barometric ascent in 20 minutes, rather than the 35 m of GPS
+    // ascent.
+    assert_approx_eq!(segment.vam().unwrap(), 150.0);
+}
+
+#[test]
+fn track_segment_gaps() {
+    let segment = |points: Vec<Waypoint>| {
+        let mut segment = TrackSegment::new();
+        segment.points = points;
+        segment
+    };
+    let mut track = Track::new();
+    track.segments = vec![
+        segment(vec![timed_point(0.0, 0.0, 0), timed_point(0.0, 0.01, 60)]),
+        // Resumes where the first segment stopped.
+        segment(vec![
+            timed_point(0.0, 0.01, 120),
+            timed_point(0.0, 0.02, 180),
+        ]),
+        segment(vec![]),
+        // Jumps a degree to the east.
+        segment(vec![timed_point(1.0, 0.02, 240)]),
+    ];
+
+    let gaps = track.segment_gaps();
+    assert_eq!(gaps.len(), 2);
+    assert_eq!(gaps[0], (1, 0.0));
+    assert_eq!(gaps[1].0, 3);
+    assert_approx_eq!(gaps[1].1, 111_195.0, 1.0);
+    assert!(Track::new().segment_gaps().is_empty());
+}
+
+#[test]
+fn track_segment_elapsed_times() {
+    let mut segment = TrackSegment::new();
+    segment.points = vec![
+        timed_point(0.0, 0.0, 30),
+        Waypoint::new(Point::new(0.0, 0.001)),
+        timed_point(0.0, 0.002, 90),
+    ];
+
+    assert_eq!(
+        segment.elapsed_times(),
+        vec![Some(Duration::zero()), None, Some(Duration::seconds(60))]
+    );
+    assert!(TrackSegment::new().elapsed_times().is_empty());
+}
+
+#[test]
+fn track_segment_positions_at_times() {
+    let mut segment = TrackSegment::new();
+    segment.points = vec![
+        timed_point(0.0, 0.0, 0),
+        Waypoint::new(Point::new(5.0, 5.0)),
+        timed_point(0.0, 0.01, 100),
+        timed_point(0.01, 0.01, 200),
+    ];
+    let at =
+        |seconds| Utc.with_ymd_and_hms(2020, 6, 1, 8, 0, 0).unwrap() + Duration::seconds(seconds);
+
+    // Out of order, and including times before and after the segment.
+    let positions = segment.positions_at_times(&[at(150), at(-10), at(25), at(100), at(250)]);
+    let points: Vec<_> = positions
+        .iter()
+        .map(|wpt| wpt.as_ref().map(|wpt| wpt.point()))
+        .collect();
+
+    assert_eq!(points[1], None);
+    assert_eq!(points[4], None);
+    assert_eq!(points[3], Some(Point::new(0.0, 0.01)));
+    let quarter = points[2].unwrap();
+    assert_approx_eq!(quarter.lng(), 0.0);
+    assert_approx_eq!(quarter.lat(), 0.0025);
+    let half = points[0].unwrap();
+    assert_approx_eq!(half.lng(), 0.005);
+    assert_approx_eq!(half.lat(), 0.01);
+    assert_eq!(positions[0].as_ref().unwrap().time, Some(at(150)));
+
+    assert!(TrackSegment::new().positions_at_times(&[at(0)])[0].is_none());
+}
+
+#[test]
+fn track_segment_hr_at() {
+    let with_hr = |seconds, hr| {
+        let mut wpt = timed_point(0.0, 0.0, seconds);
+        wpt.track_point_extension = Some(TrackPointExtension {
+            hr: Some(hr),
+            ..Default::default()
+        });
+        wpt
+    };
+    let mut segment = TrackSegment::new();
+    segment.points = vec![
+        with_hr(0, 100),
+        // No heart rate here, so it is interpolated over.
+        timed_point(0.0, 0.0, 30),
+        with_hr(60, 160),
+        with_hr(120, 150),
+    ];
+    let at =
+        |seconds| Utc.with_ymd_and_hms(2020, 6, 1, 8, 0, 0).unwrap() + Duration::seconds(seconds);
+
+    assert_eq!(segment.hr_at(at(0)), Some(100));
+    assert_eq!(segment.hr_at(at(15)), Some(115));
+    assert_eq!(segment.hr_at(at(60)), Some(160));
+    assert_eq!(segment.hr_at(at(90)), Some(155));
+    assert_eq!(segment.hr_at(at(-1)), None);
+    assert_eq!(segment.hr_at(at(121)), None);
+}
+
+#[test]
+fn track_segment_slice() {
+    let file = File::open("tests/fixtures/garmin-activity.gpx").unwrap();
+    let gpx = read(BufReader::new(file)).unwrap();
+    let segment = &gpx.tracks[0].segments[0];
+
+    let lap = segment.slice(2, 5);
+    assert_eq!(lap.points, segment.points[2..5].to_vec());
+    assert!(lap.points[0].track_point_extension.is_some());
+
+    assert!(segment.slice(3, 3).points.is_empty());
+    let len = segment.points.len();
+    assert_eq!(segment.slice(0, len), *segment);
+}
+
+#[test]
+#[should_panic(expected = "out of bounds")]
+fn track_segment_slice_out_of_bounds() {
+    let mut segment = TrackSegment::new();
+    segment.points.push(Waypoint::new(Point::new(8.0, 47.0)));
+    segment.slice(0, 2);
+}
+
+#[test]
+fn track_segment_remove_frozen_points() {
+    let mut segment = TrackSegment::new();
+    assert!(segment.remove_frozen_points(2).points.is_empty());
+
+    // A short stop, then the signal is lost and the last fix repeated.
+    segment.points = vec![
+        timed_point(8.0, 47.0, 0),
+        timed_point(8.0, 47.001, 10),
+        timed_point(8.0, 47.001, 20),
+        timed_point(8.0, 47.002, 30),
+        timed_point(8.0, 47.002, 40),
+        timed_point(8.0, 47.002, 50),
+        timed_point(8.0, 47.002, 60),
+        timed_point(8.0, 47.003, 70),
+    ];
+
+    let cleaned = segment.remove_frozen_points(2);
+    let times: Vec<i64> = cleaned
+        .points
+        .iter()
+        .map(|wpt| wpt.time.unwrap().timestamp() - segment.points[0].time.unwrap().timestamp())
+        .collect();
+    assert_eq!(times, vec![0, 10, 20, 30, 70]);
+
+    // Runs no longer than the limit are kept.
+    assert_eq!(segment.remove_frozen_points(4), segment);
+    assert_eq!(segment.remove_frozen_points(1).points.len(), 4);
+}
+
+#[test]
+fn track_predominant_bearing() {
+    // Mostly north, zigzagging a little to either side of it.
+    let track = track_from_points(vec![
+        timed_point(0.0, 0.0, 0),
+        timed_point(0.001, 0.01, 60),
+        timed_point(0.0, 0.02, 120),
+        timed_point(-0.001, 0.03, 180),
+        timed_point(0.0, 0.04, 240),
+        // A short stretch east.
+        timed_point(0.002, 0.04, 300),
+    ]);
+
+    let bearing = track.predominant_bearing().unwrap();
+    // North wraps around, so a plain mean of the bearings would not be near 0.
+    assert!(bearing < 5.0, "bearing {} is not northbound", bearing);
+
+    let westward = track_from_points(vec![timed_point(0.0, 0.0, 0), timed_point(-0.01, 0.0, 60)]);
+    assert_approx_eq!(westward.predominant_bearing().unwrap(), 270.0, 1e-6);
+    assert_eq!(Track::new().predominant_bearing(), None);
+}
+
+#[test]
+fn track_estimate_calories() {
+    // About 10 km north in an hour, climbing 100 m.
+    let mut points = Vec::new();
+    for i in 0..=10 {
+        let mut wpt = timed_point(0.0, i as f64 * 0.009, i * 360);
+        wpt.elevation = Some(100.0 + i as f64 * 10.0);
+        points.push(wpt);
+    }
+    let track = track_from_points(points);
+
+    let calories = track.estimate_calories(70.0).unwrap();
+    // Running burns roughly one kilocalorie per kilogram and kilometer.
+    assert!(calories > 700.0 && calories < 900.0, "{} kcal", calories);
+    assert!(track.estimate_calories(90.0).unwrap() > calories);
+
+    let flat = track_from_points(vec![timed_point(0.0, 0.0, 0), timed_point(0.0, 0.0, 60)]);
+    assert_eq!(flat.estimate_calories(70.0), None);
+    assert_eq!(Track::new().estimate_calories(70.0), None);
+}
+
+#[test]
+fn route_estimate_duration() {
+    let point = |lat: f64, ele: f64| {
+        let mut wpt = Waypoint::new(Point::new(8.0, lat));
+        wpt.elevation = Some(ele);
+        wpt
+    };
+    let mut route = Route::new();
+    route.points = vec![point(47.0, 500.0), point(47.01, 600.0), point(47.02, 600.0)];
+    let distance = route.linestring().haversine_length();
+
+    // At a constant speed, only the distance counts.
+    let duration = route.estimate_duration(|_| 2.0);
+    assert_approx_eq!(
+        duration.num_milliseconds() as f64 / 1000.0,
+        distance / 2.0,
+        1e-3
+    );
+
+    // Climbing at half the speed takes longer.
+    let uphill = route.estimate_duration(|grade| if grade > 0.0 { 1.0 } else { 2.0 });
+    assert!(uphill > duration);
+    let first_leg = route.points[0]
+        .point()
+        .haversine_distance(&route.points[1].point());
+    assert_approx_eq!(
+        (uphill - duration).num_milliseconds() as f64 / 1000.0,
+        first_leg / 2.0,
+        1e-2
+    );
+
+    assert_eq!(Route::new().estimate_duration(|_| 1.0), Duration::zero());
+}
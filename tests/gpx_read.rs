@@ -3,7 +3,7 @@
 // .gpx files to see how usage might be.
 
 use std::fs::File;
-use std::io::BufReader;
+use std::io::{BufReader, Read};
 
 use assert_approx_eq::assert_approx_eq;
 use chrono::{TimeZone, Utc};
@@ -11,7 +11,12 @@ use geo::algorithm::haversine_distance::HaversineDistance;
 use geo::euclidean_length::EuclideanLength;
 use geo_types::{Geometry, Point};
 
-use gpx::{read, Fix};
+use gpx::errors::{ErrorKind, Warning};
+use gpx::{
+    read, read_all, read_dir_merged, read_repair, read_streaming, read_with_options,
+    read_with_options_and_warnings, read_with_warnings, ExtensionNode, Fix, Gpx, GpxEvent,
+    GpxVersion, ParsingOptions, TrackSegment, Waypoint,
+};
 
 #[test]
 fn gpx_reader_read_test_badxml() {
@@ -39,7 +44,7 @@ fn gpx_reader_read_test_wikipedia() {
     let metadata = result.metadata.unwrap();
     assert_eq!(
         metadata.time.unwrap(),
-        Utc.ymd(2009, 10, 17).and_hms(22, 58, 43)
+        Utc.with_ymd_and_hms(2009, 10, 17, 22, 58, 43).unwrap()
     );
 
     assert_eq!(metadata.links.len(), 1);
@@ -84,7 +89,7 @@ fn gpx_reader_read_test_gpsies() {
     let metadata = result.metadata.unwrap();
     assert_eq!(
         metadata.time.unwrap(),
-        Utc.ymd(2019, 09, 11).and_hms(17, 08, 31)
+        Utc.with_ymd_and_hms(2019, 9, 11, 17, 8, 31).unwrap()
     );
 
     assert_eq!(metadata.links.len(), 1);
@@ -124,7 +129,7 @@ fn gpx_reader_read_test_garmin_activity() {
     let metadata = res.metadata.unwrap();
     assert_eq!(
         metadata.time.unwrap(),
-        Utc.ymd(2017, 7, 29).and_hms_micro(14, 46, 35, 000_000)
+        Utc.with_ymd_and_hms(2017, 7, 29, 14, 46, 35).unwrap()
     );
 
     assert_eq!(metadata.links.len(), 1);
@@ -160,8 +165,8 @@ fn gpx_reader_read_test_garmin_activity() {
 
         // Time is between a day before and after.
         let time = point.time.unwrap();
-        assert!(time > Utc.ymd(2017, 7, 28).and_hms_micro(0, 0, 0, 000_000));
-        assert!(time < Utc.ymd(2017, 7, 30).and_hms_micro(0, 0, 0, 000_000));
+        assert!(time > Utc.with_ymd_and_hms(2017, 7, 28, 0, 0, 0).unwrap());
+        assert!(time < Utc.with_ymd_and_hms(2017, 7, 30, 0, 0, 0).unwrap());
 
         // Should coerce to Point.
         let geo: Geometry<f64> = point.clone().into();
@@ -284,3 +289,894 @@ fn gpx_reader_read_test_with_accuracy() {
         Some(Fix::Other("something_not_in_the_spec".to_string()))
     );
 }
+
+#[test]
+fn gpx_reader_read_test_wrapped_in_feed() {
+    let xml = r#"<?xml version="1.0" encoding="UTF-8"?>
+<feed xmlns="http://www.w3.org/2005/Atom">
+    <title>Shared activities</title>
+    <entry>
+        <title>Morning run</title>
+        <content type="application/gpx+xml">
+            <gpx version="1.1" creator="feed test" xmlns="http://www.topografix.com/GPX/1/1">
+                <trk>
+                    <name>Morning run</name>
+                    <trkseg>
+                        <trkpt lat="47.644548" lon="-122.326897"></trkpt>
+                        <trkpt lat="47.644549" lon="-122.326898"></trkpt>
+                    </trkseg>
+                </trk>
+            </gpx>
+        </content>
+    </entry>
+</feed>"#;
+
+    // The <feed> root is rejected by default.
+    assert!(read(xml.as_bytes()).is_err());
+
+    let options = ParsingOptions {
+        find_gpx_anywhere: true,
+        ..Default::default()
+    };
+    let gpx = read_with_options(xml.as_bytes(), &options).unwrap();
+
+    assert_eq!(gpx.creator, Some(String::from("feed test")));
+    assert_eq!(gpx.tracks.len(), 1);
+    assert_eq!(gpx.tracks[0].name, Some(String::from("Morning run")));
+    assert_eq!(gpx.tracks[0].segments[0].points.len(), 2);
+}
+
+#[test]
+fn gpx_reader_read_with_warnings() {
+    let xml = r#"<gpx version="1.1">
+    <trk>
+        <trkseg>
+            <trkpt lat="47.0" lon="8.0"><ele>400</ele><time>2020-06-01T08:00:00Z</time></trkpt>
+            <trkpt lat="47.1" lon="8.0"><time>2020-06-01T08:01:00Z</time></trkpt>
+            <trkpt lat="47.2" lon="8.0"><ele>402</ele><time>2020-06-01T08:00:30Z</time></trkpt>
+        </trkseg>
+    </trk>
+</gpx>"#;
+
+    let (gpx, warnings) = read_with_warnings(xml.as_bytes()).unwrap();
+    assert_eq!(gpx.tracks[0].segments[0].points.len(), 3);
+    assert_eq!(
+        warnings,
+        vec![
+            Warning::MissingElevation { count: 1 },
+            Warning::NonMonotonicTime { count: 1 },
+        ]
+    );
+    assert_eq!(warnings[0].to_string(), "elevation missing on 1 points");
+
+    // Clean files have no warnings.
+    let file = File::open("tests/fixtures/wikipedia_example.gpx").unwrap();
+    let (_, warnings) = read_with_warnings(BufReader::new(file)).unwrap();
+    assert!(warnings.is_empty());
+}
+
+#[test]
+fn gpx_reader_read_lenient_malformed_extensions() {
+    let xml = r#"<gpx version="1.1">
+    <trk>
+        <trkseg>
+            <trkpt lat="47.0" lon="8.0">
+                <ele>400</ele>
+                <extensions><gpxtpx:TrackPointExtension><gpxtpx:hr>120</gpxtpx:TrackPointExtension></extensions>
+            </trkpt>
+            <trkpt lat="47.1" lon="8.0"><ele>401</ele></trkpt>
+        </trkseg>
+    </trk>
+</gpx>"#;
+
+    // A single broken extension fails the whole document by default.
+    assert!(read(xml.as_bytes()).is_err());
+
+    let options = ParsingOptions {
+        strict: false,
+        ..Default::default()
+    };
+    let (gpx, warnings) = read_with_options_and_warnings(xml.as_bytes(), &options).unwrap();
+
+    let points = &gpx.tracks[0].segments[0].points;
+    assert_eq!(points.len(), 2);
+    assert_eq!(points[0].elevation, Some(400.0));
+    assert_eq!(points[1].elevation, Some(401.0));
+    // The point is read as if it had no extensions.
+    assert_eq!(points[0].extensions, None);
+    assert_eq!(points[0].track_point_extension, None);
+    assert_eq!(warnings, vec![Warning::MalformedExtensions { line: 6 }]);
+}
+
+#[test]
+fn gpx_reader_read_lenient_html_description() {
+    let xml = r#"<gpx version="1.1">
+    <wpt lat="47.0" lon="8.0">
+        <name>Trailhead</name>
+        <desc>Parking<br>Open all year</desc>
+    </wpt>
+</gpx>"#;
+
+    assert!(read(xml.as_bytes()).is_err());
+
+    let options = ParsingOptions {
+        strict: false,
+        ..Default::default()
+    };
+    let gpx = read_with_options(xml.as_bytes(), &options).unwrap();
+
+    assert_eq!(gpx.waypoints[0].name, Some("Trailhead".into()));
+    assert_eq!(
+        gpx.waypoints[0].description,
+        Some("Parking<br>Open all year".into())
+    );
+}
+
+#[test]
+fn gpx_reader_read_lenient_duplicate_children() {
+    let xml = r#"<gpx version="1.1">
+    <wpt lat="47.0" lon="8.0">
+        <name>Trailhead</name>
+        <ele>400</ele>
+        <name>Summit</name>
+    </wpt>
+</gpx>"#;
+
+    let err = read(xml.as_bytes()).unwrap_err();
+    match err.kind() {
+        ErrorKind::AtPosition(_, _, _, message) => {
+            assert_eq!(message, "duplicate child element 'name' in waypoint");
+        }
+        kind => panic!("unexpected error {:?}", kind),
+    }
+
+    let options = ParsingOptions {
+        strict: false,
+        ..Default::default()
+    };
+    let gpx = read_with_options(xml.as_bytes(), &options).unwrap();
+
+    assert_eq!(gpx.waypoints[0].name.as_deref(), Some("Summit"));
+    assert_eq!(gpx.waypoints[0].elevation, Some(400.0));
+}
+
+#[test]
+fn gpx_reader_from_str_and_from_reader() {
+    let gpx =
+        gpx::from_str(r#"<gpx version="1.0"><wpt lat="47.0" lon="8.0"></wpt></gpx>"#).unwrap();
+    assert_eq!(gpx.version, GpxVersion::Gpx10);
+    assert_eq!(gpx.waypoints.len(), 1);
+
+    let file = File::open("tests/fixtures/wikipedia_example.gpx").unwrap();
+    let gpx = gpx::from_reader(BufReader::new(file)).unwrap();
+    assert_eq!(gpx.version, GpxVersion::Gpx11);
+
+    assert!(gpx::from_str("<gpx></gpx>").is_err());
+}
+
+#[test]
+fn gpx_reader_read_unknown_version() {
+    let xml = r#"<gpx version="1.2" xmlns="http://www.topografix.com/GPX/1/1">
+    <metadata><name>Experimental</name></metadata>
+    <wpt lat="47.0" lon="8.0"><ele>400</ele></wpt>
+</gpx>"#;
+
+    let (gpx, warnings) = read_with_warnings(xml.as_bytes()).unwrap();
+
+    assert_eq!(gpx.version, GpxVersion::Gpx11);
+    assert_eq!(gpx.declared_version.as_deref(), Some("1.2"));
+    assert_eq!(gpx.metadata.unwrap().name.as_deref(), Some("Experimental"));
+    assert_eq!(
+        warnings,
+        vec![Warning::UnknownVersion {
+            version: "1.2".into()
+        }]
+    );
+
+    let known = read("<gpx version=\"1.1\"></gpx>".as_bytes()).unwrap();
+    assert_eq!(known.declared_version, None);
+}
+
+#[test]
+fn gpx_reader_read_lenient_coordinates_child() {
+    let xml = r#"<gpx version="1.1">
+    <wpt><name>Summit</name><coordinates>8.5, 47.0, 1200.5</coordinates></wpt>
+    <trk><trkseg>
+        <trkpt><coordinates>8.6,47.1</coordinates><ele>400</ele></trkpt>
+    </trkseg></trk>
+</gpx>"#;
+
+    assert!(read(xml.as_bytes()).is_err());
+
+    let options = ParsingOptions {
+        strict: false,
+        ..Default::default()
+    };
+    let gpx = read_with_options(xml.as_bytes(), &options).unwrap();
+
+    assert_eq!(gpx.waypoints[0].point(), Point::new(8.5, 47.0));
+    assert_eq!(gpx.waypoints[0].elevation, Some(1200.5));
+    assert_eq!(gpx.waypoints[0].name.as_deref(), Some("Summit"));
+    let point = &gpx.tracks[0].segments[0].points[0];
+    assert_eq!(point.point(), Point::new(8.6, 47.1));
+    assert_eq!(point.elevation, Some(400.0));
+
+    // A point needs its coordinates one way or the other.
+    let xml = r#"<gpx version="1.1"><wpt><name>Nowhere</name></wpt></gpx>"#;
+    assert!(read_with_options(xml.as_bytes(), &options).is_err());
+}
+
+#[test]
+fn gpx_reader_read_deeply_nested_extensions() {
+    let depth = 5_000;
+    let xml = format!(
+        r#"<gpx version="1.1"><wpt lat="47.0" lon="8.0"><extensions>{}{}</extensions></wpt></gpx>"#,
+        "<a>".repeat(depth),
+        "</a>".repeat(depth)
+    );
+
+    // Refused rather than overflowing the stack.
+    assert!(read(xml.as_bytes()).is_err());
+
+    // Skipping them takes any depth.
+    let options = ParsingOptions {
+        drop_extensions: true,
+        ..Default::default()
+    };
+    let gpx = read_with_options(xml.as_bytes(), &options).unwrap();
+    assert_eq!(gpx.waypoints.len(), 1);
+}
+
+#[test]
+fn gpx_reader_read_trailing_content() {
+    let xml = r#"<?xml version="1.0" encoding="UTF-8"?>
+<gpx version="1.1">
+    <wpt lat="47.0" lon="8.0"><name>Summit</name></wpt>
+</gpx>
+-----BEGIN SIGNATURE-----
+iQEzBAEBCAAdFiEE <not xml & not closed
+-----END SIGNATURE-----
+</gpx><wpt lat="1.0" lon="2.0"/>"#;
+
+    let lenient = ParsingOptions {
+        strict: false,
+        ..Default::default()
+    };
+    for options in &[ParsingOptions::default(), lenient] {
+        let gpx = read_with_options(xml.as_bytes(), options).unwrap();
+        assert_eq!(gpx.waypoints.len(), 1);
+        assert_eq!(gpx.waypoints[0].name.as_deref(), Some("Summit"));
+    }
+}
+
+#[test]
+fn gpx_reader_read_standalone_declaration() {
+    for standalone in &["yes", "no"] {
+        let xml = format!(
+            r#"<?xml version="1.0" encoding="UTF-8" standalone="{}"?>
+<gpx version="1.1" creator="declared">
+    <wpt lat="47.0" lon="8.0"><name>Summit</name></wpt>
+</gpx>"#,
+            standalone
+        );
+
+        let gpx = read(xml.as_bytes()).unwrap();
+        assert_eq!(gpx.creator.as_deref(), Some("declared"));
+        assert_eq!(gpx.waypoints[0].name.as_deref(), Some("Summit"));
+
+        let options = ParsingOptions {
+            strict: false,
+            ..Default::default()
+        };
+        let lenient = read_with_options(xml.as_bytes(), &options).unwrap();
+        assert_eq!(lenient, gpx);
+    }
+}
+
+#[test]
+fn gpx_reader_read_comments_in_extensions() {
+    let xml = r#"<gpx version="1.1" xmlns:gpxtpx="http://www.garmin.com/xmlschemas/TrackPointExtension/v1">
+    <trk><trkseg>
+        <trkpt lat="47.0" lon="8.0">
+            <ele>400</ele>
+            <extensions>
+                <!-- heart rate from the chest strap, not <the watch> -->
+                <gpxtpx:TrackPointExtension>
+                    <gpxtpx:hr>1<!-- sic -->42</gpxtpx:hr>
+                    <!-- </extensions> -->
+                </gpxtpx:TrackPointExtension>
+                <note>kept <!-- dropped -->here</note>
+            </extensions>
+        </trkpt>
+    </trkseg></trk>
+</gpx>"#;
+
+    let strict = read(xml.as_bytes()).unwrap();
+    let options = ParsingOptions {
+        strict: false,
+        ..Default::default()
+    };
+    let (lenient, warnings) = read_with_options_and_warnings(xml.as_bytes(), &options).unwrap();
+    assert!(warnings.is_empty());
+    assert_eq!(lenient, strict);
+
+    let point = &strict.tracks[0].segments[0].points[0];
+    assert_eq!(point.track_point_extension.as_ref().unwrap().hr, Some(142));
+    let extensions = point.extensions.as_ref().unwrap();
+    assert_eq!(extensions.children.len(), 1);
+    match &extensions.children[0] {
+        ExtensionNode::Element(note) => assert_eq!(
+            note.children,
+            vec![ExtensionNode::Text(String::from("kept here"))]
+        ),
+        node => panic!("unexpected node {:?}", node),
+    }
+}
+
+#[test]
+fn gpx_reader_read_prefixed_elements() {
+    let xml = r#"<gpx:gpx xmlns:gpx="http://www.topografix.com/GPX/1/1" gpx:version="1.1" gpx:creator="prefixer">
+    <gpx:metadata><gpx:name>Prefixed</gpx:name></gpx:metadata>
+    <gpx:wpt gpx:lat="47.0" gpx:lon="8.0"><gpx:ele>400</gpx:ele></gpx:wpt>
+    <gpx:trk>
+        <gpx:name>Run</gpx:name>
+        <gpx:trkseg>
+            <gpx:trkpt gpx:lat="47.1" gpx:lon="8.1">
+                <gpx:time>2020-06-01T08:00:00Z</gpx:time>
+            </gpx:trkpt>
+        </gpx:trkseg>
+    </gpx:trk>
+</gpx:gpx>"#;
+
+    let gpx = read(xml.as_bytes()).unwrap();
+
+    assert_eq!(gpx.version, GpxVersion::Gpx11);
+    assert_eq!(gpx.creator.as_deref(), Some("prefixer"));
+    assert_eq!(
+        gpx.namespace.as_deref(),
+        Some("http://www.topografix.com/GPX/1/1")
+    );
+    assert_eq!(gpx.metadata.unwrap().name.as_deref(), Some("Prefixed"));
+    assert_eq!(gpx.waypoints[0].point(), Point::new(8.0, 47.0));
+    assert_eq!(gpx.waypoints[0].elevation, Some(400.0));
+    assert_eq!(gpx.tracks[0].name.as_deref(), Some("Run"));
+    let point = &gpx.tracks[0].segments[0].points[0];
+    assert_eq!(point.point(), Point::new(8.1, 47.1));
+    assert!(point.time.is_some());
+}
+
+#[test]
+fn gpx_reader_read_lenient_internal_bom() {
+    // Pieces of a recording joined back together, each with its own BOM,
+    // which can end up anywhere, even inside a value.
+    let mut data = b"\xEF\xBB\xBF<gpx version=\"1.1\"><trk><trkseg>\n".to_vec();
+    data.extend_from_slice(b"<trkpt lat=\"47.0\" lon=\"8.0\"><ele>4\xEF\xBB\xBF00</ele></trkpt>\n");
+    data.extend_from_slice(b"\xEF\xBB\xBF<trkpt lat=\"47.1\" lon=\"8.0\"></trkpt>\n");
+    data.extend_from_slice(b"</trkseg></trk></gpx>");
+
+    assert!(read(data.as_slice()).is_err());
+
+    let options = ParsingOptions {
+        strict: false,
+        ..Default::default()
+    };
+    let gpx = read_with_options(data.as_slice(), &options).unwrap();
+    let points = &gpx.tracks[0].segments[0].points;
+    assert_eq!(points.len(), 2);
+    assert_eq!(points[0].elevation, Some(400.0));
+}
+
+#[test]
+fn gpx_reader_read_repair_truncated() {
+    let mut data = Vec::new();
+    File::open("tests/fixtures/garmin-activity.gpx")
+        .unwrap()
+        .read_to_end(&mut data)
+        .unwrap();
+    let complete = read(data.as_slice()).unwrap();
+    let complete_points = complete.tracks[0].segments[0].points.len();
+
+    // The recording stopped half way through.
+    data.truncate(data.len() / 2);
+    assert!(read(data.as_slice()).is_err());
+
+    let repaired = read_repair(data.as_slice()).unwrap();
+    let points = &repaired.tracks[0].segments[0].points;
+    assert!(!points.is_empty());
+    assert!(points.len() < complete_points);
+    // All but the last point are complete, the last one may have lost some
+    // of its data.
+    let reference = &complete.tracks[0].segments[0].points;
+    let last = points.len() - 1;
+    assert_eq!(points[..last], reference[..last]);
+    assert_eq!(points[last].point(), reference[last].point());
+}
+
+#[test]
+fn gpx_reader_read_dir_merged() {
+    let dir = std::env::temp_dir().join("gpx_reader_read_dir_merged");
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir(&dir).unwrap();
+    std::fs::copy("tests/fixtures/garmin-activity.gpx", dir.join("a.gpx")).unwrap();
+    std::fs::copy("tests/fixtures/with_accuracy.gpx", dir.join("b.GPX")).unwrap();
+    std::fs::write(dir.join("notes.txt"), "not a gpx file").unwrap();
+
+    let merged = read_dir_merged(&dir);
+    std::fs::remove_dir_all(&dir).unwrap();
+    let merged = merged.unwrap();
+
+    let garmin = read(BufReader::new(
+        File::open("tests/fixtures/garmin-activity.gpx").unwrap(),
+    ))
+    .unwrap();
+    assert_eq!(merged.tracks.len(), 2);
+    assert_eq!(merged.tracks[0].name, garmin.tracks[0].name);
+    assert_eq!(merged.tracks[0].segments, garmin.tracks[0].segments);
+    assert!(!merged.tracks[1].segments.is_empty());
+}
+
+#[test]
+fn gpx_reader_read_lenient_coordinate_names() {
+    let xml = r#"<gpx version="1.1">
+    <wpt latitude="47.0" longitude="8.5"><name>Summit</name></wpt>
+    <wpt lat="47.1" lon="8.6"><name>Hut</name></wpt>
+</gpx>"#;
+
+    assert!(read(xml.as_bytes()).is_err());
+
+    let options = ParsingOptions {
+        strict: false,
+        ..Default::default()
+    };
+    let gpx = read_with_options(xml.as_bytes(), &options).unwrap();
+
+    assert_eq!(gpx.waypoints[0].point(), Point::new(8.5, 47.0));
+    assert_eq!(gpx.waypoints[1].point(), Point::new(8.6, 47.1));
+}
+
+#[test]
+fn gpx_reader_read_drop_extensions() {
+    let file = File::open("tests/fixtures/garmin-activity.gpx").unwrap();
+    let gpx = read(BufReader::new(file)).unwrap();
+    let points = &gpx.tracks[0].segments[0].points;
+    assert!(points.iter().all(|wpt| wpt.track_point_extension.is_some()));
+
+    let options = ParsingOptions {
+        drop_extensions: true,
+        ..Default::default()
+    };
+    let file = File::open("tests/fixtures/garmin-activity.gpx").unwrap();
+    let dropped = read_with_options(BufReader::new(file), &options).unwrap();
+    let dropped_points = &dropped.tracks[0].segments[0].points;
+
+    assert_eq!(dropped_points.len(), points.len());
+    assert!(dropped_points
+        .iter()
+        .all(|wpt| wpt.extensions.is_none() && wpt.track_point_extension.is_none()));
+    assert_eq!(dropped_points[0].point(), points[0].point());
+    assert_eq!(dropped_points[0].time, points[0].time);
+}
+
+#[test]
+fn gpx_reader_read_elevation_from_extensions() {
+    let xml = r#"<gpx version="1.1" xmlns:baro="urn:example:baro">
+    <trk><trkseg>
+        <trkpt lat="47.0" lon="8.0">
+            <extensions><baro:baro_ele>412.5</baro:baro_ele></extensions>
+        </trkpt>
+        <trkpt lat="47.1" lon="8.0">
+            <ele>420</ele>
+            <extensions><baro:baro_ele>418.0</baro:baro_ele></extensions>
+        </trkpt>
+    </trkseg></trk>
+</gpx>"#;
+
+    let gpx = read(xml.as_bytes()).unwrap();
+    assert_eq!(gpx.tracks[0].segments[0].points[0].elevation, None);
+
+    let options = ParsingOptions {
+        elevation_from_extensions: true,
+        ..Default::default()
+    };
+    let gpx = read_with_options(xml.as_bytes(), &options).unwrap();
+    let points = &gpx.tracks[0].segments[0].points;
+    assert_eq!(points[0].elevation, Some(412.5));
+    // An `<ele>` element is kept as it is.
+    assert_eq!(points[1].elevation, Some(420.0));
+}
+
+#[test]
+fn gpx_reader_read_course_from_extensions() {
+    let xml = r#"<gpx version="1.1" xmlns:gpxtpx="http://www.garmin.com/xmlschemas/TrackPointExtension/v2">
+    <trk><trkseg>
+        <trkpt lat="47.0" lon="8.0">
+            <extensions>
+                <gpxtpx:TrackPointExtension><gpxtpx:course>87.5</gpxtpx:course></gpxtpx:TrackPointExtension>
+            </extensions>
+        </trkpt>
+        <trkpt lat="47.1" lon="8.0">
+            <extensions><heading>270</heading></extensions>
+        </trkpt>
+        <trkpt lat="47.2" lon="8.0"></trkpt>
+    </trkseg></trk>
+</gpx>"#;
+
+    let gpx = read(xml.as_bytes()).unwrap();
+    let points = &gpx.tracks[0].segments[0].points;
+    assert_eq!(points[0].course, Some(87.5));
+    assert_eq!(points[1].course, Some(270.0));
+    assert_eq!(points[2].course, None);
+    // The extension is kept as it is.
+    assert!(points[0].extensions.is_some());
+
+    // The course of GPX 1.0 takes precedence.
+    let xml = r#"<gpx version="1.0">
+    <wpt lat="47.0" lon="8.0">
+        <course>12.0</course>
+        <extensions><heading>270</heading></extensions>
+    </wpt>
+</gpx>"#;
+    let gpx = read(xml.as_bytes()).unwrap();
+    assert_eq!(gpx.waypoints[0].course, Some(12.0));
+}
+
+#[test]
+fn gpx_reader_read_media_links() {
+    let xml = r#"<gpx version="1.1" xmlns:ph="urn:example:photos" xmlns:xlink="http://www.w3.org/1999/xlink">
+    <wpt lat="47.0" lon="8.0">
+        <link href="https://example.com/summit"><text>Summit</text></link>
+        <extensions>
+            <ph:photos>
+                <ph:photo href="IMG_0042.jpg" type="image/jpeg">View north</ph:photo>
+                <ph:photo xlink:href="IMG_0043.jpg">
+                    <ph:text>Cairn</ph:text>
+                    <ph:type>image/jpeg</ph:type>
+                </ph:photo>
+            </ph:photos>
+            <ph:rating>5</ph:rating>
+        </extensions>
+    </wpt>
+</gpx>"#;
+
+    let gpx = read(xml.as_bytes()).unwrap();
+    let wpt = &gpx.waypoints[0];
+    assert_eq!(wpt.links.len(), 1);
+
+    let media = wpt.media_links();
+    assert_eq!(media.len(), 2);
+    assert_eq!(media[0].href, "IMG_0042.jpg");
+    assert_eq!(media[0].text.as_deref(), Some("View north"));
+    assert_eq!(media[0]._type.as_deref(), Some("image/jpeg"));
+    assert_eq!(media[1].href, "IMG_0043.jpg");
+    assert_eq!(media[1].text.as_deref(), Some("Cairn"));
+    assert_eq!(media[1]._type.as_deref(), Some("image/jpeg"));
+
+    assert!(Waypoint::new(Point::new(8.0, 47.0))
+        .media_links()
+        .is_empty());
+}
+
+#[test]
+fn gpx_reader_read_waypoint_lat_lon() {
+    let file = File::open("tests/fixtures/wikipedia_example.gpx").unwrap();
+    let gpx = read(BufReader::new(file)).unwrap();
+
+    // <trkpt lat="47.644548" lon="-122.326897">
+    let point = &gpx.tracks[0].segments[0].points[0];
+    assert_eq!(point.lat(), 47.644548);
+    assert_eq!(point.lon(), -122.326897);
+    assert_eq!(point.point(), Point::new(point.lon(), point.lat()));
+}
+
+#[test]
+#[cfg(debug_assertions)]
+#[should_panic(expected = "(longitude, latitude) order")]
+fn gpx_waypoint_swapped_coordinates() {
+    // Latitude first, by mistake.
+    Waypoint::new(Point::new(47.644548, -122.326897));
+}
+
+#[test]
+fn gpx_reader_read_max_track_points_per_segment() {
+    let options = ParsingOptions {
+        max_track_points_per_segment: Some(10),
+        ..Default::default()
+    };
+    let file = File::open("tests/fixtures/garmin-activity.gpx").unwrap();
+    let preview = read_with_options(BufReader::new(file), &options).unwrap();
+    let file = File::open("tests/fixtures/garmin-activity.gpx").unwrap();
+    let gpx = read(BufReader::new(file)).unwrap();
+
+    let points = &preview.tracks[0].segments[0].points;
+    assert_eq!(points.len(), 10);
+    assert_eq!(points[..], gpx.tracks[0].segments[0].points[..10]);
+    // The rest of the document is still read.
+    assert_eq!(preview.tracks.len(), gpx.tracks.len());
+    assert_eq!(preview.metadata, gpx.metadata);
+}
+
+#[test]
+fn gpx_reader_read_lenient_epoch_time() {
+    let xml = r#"<gpx version="1.1">
+    <wpt lat="47.0" lon="8.0"><time>1591000000</time></wpt>
+    <wpt lat="47.0" lon="8.0"><time>2020-06-01T08:26:40Z</time></wpt>
+</gpx>"#;
+
+    assert!(read(xml.as_bytes()).is_err());
+
+    let options = ParsingOptions {
+        strict: false,
+        ..Default::default()
+    };
+    let gpx = read_with_options(xml.as_bytes(), &options).unwrap();
+
+    let expected = Utc.with_ymd_and_hms(2020, 6, 1, 8, 26, 40).unwrap();
+    assert_eq!(gpx.waypoints[0].time, Some(expected));
+    assert_eq!(gpx.waypoints[1].time, Some(expected));
+}
+
+#[test]
+fn gpx_reader_read_all_logged_sessions() {
+    let mut session = Vec::new();
+    File::open("tests/fixtures/wikipedia_example.gpx")
+        .unwrap()
+        .read_to_end(&mut session)
+        .unwrap();
+    let reference = read(session.as_slice()).unwrap();
+
+    // Documents appended one after the other, with blank lines between them.
+    let mut data = Vec::new();
+    for _ in 0..3 {
+        data.extend_from_slice(&session);
+        data.extend_from_slice(b"\n\n  \t\r\n");
+    }
+
+    let documents = read_all(data.as_slice()).unwrap();
+    assert_eq!(documents.len(), 3);
+    for gpx in &documents {
+        assert_eq!(gpx, &reference);
+    }
+
+    // A broken document is reported rather than skipped.
+    data.extend_from_slice(b"<gpx version=\"1.1\"><trk>");
+    assert!(read_all(data.as_slice()).is_err());
+}
+
+#[test]
+fn gpx_reader_read_extensions_from_several_namespaces() {
+    let data = "<gpx version=\"1.1\" xmlns=\"http://www.topografix.com/GPX/1/1\"
+        xmlns:gpxtpx=\"http://www.garmin.com/xmlschemas/TrackPointExtension/v1\"
+        xmlns:v=\"urn:example:vendor\">
+        <wpt lat=\"1.0\" lon=\"2.0\">
+            <extensions>
+                <gpxtpx:TrackPointExtension><gpxtpx:hr>142</gpxtpx:hr></gpxtpx:TrackPointExtension>
+                <v:power>230</v:power>
+                <v:hr>999</v:hr>
+            </extensions>
+        </wpt>
+    </gpx>";
+
+    let gpx = read(data.as_bytes()).unwrap();
+    let waypoint = &gpx.waypoints[0];
+    let fields = waypoint.track_point_extension.as_ref().unwrap();
+    assert_eq!(fields.hr, Some(142));
+
+    // The vendor's `hr` is not mistaken for the Garmin one, and is kept.
+    let extensions = waypoint.extensions.as_ref().unwrap();
+    assert_eq!(extensions.children.len(), 2);
+    assert_eq!(
+        extensions
+            .elements_in("http://www.garmin.com/xmlschemas/TrackPointExtension/v1")
+            .count(),
+        0
+    );
+    let vendor: Vec<&str> = extensions
+        .elements_in("urn:example:vendor")
+        .map(|element| element.name.as_str())
+        .collect();
+    assert_eq!(vendor, vec!["power", "hr"]);
+}
+
+#[cfg(feature = "bincode")]
+#[test]
+fn gpx_reader_read_cached_bytes() {
+    let file = File::open("tests/fixtures/garmin-activity.gpx").unwrap();
+    let gpx = read(BufReader::new(file)).unwrap();
+
+    let bytes = gpx.to_bytes().unwrap();
+    assert_eq!(gpx::Gpx::from_bytes(&bytes).unwrap(), gpx);
+    assert!(gpx::Gpx::from_bytes(&bytes[..bytes.len() / 2]).is_err());
+}
+
+#[test]
+fn gpx_reader_read_track_point_extension() {
+    let file = File::open("tests/fixtures/garmin-activity.gpx").unwrap();
+    let gpx = read(BufReader::new(file)).unwrap();
+
+    let point = &gpx.tracks[0].segments[0].points[0];
+    let fields = point.track_point_extension.as_ref().unwrap();
+    assert_eq!(fields.hr, Some(74));
+    assert_eq!(fields.cad, Some(79));
+    assert_eq!(fields.atemp, None);
+    // Nothing else was in the extensions.
+    assert!(point.extensions.is_none());
+}
+
+#[test]
+fn gpx_reader_read_error_position() {
+    let data = "<gpx version=\"1.1\">
+    <trk><trkseg>
+        <trkpt lat=\"1.0\" lon=\"2.0\"><foo/></trkpt>
+    </trkseg></trk>
+</gpx>";
+
+    let err = read(data.as_bytes()).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "error at 3:36: invalid child element 'foo' in waypoint"
+    );
+    match err.kind() {
+        ErrorKind::AtPosition(row, column, path, _) => {
+            assert_eq!((*row, *column), (3, 36));
+            assert_eq!(path, "gpx/trk/trkseg/trkpt/foo");
+        }
+        kind => panic!("unexpected error {:?}", kind),
+    }
+    // The error itself is still there as the cause.
+    assert!(err.iter().nth(1).is_some());
+}
+
+#[test]
+fn gpx_reader_read_lenient_units() {
+    let xml = r#"<gpx version="1.0">
+    <wpt lat="47.0" lon="8.0"><ele>100m</ele><speed>5.0 m/s</speed></wpt>
+    <wpt lat="47.1" lon="8.0"><ele> 101.5 m </ele></wpt>
+</gpx>"#;
+
+    assert!(read(xml.as_bytes()).is_err());
+
+    let options = ParsingOptions {
+        strict: false,
+        ..Default::default()
+    };
+    let gpx = read_with_options(xml.as_bytes(), &options).unwrap();
+
+    assert_eq!(gpx.waypoints[0].elevation, Some(100.0));
+    assert_eq!(gpx.waypoints[0].speed, Some(5.0));
+    assert_eq!(gpx.waypoints[1].elevation, Some(101.5));
+}
+
+#[test]
+fn gpx_reader_read_lenient_decimal_sat() {
+    let xml = r#"<gpx version="1.1">
+    <wpt lat="47.0" lon="8.0"><ele>400</ele><sat>4.0</sat></wpt>
+    <wpt lat="47.1" lon="8.0"><ele>410</ele><sat>11</sat></wpt>
+</gpx>"#;
+
+    assert!(read(xml.as_bytes()).is_err());
+
+    let options = ParsingOptions {
+        strict: false,
+        ..Default::default()
+    };
+    let gpx = read_with_options(xml.as_bytes(), &options).unwrap();
+
+    assert_eq!(gpx.waypoints[0].sat, Some(4));
+    assert_eq!(gpx.waypoints[1].sat, Some(11));
+
+    // Values that are no number at all are still errors.
+    let xml = r#"<gpx version="1.1"><wpt lat="47.0" lon="8.0"><sat>-</sat></wpt></gpx>"#;
+    assert!(read_with_options(xml.as_bytes(), &options).is_err());
+}
+
+#[test]
+fn gpx_reader_read_lenient_unknown_children() {
+    let xml = r#"<gpx version="1.1">
+    <wpt lat="47.0" lon="8.0">
+        <ele>400</ele>
+        <heading unit="deg"><value>12</value><source><heading/></source></heading>
+        <name>Summit</name>
+    </wpt>
+    <trk><trkseg>
+        <trkpt lat="47.1" lon="8.0"><battery>80</battery></trkpt>
+        <trkpt lat="47.2" lon="8.0"/>
+    </trkseg></trk>
+</gpx>"#;
+
+    assert!(read(xml.as_bytes()).is_err());
+
+    let options = ParsingOptions {
+        strict: false,
+        ..Default::default()
+    };
+    let gpx = read_with_options(xml.as_bytes(), &options).unwrap();
+
+    assert_eq!(gpx.waypoints.len(), 1);
+    assert_eq!(gpx.waypoints[0].elevation, Some(400.0));
+    assert_eq!(gpx.waypoints[0].name.as_deref(), Some("Summit"));
+    assert_eq!(gpx.tracks[0].segments[0].points.len(), 2);
+}
+
+/// Puts the events of `read_streaming` back together into a document.
+fn collect_events<R: Read>(reader: R) -> gpx::errors::Result<Gpx> {
+    let mut gpx = Gpx::default();
+    for event in read_streaming(reader) {
+        match event? {
+            GpxEvent::Start(start) => gpx = start,
+            GpxEvent::Metadata(metadata) => gpx.metadata = Some(metadata),
+            GpxEvent::Waypoint(waypoint) => gpx.waypoints.push(waypoint),
+            GpxEvent::TrackStart(track) => gpx.tracks.push(track),
+            GpxEvent::TrackSegmentStart => {
+                let track = gpx.tracks.last_mut().unwrap();
+                track.segments.push(TrackSegment::new());
+            }
+            GpxEvent::TrackPoint(point) => {
+                let track = gpx.tracks.last_mut().unwrap();
+                track.segments.last_mut().unwrap().points.push(point);
+            }
+            GpxEvent::RouteStart(route) => gpx.routes.push(route),
+            GpxEvent::RoutePoint(point) => gpx.routes.last_mut().unwrap().points.push(point),
+            GpxEvent::Extensions(extensions) => gpx.summary_extensions = Some(extensions),
+        }
+    }
+    Ok(gpx)
+}
+
+#[test]
+fn gpx_reader_read_streaming_fixtures() {
+    for fixture in &[
+        "wikipedia_example.gpx",
+        "gpsies_example.gpx",
+        "garmin-activity.gpx",
+        "ecology-trail-and-lovers-lane-loop.gpx",
+        "with_accuracy.gpx",
+    ] {
+        let path = format!("tests/fixtures/{}", fixture);
+        let expected = read(BufReader::new(File::open(&path).unwrap())).unwrap();
+        let streamed = collect_events(BufReader::new(File::open(&path).unwrap())).unwrap();
+        assert_eq!(streamed, expected, "{}", fixture);
+    }
+}
+
+#[test]
+fn gpx_reader_read_streaming_gpx10() {
+    let xml = r#"<gpx version="1.0">
+    <name>Morning run</name>
+    <wpt lat="47.0" lon="8.0"><name>Start</name></wpt>
+    <trk><name>Run</name><trkseg>
+        <trkpt lat="47.1" lon="8.0"/>
+        <trkpt lat="47.2" lon="8.0"/>
+    </trkseg><trkseg/></trk>
+    <rte><name>Back</name><rtept lat="47.2" lon="8.0"/></rte>
+</gpx>"#;
+
+    let streamed = collect_events(xml.as_bytes()).unwrap();
+    assert_eq!(streamed, read(xml.as_bytes()).unwrap());
+    assert_eq!(
+        streamed.metadata.unwrap().name.as_deref(),
+        Some("Morning run")
+    );
+    assert_eq!(streamed.tracks[0].segments.len(), 2);
+}
+
+#[test]
+fn gpx_reader_read_streaming_error() {
+    let xml = r#"<gpx version="1.1"><trk><trkseg>
+    <trkpt lat="47.1" lon="8.0"/>
+    <trkpt lat="north" lon="8.0"/>
+</trkseg></trk></gpx>"#;
+
+    let events: Vec<_> = read_streaming(xml.as_bytes()).collect();
+    assert_eq!(events.len(), 5);
+    assert!(events[..4].iter().all(|event| event.is_ok()));
+    assert!(matches!(events[1], Ok(GpxEvent::TrackStart(_))));
+    assert!(matches!(events[3], Ok(GpxEvent::TrackPoint(_))));
+    match &events[4] {
+        Err(err) => assert!(matches!(err.kind(), ErrorKind::AtPosition(3, ..))),
+        Ok(event) => panic!("expected an error, got {:?}", event),
+    }
+
+    // A document that is cut off ends with an error too.
+    let events: Vec<_> = read_streaming(&xml.as_bytes()[..40]).collect();
+    assert!(events.last().unwrap().is_err());
+}
@@ -1,17 +1,34 @@
 use std::fs::File;
 use std::io::BufReader;
 
-use gpx::{read, write};
-use gpx::{Gpx, Link, Waypoint};
+use chrono::{Duration, TimeZone, Timelike, Utc};
+use geo_types::Point;
+
+use gpx::{
+    append_track_points, finalize, read, write, write_to_bytes, write_unfinalized, write_with_hook,
+    write_with_options, MetadataTimePolicy, WaypointSort, WriteOptions,
+    PLAYBACK_EXTENSION_NAMESPACE,
+};
+use gpx::{
+    Copyright, ExtensionNode, Gpx, GpxVersion, Link, Metadata, Track, TrackSegment, Waypoint,
+    WaypointBuilder,
+};
 
 #[test]
 fn gpx_writer_write_unknown_gpx_version() {
     let gpx: Gpx = Default::default();
     let mut writer: Vec<u8> = Vec::new();
-    // Should fail with unknown version.
-    let result = write(&gpx, &mut writer);
+    // An empty document of unknown version is written as GPX 1.1.
+    write(&gpx, &mut writer).unwrap();
+
+    let output = String::from_utf8(writer.clone()).unwrap();
+    assert!(output.contains("version=\"1.1\""));
+    assert!(output.contains("creator=\"https://github.com/georust/gpx\""));
 
-    assert!(result.is_err());
+    let written_gpx = read(writer.as_slice()).unwrap();
+    assert_eq!(written_gpx.version, GpxVersion::Gpx11);
+    assert!(written_gpx.tracks.is_empty());
+    assert!(written_gpx.waypoints.is_empty());
 }
 
 #[test]
@@ -29,6 +46,101 @@ fn gpx_writer_write_test_with_accuracy() {
     check_write_for_example_file("tests/fixtures/with_accuracy.gpx");
 }
 
+#[test]
+fn gpx_writer_write_fractional_seconds() {
+    let xml = r#"<gpx version="1.1">
+    <wpt lat="47.0" lon="8.0"><time>2023-05-01T14:34:56.789+02:00</time></wpt>
+    <wpt lat="47.0" lon="8.0"><time>2023-05-01T12:34:57Z</time></wpt>
+</gpx>"#;
+    let gpx = read(xml.as_bytes()).unwrap();
+
+    let output = String::from_utf8(write_to_bytes(&gpx).unwrap()).unwrap();
+    assert!(output.contains("<time>2023-05-01T12:34:56.789+00:00</time>"));
+    assert!(output.contains("<time>2023-05-01T12:34:57+00:00</time>"));
+    let written = read(output.as_bytes()).unwrap();
+    assert_eq!(written.waypoints[0].time, gpx.waypoints[0].time);
+}
+
+#[test]
+fn gpx_writer_write_unknown_version() {
+    let gpx =
+        read("<gpx version=\"1.2\"><wpt lat=\"47.0\" lon=\"8.0\"></wpt></gpx>".as_bytes()).unwrap();
+
+    let output = String::from_utf8(write_to_bytes(&gpx).unwrap()).unwrap();
+    assert!(output.contains("version=\"1.2\""));
+    assert!(output.contains("xmlns=\"http://www.topografix.com/GPX/1/1\""));
+    assert_eq!(
+        read(output.as_bytes()).unwrap().declared_version,
+        gpx.declared_version
+    );
+}
+
+#[test]
+fn gpx_writer_write_gpx10_course_and_speed() {
+    let xml = r#"<gpx version="1.0">
+    <wpt lat="47.0" lon="8.0"><speed>2.5</speed><course>90.5</course></wpt>
+</gpx>"#;
+    let mut gpx = read(xml.as_bytes()).unwrap();
+    assert_eq!(gpx.waypoints[0].course, Some(90.5));
+
+    let output = String::from_utf8(write_to_bytes(&gpx).unwrap()).unwrap();
+    assert!(output.contains("<course>90.5</course>"));
+    assert!(output.contains("<speed>2.5</speed>"));
+    let written = read(output.as_bytes()).unwrap();
+    assert_eq!(written.waypoints[0].course, Some(90.5));
+    assert_eq!(written.waypoints[0].speed, Some(2.5));
+
+    // GPX 1.1 has neither.
+    gpx.version = GpxVersion::Gpx11;
+    let output = String::from_utf8(write_to_bytes(&gpx).unwrap()).unwrap();
+    assert!(!output.contains("course"));
+    assert!(!output.contains("speed"));
+}
+
+#[test]
+fn gpx_writer_write_gpx10_without_extensions() {
+    let xml = r#"<gpx version="1.1" xmlns:ex="urn:example"
+        xmlns:gpxtpx="http://www.garmin.com/xmlschemas/TrackPointExtension/v1">
+    <wpt lat="47.0" lon="8.0"><name>Summit</name><extensions><ex:note>cairn</ex:note></extensions></wpt>
+    <rte>
+        <name>Way up</name>
+        <extensions><ex:color>red</ex:color></extensions>
+        <rtept lat="47.0" lon="8.0"><extensions><ex:note>start</ex:note></extensions></rtept>
+    </rte>
+    <trk>
+        <name>Loop</name>
+        <extensions><ex:color>blue</ex:color></extensions>
+        <trkseg>
+            <trkpt lat="47.0" lon="8.0">
+                <ele>400</ele>
+                <extensions>
+                    <gpxtpx:TrackPointExtension><gpxtpx:hr>120</gpxtpx:hr></gpxtpx:TrackPointExtension>
+                </extensions>
+            </trkpt>
+        </trkseg>
+    </trk>
+    <extensions><ex:summary>fine</ex:summary></extensions>
+</gpx>"#;
+    let mut gpx = read(xml.as_bytes()).unwrap();
+    gpx.version = GpxVersion::Gpx10;
+
+    // GPX 1.0 has no extensions element, so none are written.
+    let output = String::from_utf8(write_to_bytes(&gpx).unwrap()).unwrap();
+    assert!(!output.contains("extensions"));
+    assert!(!output.contains("urn:example"));
+    assert!(!output.contains("TrackPointExtension"));
+
+    let written_gpx = read(output.as_bytes()).unwrap();
+    assert_eq!(written_gpx.version, GpxVersion::Gpx10);
+    assert_eq!(written_gpx.waypoints[0].name.as_deref(), Some("Summit"));
+    assert_eq!(written_gpx.routes[0].name.as_deref(), Some("Way up"));
+    assert_eq!(written_gpx.routes[0].points.len(), 1);
+    assert_eq!(written_gpx.tracks[0].name.as_deref(), Some("Loop"));
+    let point = &written_gpx.tracks[0].segments[0].points[0];
+    assert_eq!(point.elevation, Some(400.0));
+    assert_eq!(point.track_point_extension, None);
+}
+
 fn check_write_for_example_file(filename: &str) {
     let reference_gpx = read_test_gpx_file(filename);
     let written_gpx = write_and_reread_gpx(&reference_gpx);
@@ -49,7 +161,7 @@ fn read_test_gpx_file(filename: &str) -> Gpx {
 
 fn write_and_reread_gpx(reference_gpx: &Gpx) -> Gpx {
     let mut buffer: Vec<u8> = Vec::new();
-    let result = write(&reference_gpx, &mut buffer);
+    let result = write(reference_gpx, &mut buffer);
     assert!(result.is_ok());
 
     let written_gpx = read(buffer.as_slice()).unwrap();
@@ -69,10 +181,11 @@ fn check_metadata_equal(reference_gpx: &Gpx, written_gpx: &Gpx) {
     let written = written.as_ref().unwrap();
     assert_eq!(reference.name, written.name);
     assert_eq!(reference.time, written.time);
+    assert_eq!(reference.copyright, written.copyright);
     check_links_equal(&reference.links, &written.links);
 }
 
-fn check_links_equal(reference: &Vec<Link>, written: &Vec<Link>) {
+fn check_links_equal(reference: &[Link], written: &[Link]) {
     assert_eq!(reference.len(), written.len());
     for (r, w) in reference.iter().zip(written) {
         assert_eq!(r.href, w.href);
@@ -92,7 +205,7 @@ fn check_points_equal(reference: &Gpx, written: &Gpx) {
     }
 }
 
-fn check_waypoints_equal(reference: &Vec<Waypoint>, written: &Vec<Waypoint>) {
+fn check_waypoints_equal(reference: &[Waypoint], written: &[Waypoint]) {
     assert_eq!(reference.len(), written.len());
     for (r_wp, w_wp) in reference.iter().zip(written) {
         assert_eq!(r_wp.point(), w_wp.point());
@@ -114,5 +227,1135 @@ fn check_waypoints_equal(reference: &Vec<Waypoint>, written: &Vec<Waypoint>) {
         assert_eq!(r_wp.pdop, w_wp.pdop);
         assert_eq!(r_wp.dgps_age, w_wp.dgps_age);
         assert_eq!(r_wp.dgpsid, w_wp.dgpsid);
+        assert_eq!(r_wp.extensions, w_wp.extensions);
+        assert_eq!(r_wp.track_point_extension, w_wp.track_point_extension);
+    }
+}
+
+#[test]
+fn gpx_writer_write_header_comment() {
+    let reference_gpx = read_test_gpx_file("tests/fixtures/wikipedia_example.gpx");
+    let options = WriteOptions {
+        header_comment: Some(String::from("Generated by MyApp -- v1")),
+        ..Default::default()
+    };
+
+    let mut buffer: Vec<u8> = Vec::new();
+    write_with_options(&reference_gpx, &mut buffer, &options).unwrap();
+
+    let output = String::from_utf8(buffer.clone()).unwrap();
+    assert!(output.starts_with("<?xml"));
+    let comment_start = output.find("<!-- Generated by MyApp").unwrap();
+    assert!(comment_start < output.find("<gpx").unwrap());
+
+    // The comment does not get in the way of reading the file back.
+    let written_gpx = read(buffer.as_slice()).unwrap();
+    check_metadata_equal(&reference_gpx, &written_gpx);
+    check_points_equal(&reference_gpx, &written_gpx);
+}
+
+#[test]
+fn gpx_writer_write_metadata_time() {
+    let time = Utc.with_ymd_and_hms(2020, 6, 1, 8, 0, 0).unwrap();
+    let mut gpx = Gpx {
+        version: GpxVersion::Gpx11,
+        metadata: Some(Metadata {
+            name: Some("Trip".into()),
+            time: Some(time),
+            ..Default::default()
+        }),
+        ..Default::default()
+    };
+    let write_with_policy = |gpx: &Gpx, metadata_time: MetadataTimePolicy| {
+        let options = WriteOptions {
+            metadata_time,
+            ..Default::default()
+        };
+        let mut buffer: Vec<u8> = Vec::new();
+        write_with_options(gpx, &mut buffer, &options).unwrap();
+        read(buffer.as_slice()).unwrap().metadata.unwrap()
+    };
+
+    let preserved = write_with_policy(&gpx, MetadataTimePolicy::default());
+    assert_eq!(preserved.time, Some(time));
+
+    let before = Utc::now();
+    let updated = write_with_policy(&gpx, MetadataTimePolicy::Now);
+    assert!(updated.time.unwrap() >= before.with_nanosecond(0).unwrap());
+    assert_eq!(updated.name, Some("Trip".into()));
+
+    let omitted = write_with_policy(&gpx, MetadataTimePolicy::Omit);
+    assert_eq!(omitted.time, None);
+    assert_eq!(omitted.name, Some("Trip".into()));
+
+    // The time of writing is given even to documents without metadata.
+    gpx.metadata = None;
+    let added = write_with_policy(&gpx, MetadataTimePolicy::Now);
+    assert!(added.time.is_some());
+}
+
+#[test]
+fn gpx_writer_write_empty_metadata() {
+    let mut gpx = Gpx {
+        version: GpxVersion::Gpx11,
+        ..Default::default()
+    };
+    let write_with_empty = |gpx: &Gpx, emit_empty_metadata| {
+        let options = WriteOptions {
+            emit_empty_metadata,
+            ..Default::default()
+        };
+        let mut buffer: Vec<u8> = Vec::new();
+        write_with_options(gpx, &mut buffer, &options).unwrap();
+        String::from_utf8(buffer).unwrap()
+    };
+
+    let output = write_with_empty(&gpx, false);
+    assert!(!output.contains("metadata"));
+    assert_eq!(read(output.as_bytes()).unwrap().metadata, None);
+
+    let output = write_with_empty(&gpx, true);
+    assert!(output.contains("<metadata />"));
+    assert_eq!(
+        read(output.as_bytes()).unwrap().metadata,
+        Some(Metadata::default())
+    );
+
+    // Metadata that there is is written as it is.
+    gpx.metadata = Some(Metadata {
+        name: Some("Trip".into()),
+        ..Default::default()
+    });
+    let output = write_with_empty(&gpx, true);
+    assert!(output.contains("<metadata>\n    <name>Trip</name>"));
+
+    // GPX 1.0 has no metadata element.
+    gpx.version = GpxVersion::Gpx10;
+    gpx.metadata = None;
+    assert!(!write_with_empty(&gpx, true).contains("metadata"));
+}
+
+#[test]
+fn gpx_writer_write_time_grid() {
+    let start = Utc.with_ymd_and_hms(2020, 6, 1, 8, 0, 0).unwrap();
+    let mut segment = TrackSegment::new();
+    for millis in &[0, 1_400, 1_600, 2_500, 3_000] {
+        segment.points.push(
+            WaypointBuilder::new(Point::new(-121.97, 37.24))
+                .time(start + Duration::milliseconds(*millis))
+                .build(),
+        );
+    }
+    let mut track = Track::new();
+    track.segments.push(segment);
+    let mut gpx = Gpx {
+        version: GpxVersion::Gpx11,
+        ..Default::default()
+    };
+    gpx.tracks.push(track);
+
+    let options = WriteOptions {
+        time_grid: Some(Duration::seconds(1)),
+        ..Default::default()
+    };
+    let mut buffer: Vec<u8> = Vec::new();
+    write_with_options(&gpx, &mut buffer, &options).unwrap();
+
+    let written_gpx = read(buffer.as_slice()).unwrap();
+    let millis: Vec<i64> = written_gpx.tracks[0].segments[0]
+        .points
+        .iter()
+        .map(|wpt| (wpt.time.unwrap() - start).num_milliseconds())
+        .collect();
+    assert_eq!(millis, vec![0, 1_000, 2_000, 3_000, 3_000]);
+    assert!(String::from_utf8(buffer)
+        .unwrap()
+        .contains("<time>2020-06-01T08:00:01+00:00</time>"));
+}
+
+#[test]
+fn gpx_writer_write_link_order() {
+    let mut wpt = Waypoint::new(Point::new(-121.97, 37.24));
+    for href in &[
+        "https://c.example",
+        "https://a.example",
+        "https://b.example",
+    ] {
+        wpt.links.push(Link {
+            href: href.to_string(),
+            ..Default::default()
+        });
+    }
+    let mut gpx = Gpx {
+        version: GpxVersion::Gpx11,
+        ..Default::default()
+    };
+    gpx.waypoints.push(wpt);
+
+    for canonical in &[false, true] {
+        let options = WriteOptions {
+            canonical: *canonical,
+            ..Default::default()
+        };
+        let mut buffer: Vec<u8> = Vec::new();
+        write_with_options(&gpx, &mut buffer, &options).unwrap();
+
+        let output = String::from_utf8(buffer).unwrap();
+        let positions: Vec<usize> = gpx.waypoints[0]
+            .links
+            .iter()
+            .map(|link| output.find(&link.href).unwrap())
+            .collect();
+        assert!(positions.windows(2).all(|pair| pair[0] < pair[1]));
+        let written_gpx = read(output.as_bytes()).unwrap();
+        assert_eq!(written_gpx.waypoints[0].links, gpx.waypoints[0].links);
+    }
+}
+
+#[test]
+fn gpx_writer_write_summary_extensions() {
+    let xml = r#"<?xml version="1.0" encoding="UTF-8"?>
+<gpx version="1.1" creator="unit test" xmlns="http://www.topografix.com/GPX/1/1"
+     xmlns:ns3="http://www.garmin.com/xmlschemas/TrackStatsExtension/v1">
+    <trk>
+        <trkseg>
+            <trkpt lat="37.24" lon="-121.97"></trkpt>
+        </trkseg>
+    </trk>
+    <extensions>
+        <ns3:TrackStatsExtension>
+            <ns3:Calories>512</ns3:Calories>
+            <ns3:AvgHeartRate units="bpm">142</ns3:AvgHeartRate>
+            <ns3:Laps/>
+        </ns3:TrackStatsExtension>
+    </extensions>
+</gpx>"#;
+    let reference_gpx = read(xml.as_bytes()).unwrap();
+    let extensions = reference_gpx.summary_extensions.as_ref().unwrap();
+    assert_eq!(extensions.children.len(), 1);
+
+    let written_gpx = write_and_reread_gpx(&reference_gpx);
+    assert_eq!(
+        reference_gpx.summary_extensions,
+        written_gpx.summary_extensions
+    );
+    check_points_equal(&reference_gpx, &written_gpx);
+}
+
+#[test]
+fn gpx_writer_write_processing_instructions() {
+    let xml = r#"<?xml version="1.0" encoding="UTF-8"?>
+<?xml-stylesheet type="text/xsl" href="gpx.xsl"?>
+<gpx version="1.1" creator="unit test">
+    <wpt lat="1.0" lon="2.0"></wpt>
+</gpx>"#;
+    let reference_gpx = read(xml.as_bytes()).unwrap();
+    assert_eq!(
+        reference_gpx.processing_instructions,
+        vec![String::from(
+            r#"xml-stylesheet type="text/xsl" href="gpx.xsl""#
+        )]
+    );
+
+    let mut buffer: Vec<u8> = Vec::new();
+    write(&reference_gpx, &mut buffer).unwrap();
+    let output = String::from_utf8(buffer).unwrap();
+    assert!(output.contains(r#"<?xml-stylesheet type="text/xsl" href="gpx.xsl"?>"#));
+
+    let written_gpx = read(output.as_bytes()).unwrap();
+    assert_eq!(
+        reference_gpx.processing_instructions,
+        written_gpx.processing_instructions
+    );
+    check_points_equal(&reference_gpx, &written_gpx);
+}
+
+#[test]
+fn gpx_writer_write_to_bytes() {
+    let reference_gpx = read_test_gpx_file("tests/fixtures/wikipedia_example.gpx");
+
+    let mut buffer: Vec<u8> = Vec::new();
+    write(&reference_gpx, &mut buffer).unwrap();
+    let bytes = write_to_bytes(&reference_gpx).unwrap();
+
+    assert_eq!(bytes, buffer);
+}
+
+#[test]
+fn gpx_writer_write_waypoint_extensions() {
+    let point = WaypointBuilder::new(Point::new(-121.97, 37.24))
+        .elevation(553.21)
+        .extension("heartrate", 142)
+        .extension("power", 250.5)
+        .build();
+    let mut segment = TrackSegment::new();
+    segment.points.push(point);
+    let mut track = Track::new();
+    track.segments.push(segment);
+    let mut reference_gpx = Gpx {
+        version: GpxVersion::Gpx11,
+        ..Default::default()
+    };
+    reference_gpx.tracks.push(track);
+
+    let output = String::from_utf8(write_to_bytes(&reference_gpx).unwrap()).unwrap();
+    // The elements have no namespace, and are kept out of the GPX one.
+    assert!(output.contains(r#"<heartrate xmlns="">142</heartrate>"#));
+    assert!(output.contains(r#"<power xmlns="">250.5</power>"#));
+
+    let written_gpx = write_and_reread_gpx(&reference_gpx);
+    check_points_equal(&reference_gpx, &written_gpx);
+}
+
+#[test]
+fn gpx_writer_write_mixed_content_extensions() {
+    let xml = r#"<gpx version="1.1">
+    <wpt lat="37.24" lon="-121.97">
+        <extensions>
+            <note>Climb <b>1</b> <i>of 3</i>, steep</note>
+        </extensions>
+    </wpt>
+</gpx>"#;
+    let reference_gpx = read(xml.as_bytes()).unwrap();
+
+    let output = String::from_utf8(write_to_bytes(&reference_gpx).unwrap()).unwrap();
+    assert!(output.contains("Climb <b>1</b> <i>of 3</i>, steep</note>"));
+
+    let written_gpx = write_and_reread_gpx(&reference_gpx);
+    check_waypoints_equal(&reference_gpx.waypoints, &written_gpx.waypoints);
+}
+
+#[test]
+fn gpx_writer_write_namespace() {
+    let reference_gpx = read_test_gpx_file("tests/fixtures/wikipedia_example.gpx");
+
+    let output = String::from_utf8(write_to_bytes(&reference_gpx).unwrap()).unwrap();
+    assert!(output.contains(r#"xmlns="http://www.topografix.com/GPX/1/1""#));
+
+    let options = WriteOptions {
+        namespace: Some(String::from("http://www.example.com/GPX/1/1")),
+        ..Default::default()
+    };
+    let mut buffer: Vec<u8> = Vec::new();
+    write_with_options(&reference_gpx, &mut buffer, &options).unwrap();
+    let output = String::from_utf8(buffer).unwrap();
+    assert!(output.contains(r#"<gpx xmlns="http://www.example.com/GPX/1/1""#));
+
+    let written_gpx = read(output.as_bytes()).unwrap();
+    check_points_equal(&reference_gpx, &written_gpx);
+}
+
+#[test]
+fn gpx_writer_write_variant_namespace() {
+    let xml = r#"<gpx version="1.1" xmlns="http://www.topografix.com/GPX/1/1/variant">
+    <wpt lat="37.24" lon="-121.97"><name>Start</name></wpt>
+</gpx>"#;
+    let reference_gpx = read(xml.as_bytes()).unwrap();
+    assert_eq!(
+        reference_gpx.namespace,
+        Some(String::from("http://www.topografix.com/GPX/1/1/variant"))
+    );
+    assert_eq!(reference_gpx.waypoints[0].name, Some(String::from("Start")));
+
+    let output = String::from_utf8(write_to_bytes(&reference_gpx).unwrap()).unwrap();
+    assert!(output.contains(r#"xmlns="http://www.topografix.com/GPX/1/1/variant""#));
+
+    let written_gpx = read(output.as_bytes()).unwrap();
+    assert_eq!(reference_gpx.namespace, written_gpx.namespace);
+    check_points_equal(&reference_gpx, &written_gpx);
+}
+
+#[test]
+fn gpx_writer_write_namespace_of_other_version() {
+    let mut gpx = read_test_gpx_file("tests/fixtures/wikipedia_example.gpx");
+    assert_eq!(
+        gpx.namespace.as_deref(),
+        Some("http://www.topografix.com/GPX/1/1")
+    );
+
+    // Written as GPX 1.0, the document is in the namespace of GPX 1.0.
+    gpx.version = GpxVersion::Gpx10;
+    let output = String::from_utf8(write_to_bytes(&gpx).unwrap()).unwrap();
+    assert!(output.contains(r#"<gpx xmlns="http://www.topografix.com/GPX/1/0""#));
+    assert!(!output.contains("http://www.topografix.com/GPX/1/1\""));
+
+    let written_gpx = read(output.as_bytes()).unwrap();
+    assert_eq!(written_gpx.version, GpxVersion::Gpx10);
+}
+
+#[test]
+fn gpx_writer_write_without_namespace() {
+    for (version, namespace) in &[
+        ("1.0", "http://www.topografix.com/GPX/1/0"),
+        ("1.1", "http://www.topografix.com/GPX/1/1"),
+    ] {
+        let xml = format!(
+            r#"<gpx version="{}">
+    <wpt lat="37.24" lon="-121.97"><ele>12</ele><name>Start</name></wpt>
+    <trk><name>Loop</name><trkseg>
+        <trkpt lat="37.24" lon="-121.97"><ele>12</ele></trkpt>
+        <trkpt lat="37.25" lon="-121.98"><ele>14</ele></trkpt>
+    </trkseg></trk>
+</gpx>"#,
+            version
+        );
+        let reference_gpx = read(xml.as_bytes()).unwrap();
+        assert_eq!(reference_gpx.namespace, None);
+        assert_eq!(reference_gpx.waypoints[0].name.as_deref(), Some("Start"));
+        assert_eq!(reference_gpx.tracks[0].segments[0].points.len(), 2);
+
+        // The standard namespace is added.
+        let output = String::from_utf8(write_to_bytes(&reference_gpx).unwrap()).unwrap();
+        assert!(output.contains(&format!(r#"<gpx xmlns="{}""#, namespace)));
+
+        let written_gpx = read(output.as_bytes()).unwrap();
+        assert_eq!(written_gpx.namespace.as_deref(), Some(*namespace));
+        assert_eq!(written_gpx.waypoints, reference_gpx.waypoints);
+        assert_eq!(written_gpx.tracks, reference_gpx.tracks);
+    }
+}
+
+#[test]
+fn gpx_writer_write_unfinalized() {
+    let mut reference_gpx = read_test_gpx_file("tests/fixtures/wikipedia_example.gpx");
+    let points = reference_gpx.tracks[0].segments[0].points.split_off(2);
+
+    // Points are appended to the file as they are recorded.
+    let mut file: Vec<u8> = Vec::new();
+    write_unfinalized(&reference_gpx, &mut file).unwrap();
+    assert!(read(file.as_slice()).is_err());
+    for point in points.chunks(1) {
+        append_track_points(point, &mut file).unwrap();
+    }
+    assert!(read(file.as_slice()).is_err());
+    finalize(&mut file).unwrap();
+
+    let written_gpx = read(file.as_slice()).unwrap();
+    reference_gpx.tracks[0].segments[0].points.extend(points);
+    check_metadata_equal(&reference_gpx, &written_gpx);
+    check_points_equal(&reference_gpx, &written_gpx);
+
+    let mut with_route = reference_gpx.clone();
+    with_route.routes.push(Default::default());
+    assert!(write_unfinalized(&with_route, &mut Vec::new()).is_err());
+}
+
+#[test]
+fn gpx_writer_write_copyright() {
+    let mut reference_gpx = read_test_gpx_file("tests/fixtures/wikipedia_example.gpx");
+    reference_gpx.metadata = Some(Metadata {
+        copyright: Some(Copyright {
+            author: String::from("OpenStreetMap contributors"),
+            year: Some(2020),
+            license: Some(String::from("https://opendatacommons.org/licenses/odbl/")),
+        }),
+        ..Default::default()
+    });
+
+    let written_gpx = write_and_reread_gpx(&reference_gpx);
+    check_metadata_equal(&reference_gpx, &written_gpx);
+}
+
+#[test]
+fn gpx_writer_write_sorted_waypoints() {
+    let mut reference_gpx = read_test_gpx_file("tests/fixtures/wikipedia_example.gpx");
+    let mut waypoints = Vec::new();
+    for (name, seconds) in &[
+        (Some("b"), 20),
+        (None, 10),
+        (Some("c"), 30),
+        (Some("a"), 40),
+    ] {
+        let mut wpt = Waypoint::new(Point::new(-121.97, 37.24));
+        wpt.name = name.map(String::from);
+        wpt.time = Some(Utc.with_ymd_and_hms(2020, 6, 1, 8, 0, *seconds).unwrap());
+        waypoints.push(wpt);
+    }
+    reference_gpx.waypoints = waypoints;
+
+    let write_sorted = |sort_waypoints_by| {
+        let options = WriteOptions {
+            sort_waypoints_by,
+            ..Default::default()
+        };
+        let mut buffer: Vec<u8> = Vec::new();
+        write_with_options(&reference_gpx, &mut buffer, &options).unwrap();
+        let written_gpx = read(buffer.as_slice()).unwrap();
+        written_gpx
+            .waypoints
+            .iter()
+            .map(|wpt| wpt.name.clone().unwrap_or_default())
+            .collect::<Vec<String>>()
+    };
+
+    assert_eq!(write_sorted(None), vec!["b", "", "c", "a"]);
+    assert_eq!(
+        write_sorted(Some(WaypointSort::Name)),
+        vec!["a", "b", "c", ""]
+    );
+    assert_eq!(
+        write_sorted(Some(WaypointSort::Time)),
+        vec!["", "b", "c", "a"]
+    );
+}
+
+#[test]
+fn gpx_writer_write_track_and_route_extensions() {
+    let data = "<gpx version=\"1.1\" creator=\"test\" xmlns=\"http://www.topografix.com/GPX/1/1\"
+        xmlns:gpxx=\"http://www.garmin.com/xmlschemas/GpxExtensions/v3\">
+        <rte>
+            <name>route</name>
+            <extensions><gpxx:RouteExtension><gpxx:IsAutoNamed>false</gpxx:IsAutoNamed></gpxx:RouteExtension></extensions>
+            <rtept lat=\"1.0\" lon=\"2.0\"><extensions><gpxx:RoutePointExtension/></extensions></rtept>
+        </rte>
+        <trk>
+            <name>track</name>
+            <extensions><gpxx:TrackExtension><gpxx:DisplayColor>Red</gpxx:DisplayColor></gpxx:TrackExtension></extensions>
+            <trkseg><trkpt lat=\"1.0\" lon=\"2.0\"></trkpt></trkseg>
+        </trk>
+    </gpx>";
+    let reference_gpx = read(data.as_bytes()).unwrap();
+    assert!(reference_gpx.tracks[0].extensions.is_some());
+    assert!(reference_gpx.routes[0].extensions.is_some());
+    assert!(reference_gpx.routes[0].points[0].extensions.is_some());
+
+    let written_gpx = write_and_reread_gpx(&reference_gpx);
+    assert_eq!(written_gpx, reference_gpx);
+}
+
+#[test]
+fn gpx_writer_write_track_point_extension() {
+    let reference_gpx = read_test_gpx_file("tests/fixtures/garmin-activity.gpx");
+
+    let mut buffer: Vec<u8> = Vec::new();
+    write(&reference_gpx, &mut buffer).unwrap();
+    let output = String::from_utf8(buffer.clone()).unwrap();
+    // Written with the prefix of the file.
+    assert!(output.contains("<ns3:hr>74</ns3:hr>"));
+    assert!(!output.contains("gpxtpx"));
+
+    let written_gpx = read(buffer.as_slice()).unwrap();
+    check_points_equal(&reference_gpx, &written_gpx);
+}
+
+#[test]
+fn gpx_writer_write_wrapped_track_point_extension() {
+    let xml = r#"<gpx version="1.1" xmlns:gpxtpx="http://www.garmin.com/xmlschemas/TrackPointExtension/v1">
+    <trk><trkseg>
+        <trkpt lat="37.24" lon="-121.97">
+            <extensions>
+                <gpxtpx:TrackPointExtension>
+                    <gpxtpx:Extensions><gpxtpx:atemp>19.5</gpxtpx:atemp><gpxtpx:hr>138</gpxtpx:hr></gpxtpx:Extensions>
+                </gpxtpx:TrackPointExtension>
+            </extensions>
+        </trkpt>
+    </trkseg></trk>
+</gpx>"#;
+    let gpx = read(xml.as_bytes()).unwrap();
+    let point = &gpx.tracks[0].segments[0].points[0];
+    let fields = point.track_point_extension.as_ref().unwrap();
+    assert_eq!(fields.hr, Some(138));
+    assert_eq!(fields.atemp, Some(19.5));
+    assert!(point.extensions.is_none());
+
+    let output = String::from_utf8(write_to_bytes(&gpx).unwrap()).unwrap();
+    let wrapper = output.find("<gpxtpx:Extensions>").unwrap();
+    assert!(wrapper > output.find("<gpxtpx:TrackPointExtension").unwrap());
+    assert!(wrapper < output.find("<gpxtpx:hr>138</gpxtpx:hr>").unwrap());
+
+    let written_gpx = read(output.as_bytes()).unwrap();
+    assert_eq!(written_gpx.tracks, gpx.tracks);
+}
+
+#[test]
+fn gpx_writer_write_track_point_extension_v2() {
+    let xml = r#"<gpx version="1.1" xmlns:tpx="http://www.garmin.com/xmlschemas/TrackPointExtension/v2"
+    xmlns:acme="http://example.com/acme">
+    <trk><trkseg>
+        <trkpt lat="37.24" lon="-121.97">
+            <extensions>
+                <acme:TrackPointExtension><acme:hr>99</acme:hr></acme:TrackPointExtension>
+                <tpx:TrackPointExtension><tpx:hr>140</tpx:hr><tpx:speed>3.2</tpx:speed></tpx:TrackPointExtension>
+            </extensions>
+        </trkpt>
+    </trkseg></trk>
+</gpx>"#;
+    let gpx = read(xml.as_bytes()).unwrap();
+    let point = &gpx.tracks[0].segments[0].points[0];
+    assert_eq!(point.track_point_extension.as_ref().unwrap().hr, Some(140));
+    assert_eq!(point.extensions.as_ref().unwrap().children.len(), 2);
+
+    // The heart rate goes back into the version 2 element, next to the speed.
+    let output = String::from_utf8(write_to_bytes(&gpx).unwrap()).unwrap();
+    let element = output.find("<tpx:TrackPointExtension>").unwrap();
+    assert!(element < output.find("<tpx:hr>140</tpx:hr>").unwrap());
+    assert!(output.find("<tpx:hr>140</tpx:hr>").unwrap() < output.find("<tpx:speed>").unwrap());
+    assert_eq!(output.matches("<tpx:TrackPointExtension>").count(), 1);
+    assert!(output.contains("<acme:hr>99</acme:hr>"));
+    assert!(!output.contains("TrackPointExtension/v1"));
+
+    let written_gpx = read(output.as_bytes()).unwrap();
+    assert_eq!(written_gpx.tracks, gpx.tracks);
+}
+
+#[test]
+fn gpx_writer_write_cdata_descriptions() {
+    let mut reference_gpx = read_test_gpx_file("tests/fixtures/wikipedia_example.gpx");
+    let description = "<p>Steep <b>&amp;</b> muddy</p> ]]> end";
+    let mut wpt = Waypoint::new(Point::new(-121.97, 37.24));
+    wpt.description = Some(String::from(description));
+    reference_gpx.waypoints = vec![wpt];
+    reference_gpx.tracks[0].description = Some(String::from("<i>Loop</i>"));
+
+    let write = |cdata_descriptions| {
+        let options = WriteOptions {
+            cdata_descriptions,
+            ..Default::default()
+        };
+        let mut buffer: Vec<u8> = Vec::new();
+        write_with_options(&reference_gpx, &mut buffer, &options).unwrap();
+        String::from_utf8(buffer).unwrap()
+    };
+
+    // Descriptions are escaped by default.
+    let output = write(false);
+    assert!(!output.contains("<![CDATA["));
+    assert!(output.contains("<desc>&lt;i&gt;Loop&lt;/i&gt;</desc>"));
+
+    let output = write(true);
+    assert!(output.contains("<desc><![CDATA[<i>Loop</i>]]></desc>"));
+    assert!(output.contains("<![CDATA[<p>Steep <b>&amp;</b> muddy</p> ]]]]><![CDATA[> end]]>"));
+
+    let written_gpx = read(output.as_bytes()).unwrap();
+    assert_eq!(
+        written_gpx.waypoints[0].description.as_deref(),
+        Some(description)
+    );
+    assert_eq!(
+        written_gpx.tracks[0].description.as_deref(),
+        Some("<i>Loop</i>")
+    );
+}
+
+#[test]
+fn gpx_writer_write_with_hook() {
+    let mut reference_gpx = read_test_gpx_file("tests/fixtures/garmin-activity.gpx");
+    let mut wpt = Waypoint::new(Point::new(-121.97, 37.24));
+    wpt.name = Some(String::from("Trailhead"));
+    wpt.description = Some(String::from("<b>Parking</b> ]]> here"));
+    reference_gpx.waypoints = vec![wpt];
+    let options = WriteOptions {
+        cdata_descriptions: true,
+        ..Default::default()
+    };
+    let write = |hook: &mut dyn FnMut(&mut gpx::WrittenElement) -> bool| {
+        let mut buffer: Vec<u8> = Vec::new();
+        write_with_hook(&reference_gpx, &mut buffer, &options, hook).unwrap();
+        String::from_utf8(buffer).unwrap()
+    };
+
+    // A hook that changes nothing writes the same document as without one.
+    let mut plain: Vec<u8> = Vec::new();
+    write_with_options(&reference_gpx, &mut plain, &options).unwrap();
+    assert_eq!(write(&mut |_| true), String::from_utf8(plain).unwrap());
+
+    let mut names = Vec::new();
+    let output = write(&mut |element| {
+        names.push(element.name.clone());
+        if element.name == "name" {
+            element.text = element.text.as_ref().map(|text| text.to_uppercase());
+        }
+        true
+    });
+    // Parents are given before their children.
+    assert_eq!(names[0], "gpx");
+    assert_eq!(names[1], "metadata");
+    let written_gpx = read(output.as_bytes()).unwrap();
+    assert_eq!(written_gpx.waypoints[0].name.as_deref(), Some("TRAILHEAD"));
+    assert_eq!(
+        written_gpx.waypoints[0].description,
+        reference_gpx.waypoints[0].description
+    );
+    assert_eq!(
+        written_gpx.tracks[0].name,
+        reference_gpx.tracks[0]
+            .name
+            .as_ref()
+            .map(|name| name.to_uppercase())
+    );
+
+    // Elements left out are not written along with what they hold, which is
+    // not given to the hook either.
+    let mut names = Vec::new();
+    let output = write(&mut |element| {
+        names.push(element.name.clone());
+        element.name != "extensions"
+    });
+    assert!(!output.contains("extensions"));
+    assert!(!names.iter().any(|name| name.starts_with("gpxtpx:")));
+    let written_gpx = read(output.as_bytes()).unwrap();
+    let points = &written_gpx.tracks[0].segments[0].points;
+    assert_eq!(
+        points.len(),
+        reference_gpx.tracks[0].segments[0].points.len()
+    );
+    assert!(points.iter().all(|wpt| wpt.track_point_extension.is_none()));
+}
+
+#[test]
+fn gpx_writer_write_precision() {
+    let mut reference_gpx = read_test_gpx_file("tests/fixtures/wikipedia_example.gpx");
+    let mut wpt = Waypoint::new(Point::new(-121.970_012_345_6, 37.240_000_01));
+    wpt.elevation = Some(4.461_234);
+    let mut tiny = Waypoint::new(Point::new(0.000_000_1, -0.000_000_01));
+    tiny.elevation = Some(1.0e-7);
+    reference_gpx.waypoints = vec![wpt, tiny];
+
+    let write = |coord_precision, elevation_precision| {
+        let options = WriteOptions {
+            coord_precision,
+            elevation_precision,
+            ..Default::default()
+        };
+        let mut buffer: Vec<u8> = Vec::new();
+        write_with_options(&reference_gpx, &mut buffer, &options).unwrap();
+        String::from_utf8(buffer).unwrap()
+    };
+
+    // Exact values are written by default, never in scientific notation.
+    let output = write(None, None);
+    assert!(output.contains(r#"<wpt lat="37.24000001" lon="-121.9700123456">"#));
+    assert!(output.contains("<ele>0.0000001</ele>"));
+    assert!(!output.contains("e-"));
+    let written_gpx = read(output.as_bytes()).unwrap();
+    check_waypoints_equal(&reference_gpx.waypoints, &written_gpx.waypoints);
+
+    let output = write(Some(6), Some(1));
+    assert!(output.contains(r#"<wpt lat="37.24" lon="-121.970012">"#));
+    assert!(output.contains("<ele>4.5</ele>"));
+    assert!(output.contains(r#"<wpt lat="0" lon="0">"#));
+    assert!(output.contains("<ele>0</ele>"));
+    // The points of the tracks are rounded too.
+    assert!(output.contains("<ele>4.9</ele>"));
+
+    let written_gpx = read(output.as_bytes()).unwrap();
+    assert_eq!(
+        written_gpx.waypoints[0].point(),
+        Point::new(-121.970_012, 37.24)
+    );
+    assert_eq!(written_gpx.waypoints[0].elevation, Some(4.5));
+}
+
+#[test]
+fn gpx_writer_write_bounds_precision() {
+    let data = r#"<gpx version="1.1" creator="test" xmlns="http://www.topografix.com/GPX/1/1">
+    <metadata>
+        <bounds minlat="37.240000012" minlon="-121.9700123456" maxlat="37.2612345678" maxlon="-121.9599999999"/>
+    </metadata>
+</gpx>"#;
+    let reference_gpx = read(data.as_bytes()).unwrap();
+    let options = WriteOptions {
+        coord_precision: Some(6),
+        ..Default::default()
+    };
+    let mut buffer: Vec<u8> = Vec::new();
+    write_with_options(&reference_gpx, &mut buffer, &options).unwrap();
+    let output = String::from_utf8(buffer).unwrap();
+
+    assert!(output.contains(
+        r#"<bounds minlat="37.24" maxlat="37.261235" minlon="-121.970012" maxlon="-121.96" />"#
+    ));
+}
+
+#[test]
+fn gpx_writer_write_bounds() {
+    let mut reference_gpx = read_test_gpx_file("tests/fixtures/wikipedia_example.gpx");
+    let bounds = reference_gpx.bounds().unwrap();
+    reference_gpx
+        .metadata
+        .get_or_insert_with(Default::default)
+        .bounds = Some(bounds);
+
+    let written_gpx = write_and_reread_gpx(&reference_gpx);
+    assert_eq!(written_gpx.metadata.unwrap().bounds, Some(bounds));
+}
+
+#[test]
+fn gpx_writer_write_bounds_placement() {
+    let mut gpx = read_test_gpx_file("tests/fixtures/wikipedia_example.gpx");
+    let bounds = gpx.bounds().unwrap();
+    gpx.metadata = Some(Metadata {
+        name: Some("Bounded".into()),
+        bounds: Some(bounds),
+        ..Default::default()
+    });
+    gpx.waypoints.push(Waypoint::new(Point::new(10.0, 46.5)));
+
+    gpx.version = GpxVersion::Gpx11;
+    let output = String::from_utf8(write_to_bytes(&gpx).unwrap()).unwrap();
+    let position = |tag: &str| output.find(tag).unwrap();
+    assert!(position("<metadata>") < position("<bounds"));
+    assert!(position("<bounds") < position("</metadata>"));
+    assert_eq!(
+        read(output.as_bytes()).unwrap().metadata.unwrap().bounds,
+        Some(bounds)
+    );
+
+    gpx.version = GpxVersion::Gpx10;
+    let output = String::from_utf8(write_to_bytes(&gpx).unwrap()).unwrap();
+    let position = |tag: &str| output.find(tag).unwrap();
+    assert!(!output.contains("<metadata>"));
+    // A child of the root, after the other metadata and before the points.
+    assert!(position("<name>Bounded</name>") < position("<bounds"));
+    assert!(position("<bounds") < position("<wpt"));
+    assert_eq!(
+        read(output.as_bytes()).unwrap().metadata.unwrap().bounds,
+        Some(bounds)
+    );
+}
+
+#[test]
+fn gpx_writer_write_root_namespaces() {
+    let gpx = read_test_gpx_file("tests/fixtures/garmin-activity.gpx");
+    assert!(gpx.namespaces.contains(&(
+        "ns3".into(),
+        "http://www.garmin.com/xmlschemas/TrackPointExtension/v1".into()
+    )));
+    assert!(gpx.schema_location.is_some());
+
+    let output = String::from_utf8(write_to_bytes(&gpx).unwrap()).unwrap();
+    let root = &output[output.find("<gpx").unwrap()..];
+    let root = &root[..root.find('>').unwrap()];
+    assert!(root.contains("xmlns:ns3=\"http://www.garmin.com/xmlschemas/TrackPointExtension/v1\""));
+    assert!(root.contains("xsi:schemaLocation=\"http://www.topografix.com/GPX/1/1"));
+
+    // The Garmin GpxExtensions namespace is not used by any element.
+    assert!(!root.contains("xmlns:ns2"));
+
+    let written_gpx = read(output.as_bytes()).unwrap();
+    let used: Vec<_> = gpx
+        .namespaces
+        .iter()
+        .filter(|(prefix, _)| prefix != "ns2")
+        .cloned()
+        .collect();
+    assert_eq!(written_gpx.namespaces, used);
+    assert_eq!(written_gpx.schema_location, gpx.schema_location);
+
+    // Without any, only the GPX namespace is declared.
+    let plain = Gpx {
+        schema_location: Some("http://www.topografix.com/GPX/1/1 gpx.xsd".into()),
+        ..Default::default()
+    };
+    let output = String::from_utf8(write_to_bytes(&plain).unwrap()).unwrap();
+    assert!(output.contains("xmlns:xsi=\"http://www.w3.org/2001/XMLSchema-instance\""));
+    assert_eq!(
+        read(output.as_bytes()).unwrap().schema_location,
+        plain.schema_location
+    );
+}
+
+#[test]
+fn gpx_writer_write_schema_location() {
+    let mut gpx = Gpx {
+        version: GpxVersion::Gpx11,
+        ..Default::default()
+    };
+    let options = WriteOptions {
+        emit_schema_location: true,
+        ..Default::default()
+    };
+    let write = |gpx: &Gpx, options: &WriteOptions| {
+        let mut buffer: Vec<u8> = Vec::new();
+        write_with_options(gpx, &mut buffer, options).unwrap();
+        String::from_utf8(buffer).unwrap()
+    };
+
+    let output = write(&gpx, &Default::default());
+    assert!(!output.contains("schemaLocation"));
+    assert!(!output.contains("xmlns:xsi"));
+
+    let output = write(&gpx, &options);
+    assert!(output.contains("xmlns:xsi=\"http://www.w3.org/2001/XMLSchema-instance\""));
+    assert!(output.contains(
+        "xsi:schemaLocation=\"http://www.topografix.com/GPX/1/1 http://www.topografix.com/GPX/1/1/gpx.xsd\""
+    ));
+    assert!(read(output.as_bytes()).is_ok());
+
+    gpx.version = GpxVersion::Gpx10;
+    let output = write(&gpx, &options);
+    assert!(output.contains("http://www.topografix.com/GPX/1/0/gpx.xsd"));
+
+    // The schema location of the document is kept.
+    gpx.schema_location = Some("http://www.topografix.com/GPX/1/0 custom.xsd".into());
+    let output = write(&gpx, &options);
+    assert!(output.contains("xsi:schemaLocation=\"http://www.topografix.com/GPX/1/0 custom.xsd\""));
+}
+
+#[test]
+fn gpx_writer_write_unused_namespaces() {
+    let mut gpx = Gpx {
+        version: GpxVersion::Gpx11,
+        namespaces: vec![
+            (
+                "gpxx".into(),
+                "http://www.garmin.com/xmlschemas/GpxExtensions/v3".into(),
+            ),
+            ("ex".into(), "urn:example".into()),
+        ],
+        ..Default::default()
+    };
+    gpx.waypoints
+        .push(Waypoint::new(Point::new(-121.97, 37.24)));
+
+    // A plain document declares no extension namespace.
+    let output = String::from_utf8(write_to_bytes(&gpx).unwrap()).unwrap();
+    assert!(!output.contains("xmlns:gpxx"));
+    assert!(!output.contains("xmlns:ex"));
+
+    // Only the namespaces that are used are declared.
+    let xml = r#"<gpx version="1.1" xmlns:ex="urn:example" xmlns:gpxx="http://www.garmin.com/xmlschemas/GpxExtensions/v3">
+    <wpt lat="37.24" lon="-121.97"><extensions><ex:note>hi</ex:note></extensions></wpt>
+</gpx>"#;
+    let gpx = read(xml.as_bytes()).unwrap();
+    let output = String::from_utf8(write_to_bytes(&gpx).unwrap()).unwrap();
+    let root = &output[output.find("<gpx").unwrap()..];
+    let root = &root[..root.find('>').unwrap()];
+    assert!(root.contains("xmlns:ex=\"urn:example\""));
+    assert!(!root.contains("xmlns:gpxx"));
+}
+
+#[test]
+fn gpx_writer_write_embedded_track_stats() {
+    let gpx = read_test_gpx_file("tests/fixtures/garmin-activity.gpx");
+    let options = WriteOptions {
+        embed_stats: true,
+        ..Default::default()
+    };
+    let stats_namespace = "http://www.garmin.com/xmlschemas/TrackStatsExtension/v1";
+    let write_and_read = |gpx: &Gpx| {
+        let mut buffer = Vec::new();
+        write_with_options(gpx, &mut buffer, &options).unwrap();
+        read(buffer.as_slice()).unwrap()
+    };
+
+    let written_gpx = write_and_read(&gpx);
+    let extensions = written_gpx.tracks[0].extensions.as_ref().unwrap();
+    let stats: Vec<_> = extensions.elements_in(stats_namespace).collect();
+    assert_eq!(stats.len(), 1);
+    let value = |name: &str| -> f64 {
+        let element = stats[0]
+            .children
+            .iter()
+            .find_map(|node| match node {
+                ExtensionNode::Element(element) if element.name == name => Some(element),
+                _ => None,
+            })
+            .unwrap();
+        match &element.children[..] {
+            [ExtensionNode::Text(text)] => text.parse().unwrap(),
+            children => panic!("unexpected content {:?}", children),
+        }
+    };
+    assert!(value("Distance") > 0.0);
+    let points = &gpx.tracks[0].segments[0].points;
+    let elapsed = points.last().unwrap().time.unwrap() - points[0].time.unwrap();
+    assert_eq!(value("TotalElapsedTime"), elapsed.num_seconds() as f64);
+
+    // Writing again replaces the stats instead of adding more.
+    let rewritten_gpx = write_and_read(&written_gpx);
+    let extensions = rewritten_gpx.tracks[0].extensions.as_ref().unwrap();
+    assert_eq!(extensions.elements_in(stats_namespace).count(), 1);
+
+    // Nothing is added by default.
+    let plain = String::from_utf8(write_to_bytes(&gpx).unwrap()).unwrap();
+    assert!(!plain.contains("TrackStatsExtension"));
+}
+
+#[test]
+fn gpx_writer_write_embedded_progress() {
+    let mut gpx = read_test_gpx_file("tests/fixtures/garmin-activity.gpx");
+    // The progress runs on over the segments of a track.
+    let second = gpx.tracks[0].segments[0].slice(10, 20);
+    gpx.tracks[0].segments.push(second);
+    let options = WriteOptions {
+        embed_progress: true,
+        ..Default::default()
+    };
+    let write_and_read = |gpx: &Gpx| {
+        let mut buffer = Vec::new();
+        write_with_options(gpx, &mut buffer, &options).unwrap();
+        read(buffer.as_slice()).unwrap()
+    };
+    let progress = |gpx: &Gpx| -> Vec<f64> {
+        gpx.tracks[0]
+            .segments
+            .iter()
+            .flat_map(|seg| &seg.points)
+            .map(|wpt| {
+                let extensions = wpt.extensions.as_ref().unwrap();
+                let elements: Vec<_> = extensions
+                    .elements_in(PLAYBACK_EXTENSION_NAMESPACE)
+                    .collect();
+                assert_eq!(elements.len(), 1);
+                assert_eq!(elements[0].name, "progress");
+                match &elements[0].children[..] {
+                    [ExtensionNode::Text(text)] => text.parse().unwrap(),
+                    children => panic!("unexpected content {:?}", children),
+                }
+            })
+            .collect()
+    };
+
+    let written_gpx = write_and_read(&gpx);
+    let written = progress(&written_gpx);
+    assert_eq!(
+        written.len(),
+        gpx.tracks[0]
+            .segments
+            .iter()
+            .map(|seg| seg.points.len())
+            .sum::<usize>()
+    );
+    assert_eq!(written[0], 0.0);
+    assert_eq!(*written.last().unwrap(), 1.0);
+    assert!(written.windows(2).all(|pair| pair[0] <= pair[1]));
+    // Other extensions are kept.
+    assert_eq!(
+        written_gpx.tracks[0].segments[0].points[0].track_point_extension,
+        gpx.tracks[0].segments[0].points[0].track_point_extension
+    );
+
+    // Writing again replaces the progress instead of adding more.
+    let rewritten_gpx = write_and_read(&written_gpx);
+    assert_eq!(progress(&rewritten_gpx), written);
+
+    // Nothing is added by default.
+    let plain = String::from_utf8(write_to_bytes(&gpx).unwrap()).unwrap();
+    assert!(!plain.contains(PLAYBACK_EXTENSION_NAMESPACE));
+}
+
+#[test]
+fn gpx_writer_write_canonical() {
+    let options = WriteOptions {
+        canonical: true,
+        ..Default::default()
+    };
+    let write_bytes = |gpx: &Gpx, options: &WriteOptions| {
+        let mut buffer = Vec::new();
+        write_with_options(gpx, &mut buffer, options).unwrap();
+        buffer
+    };
+
+    let gpx = read_test_gpx_file("tests/fixtures/garmin-activity.gpx");
+    assert_eq!(write_bytes(&gpx, &options), write_bytes(&gpx, &options));
+
+    // The same data, with other formatting and attribute order, and
+    // coordinates that only differ by rounding errors.
+    let xml = |name: &str, attributes: &str, lat: &str| {
+        format!(
+            r#"<gpx version="1.1" xmlns:b="urn:b" xmlns:a="urn:a">
+    <wpt lat="{}" lon="8.0">
+        <name>{}</name>
+        <extensions>
+            <a:sensor {}/>
+        </extensions>
+    </wpt>
+</gpx>"#,
+            lat, name, attributes
+        )
+    };
+    let first = read(xml("Summit", r#"a:id="1" a:kind="hr""#, "47.0").as_bytes()).unwrap();
+    let second = read(
+        xml(
+            "\n  Summit  \n",
+            r#"a:kind="hr"   a:id="1""#,
+            "47.00000000001",
+        )
+        .as_bytes(),
+    )
+    .unwrap();
+
+    assert_ne!(
+        write_bytes(&first, &Default::default()),
+        write_bytes(&second, &Default::default())
+    );
+    let canonical = write_bytes(&first, &options);
+    assert_eq!(canonical, write_bytes(&second, &options));
+
+    let written_gpx = read(canonical.as_slice()).unwrap();
+    assert_eq!(written_gpx.waypoints[0].name.as_deref(), Some("Summit"));
+    assert_eq!(written_gpx.waypoints[0].point(), Point::new(8.0, 47.0));
+}
+
+#[test]
+fn gpx_writer_write_canonical_metadata() {
+    let data = r#"<gpx version="1.1" creator="test" xmlns="http://www.topografix.com/GPX/1/1">
+    <metadata>
+        <bounds minlat="37.240000012345" minlon="-121.97" maxlat="37.26" maxlon="-121.96"/>
+        <keywords>hike</keywords>
+        <time>2020-06-01T08:00:00Z</time>
+        <link href="https://example.com"/>
+        <name>Loop</name>
+    </metadata>
+</gpx>"#;
+    let gpx = read(data.as_bytes()).unwrap();
+    let options = WriteOptions {
+        canonical: true,
+        ..Default::default()
+    };
+    let mut buffer: Vec<u8> = Vec::new();
+    write_with_options(&gpx, &mut buffer, &options).unwrap();
+    let output = String::from_utf8(buffer).unwrap();
+
+    // In the order of the schema.
+    let positions: Vec<usize> = ["<name>", "<link", "<time>", "<keywords>", "<bounds"]
+        .iter()
+        .map(|tag| output.find(tag).unwrap())
+        .collect();
+    assert!(positions.windows(2).all(|w| w[0] < w[1]));
+    assert!(output.contains(r#"minlat="37.24" "#));
+}
+
+#[test]
+fn gpx_writer_write_max_points_per_segment() {
+    let mut segment = TrackSegment::new();
+    for i in 0..300 {
+        segment
+            .points
+            .push(Waypoint::new(Point::new(8.0, 47.0 + i as f64 * 0.0001)));
     }
+    let mut track = Track::new();
+    track.segments.push(segment.clone());
+    let gpx = Gpx {
+        version: GpxVersion::Gpx11,
+        tracks: vec![track],
+        ..Default::default()
+    };
+    let options = WriteOptions {
+        max_points_per_segment: Some(100),
+        ..Default::default()
+    };
+
+    let mut buffer = Vec::new();
+    write_with_options(&gpx, &mut buffer, &options).unwrap();
+    let written_gpx = read(buffer.as_slice()).unwrap();
+
+    let segments = &written_gpx.tracks[0].segments;
+    assert_eq!(segments.len(), 3);
+    assert!(segments.iter().all(|seg| seg.points.len() == 100));
+    let points: Vec<Waypoint> = segments
+        .iter()
+        .flat_map(|seg| seg.points.iter().cloned())
+        .collect();
+    assert_eq!(points, segment.points);
+
+    // Shorter segments are left alone.
+    let options = WriteOptions {
+        max_points_per_segment: Some(300),
+        ..Default::default()
+    };
+    let mut buffer = Vec::new();
+    write_with_options(&gpx, &mut buffer, &options).unwrap();
+    assert_eq!(read(buffer.as_slice()).unwrap().tracks[0].segments.len(), 1);
 }